@@ -0,0 +1,53 @@
+#[macro_use]
+extern crate criterion;
+extern crate crdts;
+
+use criterion::Criterion;
+use crdts::{Orswot, CvRDT, CmRDT};
+
+const SIZES: [u64; 2] = [10_000, 100_000];
+
+fn build(actor: u8, members: impl Iterator<Item=u64>) -> Orswot<u64, u8> {
+    let mut set = Orswot::new();
+    for member in members {
+        let op = set.add(member, set.iter().derive_add_ctx(actor));
+        set.apply(&op);
+    }
+    set
+}
+
+fn merge_identical(c: &mut Criterion) {
+    c.bench_function_over_inputs("orswot merge identical", |b, &&size| {
+        let set = build(1, 0..size);
+        b.iter_with_setup(|| set.clone(), |mut a| { a.merge(&set); a })
+    }, &SIZES);
+}
+
+fn merge_small_delta(c: &mut Criterion) {
+    c.bench_function_over_inputs("orswot merge small delta", |b, &&size| {
+        let set = build(1, 0..size);
+        let mut other = set.clone();
+        for member in 0..10 {
+            let op = other.add(size + member, other.iter().derive_add_ctx(2));
+            other.apply(&op);
+            let op = other.remove(member, other.contains(&member).derive_rm_ctx());
+            other.apply(&op);
+        }
+        b.iter_with_setup(|| set.clone(), |mut a| { a.merge(&other); a })
+    }, &SIZES);
+}
+
+fn merge_disjoint(c: &mut Criterion) {
+    c.bench_function_over_inputs("orswot merge disjoint", |b, &&size| {
+        let set = build(1, 0..size / 2);
+        let other = build(2, size / 2..size);
+        b.iter_with_setup(|| set.clone(), |mut a| { a.merge(&other); a })
+    }, &SIZES);
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = merge_identical, merge_small_delta, merge_disjoint
+}
+criterion_main!(benches);
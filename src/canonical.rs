@@ -0,0 +1,91 @@
+//! Canonical encoding of CRDT state.
+//!
+//! Converged replicas hold the same state, but CRDT's built on `HashMap`'s
+//! and `HashSet`'s will happily serialize that state in a different order
+//! on every replica. The canonical encoding sorts these collections by the
+//! encoding of their elements, so equal states encode to equal bytes.
+//!
+//! The canonical encoding is a valid binary encoding, it can be decoded
+//! with `from_binary`.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Orswot, CmRDT, CvRDT, Canonical, from_binary};
+//!
+//! let mut a: Orswot<u8, u8> = Orswot::new();
+//! let mut b: Orswot<u8, u8> = Orswot::new();
+//! for member in 0..16 {
+//!     let op = a.add(member, a.value().derive_add_ctx(1));
+//!     a.apply(&op);
+//! }
+//! for member in (0..16).rev() {
+//!     let op = b.add(member, b.value().derive_add_ctx(2));
+//!     b.apply(&op);
+//! }
+//!
+//! a.merge(&b);
+//! b.merge(&a);
+//! assert_eq!(a.digest(), b.digest());
+//! assert_eq!(a.to_canonical_binary(), b.to_canonical_binary());
+//!
+//! let decoded: Orswot<u8, u8> = from_binary(a.to_canonical_binary()).unwrap();
+//! assert_eq!(decoded, a);
+//! ```
+
+use bincode::{Infinite, serialize_into};
+use serde::Serialize;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Canonical is implemented by CRDT's that can produce a canonical
+/// encoding of their state.
+///
+/// Opaque values stored in a CRDT (set members, register values, keys)
+/// are encoded as is, so they must serialize deterministically themselves.
+pub trait Canonical {
+    /// Append the canonical encoding of self to buf.
+    fn encode_canonical(&self, buf: &mut Vec<u8>);
+
+    /// Returns the canonical encoding of self.
+    fn to_canonical_binary(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_canonical(&mut buf);
+        buf
+    }
+
+    /// Returns a stable 64 bit digest of the state, replicas with equal
+    /// state have equal digests. The digest is the FNV-1a hash of the
+    /// canonical encoding, so it's stable across platforms and releases
+    /// that don't change the encoding.
+    fn digest(&self) -> u64 {
+        fnv1a(&self.to_canonical_binary())
+    }
+}
+
+/// Append the binary encoding of val to buf.
+pub fn encode<T: Serialize + ?Sized>(val: &T, buf: &mut Vec<u8>) {
+    serialize_into(buf, val, Infinite).unwrap()
+}
+
+/// Append an encoded collection to buf, sorting its encoded elements.
+/// The result has the same layout as the binary encoding of a sequence
+/// or map with the elements in sorted order.
+pub fn encode_sorted(mut elems: Vec<Vec<u8>>, buf: &mut Vec<u8>) {
+    elems.sort();
+    encode(&(elems.len() as u64), buf);
+    for elem in elems {
+        buf.extend(elem);
+    }
+}
+
+/// The 64 bit FNV-1a hash of some bytes.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
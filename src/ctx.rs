@@ -1,15 +1,18 @@
-use serde::{Serialize, de::DeserializeOwned};
+use std::borrow::Cow;
+use std::io;
+
 use vclock::{Actor, VClock, Dot};
 use traits::CmRDT;
+use dotalloc::DotAllocator;
 
 /// ReadCtx's are used to extract data from CRDT's while maintaining some causal history.
 /// You should store ReadCtx's close to where mutation is exposed to the user.
 ///
 /// e.g. Ship ReadCtx to the clients, then derive an Add/RmCtx and ship that back to
 /// where the CRDT is stored to perform the mutation operation.
-#[serde(bound(deserialize = ""))]
+#[serde(bound(deserialize = "V: ::serde::de::DeserializeOwned"))]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct ReadCtx<V: Serialize + DeserializeOwned, A: Actor> {
+pub struct ReadCtx<V, A: Actor> {
     /// clock used to derive an AddCtx
     pub add_clock: VClock<A>,
 
@@ -20,6 +23,21 @@ pub struct ReadCtx<V: Serialize + DeserializeOwned, A: Actor> {
     pub val: V
 }
 
+/// ReadCtxRef is the borrowing counterpart of ReadCtx, the clocks refer back into
+/// the CRDT instead of being cloned out of it. Use it on hot read paths where the
+/// ReadCtx is rarely used to derive a mutation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadCtxRef<'a, V, A: Actor + 'a> {
+    /// clock used to derive an AddCtx
+    pub add_clock: Cow<'a, VClock<A>>,
+
+    /// clock used to derive an RmCtx
+    pub rm_clock: Cow<'a, VClock<A>>,
+
+    /// the data read from the CRDT
+    pub val: V
+}
+
 /// AddCtx is used for mutations add new information to a CRDT
 #[serde(bound(deserialize = ""))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,7 +57,7 @@ pub struct RmCtx<A: Actor> {
     pub clock: VClock<A>
 }
 
-impl<V: Serialize + DeserializeOwned, A: Actor> ReadCtx<V, A> {
+impl<V, A: Actor> ReadCtx<V, A> {
 
     /// Derives an AddCtx for a given actor from a ReadCtx
     pub fn derive_add_ctx(&self, actor: A) -> AddCtx<A> {
@@ -52,6 +70,18 @@ impl<V: Serialize + DeserializeOwned, A: Actor> ReadCtx<V, A> {
         }
     }
 
+    /// Derives an AddCtx from a ReadCtx, the dot is handed out by a
+    /// `DotAllocator` so it's never reused, even across crashes.
+    pub fn derive_add_ctx_with(&self, alloc: &mut DotAllocator<A>) -> io::Result<AddCtx<A>> {
+        let mut clock = self.add_clock.clone();
+        let dot = alloc.next_dot(&clock)?;
+        clock.apply(&dot);
+        Ok(AddCtx {
+            clock,
+            dot
+        })
+    }
+
     /// Derives a RmCtx from a ReadCtx
     pub fn derive_rm_ctx(&self) -> RmCtx<A> {
         RmCtx {
@@ -59,3 +89,45 @@ impl<V: Serialize + DeserializeOwned, A: Actor> ReadCtx<V, A> {
         }
     }
 }
+
+impl<'a, V, A: Actor> ReadCtxRef<'a, V, A> {
+
+    /// Derives an AddCtx for a given actor from a ReadCtxRef
+    pub fn derive_add_ctx(&self, actor: A) -> AddCtx<A> {
+        let mut clock = self.add_clock.clone().into_owned();
+        let dot = clock.inc(actor);
+        clock.apply(&dot);
+        AddCtx {
+            clock,
+            dot
+        }
+    }
+
+    /// Derives an AddCtx from a ReadCtxRef, the dot is handed out by a
+    /// `DotAllocator` so it's never reused, even across crashes.
+    pub fn derive_add_ctx_with(&self, alloc: &mut DotAllocator<A>) -> io::Result<AddCtx<A>> {
+        let mut clock = self.add_clock.clone().into_owned();
+        let dot = alloc.next_dot(&clock)?;
+        clock.apply(&dot);
+        Ok(AddCtx {
+            clock,
+            dot
+        })
+    }
+
+    /// Derives a RmCtx from a ReadCtxRef
+    pub fn derive_rm_ctx(&self) -> RmCtx<A> {
+        RmCtx {
+            clock: self.rm_clock.clone().into_owned()
+        }
+    }
+
+    /// Converts this into an owned ReadCtx, cloning the clocks if they are borrowed
+    pub fn into_owned(self) -> ReadCtx<V, A> {
+        ReadCtx {
+            add_clock: self.add_clock.into_owned(),
+            rm_clock: self.rm_clock.into_owned(),
+            val: self.val
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use traits::CvRDT;
+use vclock::{Dot, VClock, Actor};
+
+/// Merge the dots of an element held by two replicas whose clocks are
+/// `seen` and `other_seen`, an element one side doesn't hold has no dots
+/// there.
+///
+/// SUBTLE: an element present on both sides may still be dropped. The
+/// dots both sides hold survive, a dot held by one side only survives if
+/// the other side hasn't seen it, if it has, it removed it.
+pub fn merge_dots<A: Actor>(
+    dots: &mut VClock<A>,
+    other_dots: &VClock<A>,
+    seen: &VClock<A>,
+    other_seen: &VClock<A>
+) {
+    if dots == other_dots {
+        // both sides agree on this element, nothing to do
+        return;
+    }
+    let mut common = dots.intersection(other_dots);
+
+    let mut ours = dots.clone();
+    ours.subtract(&common);
+    ours.subtract(other_seen);
+
+    let mut theirs = other_dots.clone();
+    theirs.subtract(&common);
+    theirs.subtract(seen);
+
+    common.merge(&ours);
+    common.merge(&theirs);
+    *dots = common;
+}
+
+/// The removes a CRDT defers until it has seen every dot of their clock,
+/// `R` describes what a clock removes.
+///
+/// The removes are indexed by the dots of their clocks, see
+/// `DeferredIndex`, only the removes themselves are serialized.
+#[serde(transparent)]
+#[serde(bound(serialize = "R: Serialize", deserialize = "R: DeserializeOwned"))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deferred<A: Actor, R> {
+    removes: HashMap<VClock<A>, R>,
+    #[serde(skip)]
+    index: DeferredIndex<A>
+}
+
+impl<A: Actor, R> Default for Deferred<A, R> {
+    fn default() -> Self {
+        Deferred::new()
+    }
+}
+
+impl<A: Actor, R> Deferred<A, R> {
+    /// Returns an empty set of deferred removes.
+    pub fn new() -> Self {
+        Deferred { removes: HashMap::new(), index: DeferredIndex::default() }
+    }
+
+    /// The number of deferred clocks.
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.removes.len()
+    }
+
+    /// Iterate over the deferred clocks and their removes.
+    pub fn iter(&self) -> impl Iterator<Item=(&VClock<A>, &R)> {
+        self.removes.iter()
+    }
+
+    /// The removes of a deferred clock.
+    pub fn get(&self, clock: &VClock<A>) -> Option<&R> {
+        self.removes.get(clock)
+    }
+
+    /// The removes of a clock, deferring it if it isn't already.
+    pub fn entry(&mut self, clock: &VClock<A>) -> &mut R
+        where R: Default
+    {
+        if self.index.is_built() && !self.removes.contains_key(clock) {
+            self.index.insert(clock);
+        }
+        self.removes.entry(clock.clone()).or_default()
+    }
+
+    /// The deferred clocks that cover the given dot.
+    pub fn covering(&mut self, dot: &Dot<A>) -> Vec<VClock<A>> {
+        self.build_index();
+        self.index.covering(dot)
+    }
+
+    /// Take the removes that were only waiting on the given dot, now that
+    /// the owner's clock has seen it.
+    pub fn take_unblocked(&mut self, dot: &Dot<A>, seen: &VClock<A>) -> Vec<(VClock<A>, R)> {
+        self.build_index();
+        let mut unblocked = Vec::new();
+        for clock in self.index.unblocked(dot) {
+            if clock <= *seen {
+                if let Some(removes) = self.removes.remove(&clock) {
+                    unblocked.push((clock, removes));
+                }
+            }
+        }
+        unblocked
+    }
+
+    /// Take all the removes, the owner re-applies them after its entries
+    /// were changed wholesale.
+    pub fn take_all(&mut self) -> HashMap<VClock<A>, R> {
+        self.index.reset();
+        mem::take(&mut self.removes)
+    }
+
+    /// Forget the dots of the given clock, removes whose clocks end up
+    /// equal are combined.
+    pub fn truncate<F: FnMut(&mut R, R)>(&mut self, clock: &VClock<A>, mut combine: F) {
+        let mut removes: HashMap<VClock<A>, R> = HashMap::new();
+        for (mut rm_clock, rm) in mem::take(&mut self.removes) {
+            rm_clock.subtract(clock);
+            if rm_clock.is_empty() {
+                continue;
+            }
+            if let Some(existing) = removes.get_mut(&rm_clock) {
+                combine(existing, rm);
+                continue;
+            }
+            removes.insert(rm_clock, rm);
+        }
+        self.removes = removes;
+        self.index.invalidate();
+    }
+
+    /// Mark the index as stale, used once the owner's clock forgot dots.
+    pub fn invalidate(&mut self) {
+        self.index.invalidate();
+    }
+
+    fn build_index(&mut self) {
+        if !self.index.is_built() {
+            self.index.reset();
+            for clock in self.removes.keys() {
+                self.index.insert(clock);
+            }
+        }
+    }
+}
+
+/// An index over the clocks of a CRDT's deferred removes.
+///
+/// Deferred removes are kept around until the CRDT's clock has seen
+/// every dot of the remove's clock, so they only need to be revisited
+/// when a dot they are waiting on is witnessed, or when a dot they cover
+/// is added. The index finds those removes without walking all of them.
+///
+/// The index is derived from the deferred removes, so it's not serialized
+/// and doesn't take part in equality. After deserializing (or when the
+/// owner rewrites its deferred removes wholesale) it's marked as not
+/// built, the owner rebuilds it before its next use.
+#[derive(Debug, Clone)]
+pub struct DeferredIndex<A: Actor, K = ()> {
+    built: bool,
+    // actor -> counter -> deferred clocks that have seen up to this
+    // counter from the actor. May contain clocks that are no longer
+    // deferred, those are filtered out by the owner.
+    dots: HashMap<A, BTreeMap<u64, HashSet<VClock<A>>>>,
+    // key -> deferred clocks that remove this key
+    keys: BTreeMap<K, HashSet<VClock<A>>>
+}
+
+impl<A: Actor, K> Default for DeferredIndex<A, K> {
+    fn default() -> Self {
+        DeferredIndex {
+            built: false,
+            dots: HashMap::new(),
+            keys: BTreeMap::new()
+        }
+    }
+}
+
+impl<A: Actor, K> PartialEq for DeferredIndex<A, K> {
+    fn eq(&self, _other: &Self) -> bool {
+        // the index is derived state
+        true
+    }
+}
+
+impl<A: Actor, K> Eq for DeferredIndex<A, K> {}
+
+impl<A: Actor, K: Ord + Clone> DeferredIndex<A, K> {
+    /// Returns true if the index reflects the owners deferred removes.
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// Empty the index and mark it as built, the owner is expected to
+    /// insert all of its deferred removes.
+    pub fn reset(&mut self) {
+        self.built = true;
+        self.dots.clear();
+        self.keys.clear();
+    }
+
+    /// Mark the index as stale, it will be rebuilt before its next use.
+    pub fn invalidate(&mut self) {
+        self.built = false;
+        self.dots.clear();
+        self.keys.clear();
+    }
+
+    /// Index a deferred clock by each of its dots.
+    pub fn insert(&mut self, clock: &VClock<A>) {
+        for (actor, counter) in clock.iter() {
+            self.dots.entry(actor.clone())
+                .or_default()
+                .entry(*counter)
+                .or_default()
+                .insert(clock.clone());
+        }
+    }
+
+    /// Index a deferred clock by a key it removes.
+    pub fn insert_key(&mut self, key: K, clock: &VClock<A>) {
+        self.keys.entry(key)
+            .or_default()
+            .insert(clock.clone());
+    }
+
+    /// Forget that the deferred clock removes key.
+    pub fn remove_key(&mut self, key: &K, clock: &VClock<A>) {
+        let now_empty = if let Some(clocks) = self.keys.get_mut(key) {
+            clocks.remove(clock);
+            clocks.is_empty()
+        } else {
+            false
+        };
+        if now_empty {
+            self.keys.remove(key);
+        }
+    }
+
+    /// The deferred clocks that remove key.
+    pub fn keyed(&self, key: &K) -> Vec<VClock<A>> {
+        self.keys.get(key)
+            .map(|clocks| clocks.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The deferred clocks that cover the given dot.
+    pub fn covering(&self, dot: &Dot<A>) -> Vec<VClock<A>> {
+        self.dots.get(&dot.actor)
+            .map(|counters| {
+                counters.range(dot.counter..)
+                    .flat_map(|(_, clocks)| clocks.iter().cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Once a dot has been witnessed, no deferred clock is waiting on the
+    /// dots of that actor up to the dots counter. These clocks are dropped
+    /// from the index and returned so the owner can check whether they are
+    /// still waiting on some other actor.
+    pub fn unblocked(&mut self, dot: &Dot<A>) -> Vec<VClock<A>> {
+        let mut unblocked = Vec::new();
+        let now_empty = if let Some(counters) = self.dots.get_mut(&dot.actor) {
+            let waiting = counters.split_off(&(dot.counter + 1));
+            let seen = ::std::mem::replace(counters, waiting);
+            for (_, clocks) in seen {
+                unblocked.extend(clocks);
+            }
+            counters.is_empty()
+        } else {
+            false
+        };
+        if now_empty {
+            self.dots.remove(&dot.actor);
+        }
+        unblocked
+    }
+}
@@ -0,0 +1,806 @@
+//! A JSON document CRDT.
+//!
+//! A `Doc` is a recursive value that replicates JSON-like documents.
+//! Every level of a document can hold any of the following kinds:
+//!
+//! * a map of strings to nested docs, built on `Map`
+//! * a list of nested docs, built on `List`
+//! * text, a `List` of characters
+//! * a counter, an `Orswot` of the steps taken by each edit
+//! * a register of a primitive JSON value, built on `MVReg`
+//!
+//! The kind of a doc is itself held in an `MVReg`, every edit that
+//! changes the kind clears the content of the other kinds. Replicas
+//! that concurrently give a doc different kinds keep all of them, the
+//! doc reads as the first of them in the order above, and `conflicts`
+//! exposes every kind along with its content. Concurrent writes of a
+//! primitive value are exposed the same way, the doc reads as the least
+//! of the written values.
+//!
+//! Removing a map key, along with the keys a map loses when it's
+//! replaced, follows the reset-remove semantics of `Map`: the edits seen
+//! by the remove are gone and concurrent edits keep the key. Removing a
+//! list element follows `List`, the remove wins over concurrent edits.
+//!
+//! Counters aren't a `PNCounter`: a `PNCounter` only keeps the total of
+//! each actor, so it can't tell the steps a remove or a change of kind
+//! has seen from the steps taken concurrently, and clearing it would
+//! take back both. Each step is instead an element of an `Orswot`,
+//! tagged with the dot of its edit, and a remove drops exactly the steps
+//! it has seen. The price is an element per step rather than a total per
+//! actor.
+//!
+//! Nested edits are built with `update`, which walks a path of map keys
+//! and list indices and wraps the edit of the doc at the end of the path
+//! into a single op for the root. Map keys that don't exist are created
+//! along the way, list indices must exist.
+//!
+//! JSON numbers, strings, booleans and null are written as registers,
+//! text and counters are only created by their own edits. An empty doc
+//! reads as null.
+//!
+//! Ops must be applied in the order they were produced, see `List`.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate crdts;
+//! extern crate serde_json;
+//!
+//! use crdts::{Doc, CvRDT, CmRDT};
+//! use crdts::doc::Seg;
+//!
+//! # fn main() {
+//! let json = serde_json::from_str(r#"{"title": "groceries", "items": ["milk"]}"#).unwrap();
+//! let mut a: Doc<u8> = Doc::from_json(&json, 1);
+//! let mut b = a.clone();
+//!
+//! // a adds an item and renames the list while b renames it too
+//! let op = a.update(&[Seg::from("items")], a.read().derive_add_ctx(1), |items, ctx| {
+//!     items.push(&"eggs".into(), ctx)
+//! }).unwrap();
+//! a.apply(&op);
+//! let op = a.update(&[Seg::from("title")], a.read().derive_add_ctx(1), |title, ctx| {
+//!     title.set(&"shopping".into(), ctx)
+//! }).unwrap();
+//! a.apply(&op);
+//! let op = b.update(&[Seg::from("title")], b.read().derive_add_ctx(2), |title, ctx| {
+//!     title.set(&"errands".into(), ctx)
+//! }).unwrap();
+//! b.apply(&op);
+//!
+//! a.merge(&b);
+//! assert_eq!(
+//!     a.to_json(),
+//!     serde_json::from_str::<serde_json::Value>(
+//!         r#"{"title": "errands", "items": ["milk", "eggs"]}"#
+//!     ).unwrap()
+//! );
+//!
+//! let conflicts = a.conflicts();
+//! assert_eq!(conflicts.len(), 1);
+//! assert_eq!(conflicts[0].path, vec![Seg::from("title")]);
+//! assert_eq!(conflicts[0].values, vec!["errands", "shopping"]);
+//! # }
+//! ```
+
+use std::cmp;
+use std::ops::Range;
+
+use serde_json::{self, Value};
+
+use traits::{Causal, CvRDT, CmRDT};
+use vclock::{Dot, VClock, Actor};
+use ctx::{ReadCtx, AddCtx, RmCtx};
+use map::{self, Map};
+use list::{self, List};
+use mvreg::{self, MVReg};
+use orswot::{self, Orswot};
+use canonical::Canonical;
+
+/// `Doc` is a replicated JSON-like document.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Doc<A: Actor> {
+    kind: MVReg<Kind, A>,
+    map: Map<String, Doc<A>, A>,
+    list: List<Doc<A>, A>,
+    text: List<MVReg<char, A>, A>,
+    // every step of the counter along with the dot of its edit
+    counter: Orswot<(Dot<A>, i64), A>,
+    reg: MVReg<Prim, A>,
+    // the edits removed from the doc, merges remove them from the
+    // content of the other doc too
+    removed: VClock<A>
+}
+
+/// The kinds of values a `Doc` can hold, a doc with many kinds reads
+/// as the first of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Kind {
+    /// A map of strings to docs
+    Map,
+    /// A list of docs
+    List,
+    /// Text
+    Text,
+    /// A counter
+    Counter,
+    /// A primitive JSON value
+    Reg
+}
+
+/// A primitive JSON value
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Prim {
+    /// null
+    Null,
+    /// true or false
+    Bool(bool),
+    /// A number, kept in its JSON representation
+    Num(String),
+    /// A string
+    Str(String)
+}
+
+/// A step of a path into a `Doc`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Seg {
+    /// A key of a map
+    Key(String),
+    /// An index of a list
+    Index(usize)
+}
+
+/// The concurrent values of a doc nested in a `Doc`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// The path of the doc
+    pub path: Vec<Seg>,
+    /// The concurrent values, ordered by kind or by value
+    pub values: Vec<Value>
+}
+
+/// Op's define an edit to a `Doc`
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Op<A: Actor> {
+    /// Set the kind of the doc
+    Kind(mvreg::Op<Kind, A>),
+    /// Edit the map
+    Map(Box<map::Op<String, Doc<A>, A>>),
+    /// Edit the list
+    List(Box<list::Op<Doc<A>, A>>),
+    /// Edit the text
+    Text(list::Op<MVReg<char, A>, A>),
+    /// Step the counter
+    Counter(orswot::Op<(Dot<A>, i64), A>),
+    /// Set the primitive value
+    Reg(mvreg::Op<Prim, A>),
+    /// Apply many ops in order
+    Batch(Vec<Op<A>>)
+}
+
+impl<'a> From<&'a str> for Seg {
+    fn from(key: &'a str) -> Self {
+        Seg::Key(key.to_string())
+    }
+}
+
+impl From<String> for Seg {
+    fn from(key: String) -> Self {
+        Seg::Key(key)
+    }
+}
+
+impl From<usize> for Seg {
+    fn from(index: usize) -> Self {
+        Seg::Index(index)
+    }
+}
+
+impl Prim {
+    /// The primitive of a JSON value, None for arrays and objects
+    pub fn from_json(val: &Value) -> Option<Prim> {
+        match val {
+            Value::Null => Some(Prim::Null),
+            Value::Bool(b) => Some(Prim::Bool(*b)),
+            Value::Number(n) => Some(Prim::Num(n.to_string())),
+            Value::String(s) => Some(Prim::Str(s.clone())),
+            Value::Array(_) | Value::Object(_) => None
+        }
+    }
+
+    /// The JSON value of the primitive
+    pub fn to_json(&self) -> Value {
+        match self {
+            Prim::Null => Value::Null,
+            Prim::Bool(b) => Value::Bool(*b),
+            Prim::Num(n) => serde_json::from_str(n).unwrap_or(Value::Null),
+            Prim::Str(s) => Value::String(s.clone())
+        }
+    }
+}
+
+impl<A: Actor> Op<A> {
+    /// Drops the edits in the clock from the op: puts no longer cover
+    /// them and inserts anchored on the elements they inserted go to the
+    /// front, as they do in a list the elements were truncated from.
+    fn truncate(&mut self, clock: &VClock<A>) {
+        match self {
+            Op::Kind(op) => truncate_put(op, clock),
+            Op::Map(op) => match **op {
+                map::Op::Up { ref mut op, .. } => op.truncate(clock),
+                map::Op::Batch { ref mut updates, .. } => {
+                    for (_, op) in updates.iter_mut() {
+                        op.truncate(clock);
+                    }
+                },
+                map::Op::Rm { .. } | map::Op::Nop => ()
+            },
+            Op::List(op) => truncate_list_op(op, clock, |op, clock| op.truncate(clock)),
+            Op::Text(op) => truncate_list_op(op, clock, truncate_put),
+            Op::Counter(_) => (),
+            Op::Reg(op) => truncate_put(op, clock),
+            Op::Batch(ops) => {
+                for op in ops.iter_mut() {
+                    op.truncate(clock);
+                }
+            }
+        }
+    }
+
+    /// Returns true if the op holds any of the edits in the clock, the
+    /// other ops are left as they are by `truncate`.
+    fn holds_any(&self, clock: &VClock<A>) -> bool {
+        match self {
+            Op::Kind(op) => put_holds_any(op, clock),
+            Op::Map(op) => match **op {
+                map::Op::Up { ref op, .. } => op.holds_any(clock),
+                map::Op::Batch { ref updates, .. } => {
+                    updates.iter().any(|(_, op)| op.holds_any(clock))
+                },
+                map::Op::Rm { .. } | map::Op::Nop => false
+            },
+            Op::List(op) => list_op_holds_any(op, clock, |op, clock| op.holds_any(clock)),
+            Op::Text(op) => list_op_holds_any(op, clock, put_holds_any),
+            Op::Counter(_) => false,
+            Op::Reg(op) => put_holds_any(op, clock),
+            Op::Batch(ops) => ops.iter().any(|op| op.holds_any(clock))
+        }
+    }
+}
+
+impl<A: Actor> Default for Doc<A> {
+    fn default() -> Self {
+        Doc::new()
+    }
+}
+
+impl<A: Actor> CmRDT for Doc<A> {
+    type Op = Op<A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        if self.removed.is_empty() || !op.holds_any(&self.removed) {
+            self.apply_op(op);
+        } else {
+            // an op built by a replica that hadn't seen a remove still
+            // holds the removed edits, it's applied without them
+            let mut op = op.clone();
+            op.truncate(&self.removed);
+            self.apply_op(&op);
+        }
+    }
+}
+
+impl<A: Actor> CvRDT for Doc<A> {
+    fn merge(&mut self, other: &Self) {
+        // the registers and lists forget what they had removed, the
+        // removes each doc has seen are applied to the other's content
+        if other.removed > self.removed || other.removed.concurrent(&self.removed) {
+            self.discard(&other.removed);
+        }
+        let mut truncated;
+        let other = if self.removed <= other.removed {
+            other
+        } else {
+            truncated = other.clone();
+            truncated.discard(&self.removed);
+            &truncated
+        };
+
+        self.kind.merge(&other.kind);
+        self.map.merge(&other.map);
+        self.list.merge(&other.list);
+        self.text.merge(&other.text);
+        self.counter.merge(&other.counter);
+        self.reg.merge(&other.reg);
+        self.removed.merge(&other.removed);
+    }
+}
+
+impl<A: Actor> Causal<A> for Doc<A> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        self.discard(clock);
+        self.removed.merge(clock);
+    }
+}
+
+impl<A: Actor> Doc<A> {
+    fn apply_op(&mut self, op: &Op<A>) {
+        match op {
+            Op::Kind(op) => self.kind.apply(op),
+            Op::Map(op) => self.map.apply(op),
+            Op::List(op) => self.list.apply(op),
+            Op::Text(op) => self.text.apply(op),
+            Op::Counter(op) => self.counter.apply(op),
+            Op::Reg(op) => self.reg.apply(op),
+            Op::Batch(ops) => {
+                for op in ops.iter() {
+                    self.apply_op(op);
+                }
+            }
+        }
+    }
+
+    /// Removes the content written by the edits in the clock
+    fn discard(&mut self, clock: &VClock<A>) {
+        self.kind.truncate(clock);
+
+        // removing the keys and steps instead of truncating keeps the
+        // removed edits in the clocks, so merges drop them from other
+        // replicas too
+        let seen = witnessed(clock, &self.map.len().rm_clock);
+        let keys: Vec<String> = self.map.keys().val.cloned().collect();
+        for key in keys {
+            self.map.apply(&map::Op::Rm { clock: seen.clone(), key });
+        }
+        let seen = witnessed(clock, &self.counter.value().rm_clock);
+        let steps: Vec<(Dot<A>, i64)> = self.counter.iter().val.cloned().collect();
+        for step in steps {
+            self.counter.apply(&self.counter.remove(step, RmCtx { clock: seen.clone() }));
+        }
+
+        self.list.truncate(clock);
+        self.text.truncate(clock);
+        self.reg.truncate(clock);
+    }
+}
+
+impl<A: Actor> Canonical for Doc<A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.kind.encode_canonical(buf);
+        self.map.encode_canonical(buf);
+        self.list.encode_canonical(buf);
+        self.text.encode_canonical(buf);
+        self.counter.encode_canonical(buf);
+        self.reg.encode_canonical(buf);
+        self.removed.encode_canonical(buf);
+    }
+}
+
+impl<A: Actor> Doc<A> {
+    /// Constructs an empty doc, it reads as null
+    pub fn new() -> Self {
+        Doc {
+            kind: MVReg::new(),
+            map: Map::new(),
+            list: List::new(),
+            text: List::new(),
+            counter: Orswot::new(),
+            reg: MVReg::new(),
+            removed: VClock::new()
+        }
+    }
+
+    /// Constructs a doc holding a JSON value, written by actor
+    pub fn from_json(val: &Value, actor: A) -> Self {
+        let mut doc = Doc::new();
+        let op = doc.set(val, doc.read().derive_add_ctx(actor));
+        doc.apply(&op);
+        doc
+    }
+
+    /// Read the doc as a JSON value
+    pub fn read(&self) -> ReadCtx<Value, A> {
+        let clock = self.clock();
+        ReadCtx {
+            add_clock: clock.clone(),
+            rm_clock: clock,
+            val: self.to_json()
+        }
+    }
+
+    /// The JSON value of the doc
+    pub fn to_json(&self) -> Value {
+        match self.kind() {
+            Some(kind) => self.render(kind),
+            None => Value::Null
+        }
+    }
+
+    /// The kind of the doc, None if the doc is empty
+    pub fn kind(&self) -> Option<Kind> {
+        match self.kind.read().val.into_iter().min() {
+            Some(kind) => Some(kind),
+            // the kind of a map is removed along with the edits seen by a
+            // remove, the keys updated concurrently survive it
+            None if self.map.len().val > 0 => Some(Kind::Map),
+            None => None
+        }
+    }
+
+    /// The doc at the end of a path, None if there's no such doc
+    pub fn get(&self, path: &[Seg]) -> Option<&Doc<A>> {
+        match path.split_first() {
+            Some((seg, rest)) => self.child(seg)?.get(rest),
+            None => Some(self)
+        }
+    }
+
+    /// Returns the concurrent values of the docs nested in this doc,
+    /// in document order.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        self.collect_conflicts(&mut Vec::new(), &mut conflicts);
+        conflicts
+    }
+
+    /// Edit the doc at the end of a path, the updater is given the doc
+    /// and the ctx to build the edit with. Missing map keys are created
+    /// and the empty docs the path goes through become maps, None if the
+    /// path goes through an index that doesn't exist or through a doc of
+    /// another kind.
+    pub fn update<F>(&self, path: &[Seg], ctx: AddCtx<A>, f: F) -> Option<Op<A>>
+        where F: FnOnce(&Doc<A>, AddCtx<A>) -> Op<A>
+    {
+        let (seg, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return Some(f(self, ctx))
+        };
+        match seg {
+            Seg::Key(key) => {
+                if self.kind().map(|kind| kind != Kind::Map).unwrap_or(false) {
+                    return None;
+                }
+                let op = match self.map.get_ref(key).val {
+                    Some(doc) => doc.update(rest, ctx.clone(), f)?,
+                    None => Doc::new().update(rest, ctx.clone(), f)?
+                };
+                let mut ops = if self.kind.read().val.is_empty() {
+                    self.convert(Kind::Map, &ctx)
+                } else {
+                    Vec::new()
+                };
+                ops.push(Op::Map(Box::new(self.map.update(key.clone(), ctx, |_, _| op))));
+                Some(Op::Batch(ops))
+            },
+            Seg::Index(index) => {
+                let op = self.child(seg)?.update(rest, ctx.clone(), f)?;
+                self.list.update(*index, ctx, |_, _| op)
+                    .map(|op| Op::List(Box::new(op)))
+            }
+        }
+    }
+
+    /// Replace the doc with a JSON value. Maps and lists are replaced
+    /// entry by entry, the other values are written to the register.
+    pub fn set(&self, val: &Value, ctx: AddCtx<A>) -> Op<A> {
+        match val {
+            Value::Object(entries) => {
+                let mut ops = self.convert(Kind::Map, &ctx);
+                let mut batch = self.map.batch(ctx.clone());
+                for key in self.map.keys().val {
+                    if !entries.contains_key(key) {
+                        batch = batch.rm(key.clone(), self.rm_ctx());
+                    }
+                }
+                for (key, val) in entries.iter() {
+                    batch = batch.update(key.clone(), |doc, ctx| doc.set(val, ctx));
+                }
+                let batch = batch.build().expect("object keys are unique");
+                ops.push(Op::Map(Box::new(batch)));
+                Op::Batch(ops)
+            },
+            Value::Array(vals) => {
+                let mut ops = self.convert(Kind::List, &ctx);
+                let len = self.list.len().val;
+                if len > 0 {
+                    ops.push(Op::List(Box::new(self.list.remove_range(0..len))));
+                }
+                let inits = vals.iter()
+                    .map(|val| Doc::new().set(val, ctx.clone()))
+                    .collect();
+                ops.push(Op::List(Box::new(self.list.insert_all(0, ctx, inits))));
+                Op::Batch(ops)
+            },
+            _ => {
+                let mut ops = self.convert(Kind::Reg, &ctx);
+                let prim = Prim::from_json(val).unwrap_or(Prim::Null);
+                ops.push(Op::Reg(self.reg.set(prim, reg_ctx(&self.reg, &ctx))));
+                Op::Batch(ops)
+            }
+        }
+    }
+
+    /// Put a JSON value under key, the doc becomes a map if it wasn't.
+    pub fn put(&self, key: impl Into<String>, val: &Value, ctx: AddCtx<A>) -> Op<A> {
+        let mut ops = self.convert(Kind::Map, &ctx);
+        let op = self.map.update(key, ctx, |doc, ctx| doc.set(val, ctx));
+        ops.push(Op::Map(Box::new(op)));
+        Op::Batch(ops)
+    }
+
+    /// Remove a key from the map, derive the ctx from `read`.
+    pub fn remove(&self, key: impl Into<String>, ctx: RmCtx<A>) -> Op<A> {
+        Op::Map(Box::new(self.map.rm(key, ctx)))
+    }
+
+    /// Insert a JSON value into the list at index, the doc becomes a
+    /// list if it wasn't.
+    pub fn insert(&self, index: usize, val: &Value, ctx: AddCtx<A>) -> Op<A> {
+        let mut ops = self.convert(Kind::List, &ctx);
+        let init = Doc::new().set(val, ctx.clone());
+        ops.push(Op::List(Box::new(self.list.insert_all(index, ctx, vec![init]))));
+        Op::Batch(ops)
+    }
+
+    /// Append a JSON value to the list, the doc becomes a list if it
+    /// wasn't.
+    pub fn push(&self, val: &Value, ctx: AddCtx<A>) -> Op<A> {
+        let index = if self.kind() == Some(Kind::List) { self.list.len().val } else { 0 };
+        self.insert(index, val, ctx)
+    }
+
+    /// Remove the element at index from the list, None if there's no
+    /// such element.
+    pub fn remove_at(&self, index: usize) -> Option<Op<A>> {
+        if self.kind() != Some(Kind::List) {
+            return None;
+        }
+        self.list.remove(index).map(|op| Op::List(Box::new(op)))
+    }
+
+    /// Insert text at the character index, the doc becomes empty text
+    /// if it wasn't text.
+    pub fn insert_text(&self, index: usize, text: &str, ctx: AddCtx<A>) -> Op<A> {
+        let mut ops = self.convert(Kind::Text, &ctx);
+        let empty = MVReg::new();
+        let char_ctx = reg_ctx(&empty, &ctx);
+        let chars = text.chars()
+            .map(|c| empty.set(c, char_ctx.clone()))
+            .collect();
+        ops.push(Op::Text(self.text.insert_all(index, ctx, chars)));
+        Op::Batch(ops)
+    }
+
+    /// Remove the characters in the range of indices
+    pub fn remove_text(&self, range: Range<usize>) -> Op<A> {
+        Op::Text(self.text.remove_range(range))
+    }
+
+    /// Increment the counter, the doc becomes a zero counter if it
+    /// wasn't a counter. Amounts past i64::MAX are taken as i64::MAX.
+    pub fn inc(&self, amount: u64, ctx: AddCtx<A>) -> Op<A> {
+        self.step(step_amount(amount), ctx)
+    }
+
+    /// Decrement the counter, the doc becomes a zero counter if it
+    /// wasn't a counter. Amounts past i64::MAX are taken as i64::MAX.
+    pub fn dec(&self, amount: u64, ctx: AddCtx<A>) -> Op<A> {
+        self.step(-step_amount(amount), ctx)
+    }
+
+    fn step(&self, amount: i64, ctx: AddCtx<A>) -> Op<A> {
+        let mut ops = self.convert(Kind::Counter, &ctx);
+        if amount != 0 {
+            let step = (ctx.dot.clone(), amount);
+            ops.push(Op::Counter(self.counter.add(step, ctx)));
+        }
+        Op::Batch(ops)
+    }
+
+    /// The ops writing the kind of the doc and clearing the content of
+    /// the other kinds. The kind is written by every edit, so it survives
+    /// a concurrent remove along with the edit.
+    fn convert(&self, kind: Kind, ctx: &AddCtx<A>) -> Vec<Op<A>> {
+        let mut ops = vec![Op::Kind(self.kind.set(kind, reg_ctx(&self.kind, ctx)))];
+        if kind != Kind::Map {
+            for key in self.map.keys().val {
+                let op = self.map.rm(key.clone(), self.rm_ctx());
+                ops.push(Op::Map(Box::new(op)));
+            }
+        }
+        if kind != Kind::List {
+            let len = self.list.len().val;
+            if len > 0 {
+                ops.push(Op::List(Box::new(self.list.remove_range(0..len))));
+            }
+        }
+        if kind != Kind::Text {
+            let len = self.text.len().val;
+            if len > 0 {
+                ops.push(Op::Text(self.text.remove_range(0..len)));
+            }
+        }
+        if kind != Kind::Counter {
+            let steps = self.counter.value();
+            let rm_ctx = steps.derive_rm_ctx();
+            for step in steps.val {
+                ops.push(Op::Counter(self.counter.remove(step, rm_ctx.clone())));
+            }
+        }
+        ops
+    }
+
+    /// The JSON value of one kind of content
+    fn render(&self, kind: Kind) -> Value {
+        match kind {
+            Kind::Map => Value::Object(
+                self.map.iter().val
+                    .map(|(key, doc)| (key.clone(), doc.to_json()))
+                    .collect()
+            ),
+            Kind::List => Value::Array(
+                self.list.iter().val
+                    .map(|doc| doc.to_json())
+                    .collect()
+            ),
+            Kind::Text => Value::String(
+                self.text.iter().val
+                    .filter_map(|reg| reg.read().val.into_iter().min())
+                    .collect()
+            ),
+            Kind::Counter => Value::from(self.counter_value()),
+            Kind::Reg => self.reg.read().val.into_iter()
+                .min()
+                .map(|prim| prim.to_json())
+                .unwrap_or(Value::Null)
+        }
+    }
+
+    fn child(&self, seg: &Seg) -> Option<&Doc<A>> {
+        match seg {
+            Seg::Key(key) if self.kind() == Some(Kind::Map) => {
+                self.map.get_ref(key).val
+            },
+            Seg::Index(index) if self.kind() == Some(Kind::List) => {
+                self.list.iter().val.nth(*index)
+            },
+            _ => None
+        }
+    }
+
+    fn collect_conflicts(&self, path: &mut Vec<Seg>, conflicts: &mut Vec<Conflict>) {
+        let mut kinds = self.kind.read().val;
+        kinds.sort();
+        kinds.dedup();
+        if kinds.len() > 1 {
+            conflicts.push(Conflict {
+                path: path.clone(),
+                values: kinds.into_iter().map(|kind| self.render(kind)).collect()
+            });
+        } else if kinds == vec![Kind::Reg] {
+            let mut prims = self.reg.read().val;
+            prims.sort();
+            prims.dedup();
+            if prims.len() > 1 {
+                conflicts.push(Conflict {
+                    path: path.clone(),
+                    values: prims.iter().map(|prim| prim.to_json()).collect()
+                });
+            }
+        }
+
+        match self.kind() {
+            Some(Kind::Map) => {
+                for (key, doc) in self.map.iter().val {
+                    path.push(Seg::Key(key.clone()));
+                    doc.collect_conflicts(path, conflicts);
+                    path.pop();
+                }
+            },
+            Some(Kind::List) => {
+                for (index, doc) in self.list.iter().val.enumerate() {
+                    path.push(Seg::Index(index));
+                    doc.collect_conflicts(path, conflicts);
+                    path.pop();
+                }
+            },
+            _ => ()
+        }
+    }
+
+    /// Removes cover every edit applied to the doc, the nested registers
+    /// hold the clocks of the edits made before them.
+    fn rm_ctx(&self) -> RmCtx<A> {
+        RmCtx { clock: self.clock() }
+    }
+
+    /// The clock of every edit applied to the doc
+    fn clock(&self) -> VClock<A> {
+        let mut clock = self.kind.read().add_clock;
+        clock.merge(&self.map.len().add_clock);
+        clock.merge(&self.list.len().add_clock);
+        clock.merge(&self.text.len().add_clock);
+        clock.merge(&self.reg.read().add_clock);
+        clock.merge(&self.counter.value().add_clock);
+        clock
+    }
+
+    /// The sum of the steps, saturating at the bounds of i64
+    fn counter_value(&self) -> i64 {
+        let sum: i128 = self.counter.iter().val.map(|(_, amount)| *amount as i128).sum();
+        cmp::max(cmp::min(sum, i64::MAX as i128), i64::MIN as i128) as i64
+    }
+}
+
+fn step_amount(amount: u64) -> i64 {
+    cmp::min(amount, i64::MAX as u64) as i64
+}
+
+/// A ctx for writing a register nested in a doc. The write only covers
+/// the register's own values, so truncating the edits of other parts of
+/// the doc doesn't make it concurrent with the values it replaced.
+fn reg_ctx<T: mvreg::Val, A: Actor>(reg: &MVReg<T, A>, ctx: &AddCtx<A>) -> AddCtx<A> {
+    let mut clock = reg.read().add_clock;
+    clock.apply(&ctx.dot);
+    AddCtx { clock, dot: ctx.dot.clone() }
+}
+
+fn truncate_put<T: mvreg::Val, A: Actor>(op: &mut mvreg::Op<T, A>, clock: &VClock<A>) {
+    match op {
+        mvreg::Op::Put { clock: put_clock, .. } => put_clock.subtract(clock)
+    }
+}
+
+fn put_holds_any<T: mvreg::Val, A: Actor>(op: &mvreg::Op<T, A>, clock: &VClock<A>) -> bool {
+    match op {
+        mvreg::Op::Put { clock: put_clock, .. } => {
+            put_clock.iter().any(|(actor, counter)| clock.get(actor) >= *counter)
+        }
+    }
+}
+
+fn truncate_list_op<V, A, F>(op: &mut list::Op<V, A>, clock: &VClock<A>, truncate: F)
+    where V: map::Val<A>, A: Actor, F: Fn(&mut V::Op, &VClock<A>)
+{
+    match op {
+        list::Op::Insert { after, ops, .. } => {
+            if after.as_ref().is_some_and(|id| seen(clock, &id.dot)) {
+                *after = None;
+            }
+            for op in ops.iter_mut() {
+                truncate(op, clock);
+            }
+        },
+        list::Op::Up { op, .. } => truncate(op, clock),
+        list::Op::Rm { .. } => ()
+    }
+}
+
+fn list_op_holds_any<V, A, F>(op: &list::Op<V, A>, clock: &VClock<A>, holds_any: F) -> bool
+    where V: map::Val<A>, A: Actor, F: Fn(&V::Op, &VClock<A>) -> bool
+{
+    match op {
+        list::Op::Insert { after, ops, .. } => {
+            after.as_ref().is_some_and(|id| seen(clock, &id.dot))
+                || ops.iter().any(|op| holds_any(op, clock))
+        },
+        list::Op::Up { op, .. } => holds_any(op, clock),
+        list::Op::Rm { .. } => false
+    }
+}
+
+fn seen<A: Actor>(clock: &VClock<A>, dot: &Dot<A>) -> bool {
+    clock.get(&dot.actor) >= dot.counter
+}
+
+/// The part of the clock that was witnessed by another clock
+fn witnessed<A: Actor>(clock: &VClock<A>, other: &VClock<A>) -> VClock<A> {
+    let mut seen = VClock::new();
+    for (actor, counter) in clock.iter() {
+        seen.witness(actor.clone(), cmp::min(*counter, other.get(actor)));
+    }
+    seen
+}
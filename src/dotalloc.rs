@@ -0,0 +1,155 @@
+//! Crash-safe dot generation.
+//!
+//! `VClock::inc` derives the next dot from the in-memory clock. If a node
+//! crashes after broadcasting an op but before persisting the op's effect,
+//! it restarts with an older clock and hands out the same dot again for a
+//! different op, which the rest of the cluster will silently ignore.
+//!
+//! A `DotAllocator` reserves ranges of counters (leases) in a local file
+//! before handing them out. After a crash, the allocator resumes after the
+//! end of the last lease, so a counter is never reused. The file is only
+//! written when a lease runs out, so with a lease size of `n` only one in
+//! every `n` dots costs a disk sync.
+//!
+//! The counters that were leased but not used before a crash are skipped,
+//! this leaves gaps in the actor's counters. Dot based CRDT's (`Orswot`,
+//! `Map`, `MVReg`, ...) don't mind the gaps, but the counters of a
+//! `GCounter` (and `PNCounter`) *are* its value, don't use an allocator to
+//! produce their ops.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Orswot, CmRDT};
+//! use crdts::dotalloc::DotAllocator;
+//!
+//! let path = std::env::temp_dir().join(format!("crdts-doc-dotalloc-{}", std::process::id()));
+//! # let _ = std::fs::remove_file(&path);
+//! let mut alloc = DotAllocator::open(7u8, &path, 100).unwrap();
+//!
+//! let mut set: Orswot<String, u8> = Orswot::new();
+//! let op = set.add("bob", set.value().derive_add_ctx_with(&mut alloc).unwrap());
+//! set.apply(&op);
+//! drop(alloc);
+//!
+//! // the process crashed and lost `set`, the new allocator resumes past the lease
+//! let mut alloc = DotAllocator::open(7u8, &path, 100).unwrap();
+//! let set: Orswot<String, u8> = Orswot::new();
+//! let ctx = set.value().derive_add_ctx_with(&mut alloc).unwrap();
+//! assert_eq!(ctx.dot.counter, 101);
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use vclock::{Actor, Dot, VClock, Counter};
+use super::{to_binary, from_binary};
+
+/// The on-disk record of a lease
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Lease<A: Actor> {
+    actor: A,
+    // every counter up to and including `end` may have been handed out
+    end: Counter
+}
+
+/// `DotAllocator` hands out dots for a single actor, reserving counters
+/// in a local file before they are used.
+#[derive(Debug)]
+pub struct DotAllocator<A: Actor> {
+    actor: A,
+    path: PathBuf,
+    lease_size: Counter,
+    // the last counter handed out
+    last: Counter,
+    // the end of the lease persisted on disk
+    leased: Counter
+}
+
+impl<A: Actor> DotAllocator<A> {
+    /// Open the allocator backed by the file at path, creating the file if
+    /// needed. Counters are reserved lease_size at a time.
+    ///
+    /// If the file holds a lease, the allocator resumes after its end,
+    /// so the counters that may have been used before are never reused.
+    /// Fails with `InvalidData` if the file holds the lease of another actor.
+    pub fn open(actor: A, path: impl AsRef<Path>, lease_size: Counter) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let lease_end = match File::open(&path) {
+            Ok(mut file) => {
+                let mut buf = Vec::new();
+                file.read_to_end(&mut buf)?;
+                let lease: Lease<A> = from_binary(buf)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                if lease.actor != actor {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("dot lease belongs to {:?} not {:?}", lease.actor, actor)
+                    ));
+                }
+                lease.end
+            },
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err)
+        };
+
+        Ok(DotAllocator {
+            actor,
+            path,
+            lease_size: lease_size.max(1),
+            last: lease_end,
+            leased: lease_end
+        })
+    }
+
+    /// The actor this allocator hands out dots for
+    pub fn actor(&self) -> &A {
+        &self.actor
+    }
+
+    /// Returns the next dot for this actor, it's greater than the actor's
+    /// entry in clock and greater than any dot handed out before, even by
+    /// an allocator that was open on the same file before a crash.
+    ///
+    /// Fails if every counter of the actor was handed out, the last lease
+    /// ends at the largest counter.
+    pub fn next_dot(&mut self, clock: &VClock<A>) -> io::Result<Dot<A>> {
+        let counter = self.last.max(clock.get(&self.actor)).checked_add(1)
+            .ok_or_else(|| io::Error::other(format!("the dot counters of {:?} ran out", self.actor)))?;
+        if counter > self.leased {
+            let end = counter.saturating_add(self.lease_size - 1);
+            self.persist(end)?;
+            self.leased = end;
+        }
+        self.last = counter;
+        Ok(Dot { actor: self.actor.clone(), counter })
+    }
+
+    /// Atomically replace the lease on disk: write a temporary file, sync
+    /// it, then rename it over the old lease.
+    fn persist(&self, end: Counter) -> io::Result<()> {
+        let lease = Lease { actor: self.actor.clone(), end };
+        // append to the whole file name, leases that only differ by
+        // extension mustn't share a temporary file
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(&to_binary(&lease))?;
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+
+        // make the rename itself durable, not every platform lets us
+        // open a directory so this is best effort.
+        if let Some(dir) = self.path.parent() {
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(())
+    }
+}
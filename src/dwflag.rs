@@ -0,0 +1,99 @@
+//! The `dwflag` crate provides a disable-wins flag.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{DWFlag, CmRDT};
+//!
+//! let mut flag = DWFlag::<u8>::new();
+//! assert_eq!(flag.read().val, true);
+//!
+//! let op = flag.disable(flag.read().derive_add_ctx(1));
+//! flag.apply(&op);
+//! assert_eq!(flag.read().val, false);
+//!
+//! let op = flag.enable(flag.read().derive_rm_ctx());
+//! flag.apply(&op);
+//! assert_eq!(flag.read().val, true);
+//! ```
+
+use traits::{CvRDT, CmRDT, Causal};
+use canonical::Canonical;
+use vclock::{VClock, Actor};
+use ctx::{ReadCtx, AddCtx, RmCtx};
+use orswot::{self, Orswot};
+
+/// `DWFlag` is a boolean flag where concurrent disables win over enables.
+///
+/// This is the dual of the `EWFlag`: every disable is witnessed by a dot
+/// and enabling the flag removes the disabling dots seen by the enabling
+/// actor. The flag is enabled when no disabling dot survives, so a new
+/// `DWFlag` starts out enabled.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DWFlag<A: Actor> {
+    disables: Orswot<(), A>
+}
+
+/// Op's define an edit to a DWFlag, they are the ops of the underlying
+/// Orswot of disabling dots.
+pub type Op<A> = orswot::Op<(), A>;
+
+impl<A: Actor> Default for DWFlag<A> {
+    fn default() -> Self {
+        DWFlag::new()
+    }
+}
+
+impl<A: Actor> CmRDT for DWFlag<A> {
+    type Op = Op<A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        self.disables.apply(op)
+    }
+}
+
+impl<A: Actor> CvRDT for DWFlag<A> {
+    fn merge(&mut self, other: &Self) {
+        self.disables.merge(&other.disables)
+    }
+}
+
+impl<A: Actor> Causal<A> for DWFlag<A> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        self.disables.truncate(clock)
+    }
+}
+
+impl<A: Actor> Canonical for DWFlag<A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.disables.encode_canonical(buf)
+    }
+}
+
+impl<A: Actor> DWFlag<A> {
+    /// Returns a new, enabled, `DWFlag`.
+    pub fn new() -> Self {
+        DWFlag { disables: Orswot::new() }
+    }
+
+    /// Enable the flag, only the disables witnessed by the ctx are undone.
+    pub fn enable(&self, ctx: RmCtx<A>) -> Op<A> {
+        self.disables.remove((), ctx)
+    }
+
+    /// Disable the flag.
+    pub fn disable(&self, ctx: AddCtx<A>) -> Op<A> {
+        self.disables.add((), ctx)
+    }
+
+    /// Read the state of the flag.
+    pub fn read(&self) -> ReadCtx<bool, A> {
+        let ctx = self.disables.contains(&());
+        ReadCtx {
+            add_clock: ctx.add_clock,
+            rm_clock: ctx.rm_clock,
+            val: !ctx.val
+        }
+    }
+}
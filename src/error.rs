@@ -14,7 +14,10 @@ pub enum Error {
     /// A generic error for any unmergable conflicts that may occur
     MergeConflict,
     /// We failed to apply a nested op to a nested CRDT
-    NestedOpFailed
+    NestedOpFailed,
+    /// A merkle tree or delta from another replica is malformed or
+    /// doesn't fit this CRDT
+    InvalidTree
 }
 
 impl error::Error for Error {
@@ -25,14 +28,17 @@ impl error::Error for Error {
             Error::MergeConflict =>
                 "There was a conflict while merging",
             Error::NestedOpFailed =>
-                "We failed to apply a nested op to a nested CRDT"
+                "We failed to apply a nested op to a nested CRDT",
+            Error::InvalidTree =>
+                "The merkle tree or delta is malformed or doesn't fit the CRDT"
         }
     }
     fn cause(&self) -> Option<&error::Error> {
         match self {
             Error::ConflictingMarker => None,
             Error::MergeConflict => None,
-            Error::NestedOpFailed => None
+            Error::NestedOpFailed => None,
+            Error::InvalidTree => None
         }
     }
 }
@@ -51,6 +57,10 @@ impl fmt::Display for Error {
             Error::NestedOpFailed => {
                 use std::error::Error;
                 write!(f, "{}", self.description())
+            },
+            Error::InvalidTree => {
+                use std::error::Error;
+                write!(f, "{}", self.description())
             }
         }
     }
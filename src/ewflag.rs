@@ -0,0 +1,93 @@
+//! The `ewflag` crate provides an enable-wins flag.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{EWFlag, CmRDT};
+//!
+//! let mut flag = EWFlag::<u8>::new();
+//! assert_eq!(flag.read().val, false);
+//!
+//! let op = flag.enable(flag.read().derive_add_ctx(1));
+//! flag.apply(&op);
+//! assert_eq!(flag.read().val, true);
+//!
+//! let op = flag.disable(flag.read().derive_rm_ctx());
+//! flag.apply(&op);
+//! assert_eq!(flag.read().val, false);
+//! ```
+
+use traits::{CvRDT, CmRDT, Causal};
+use canonical::Canonical;
+use vclock::{VClock, Actor};
+use ctx::{ReadCtx, AddCtx, RmCtx};
+use orswot::{self, Orswot};
+
+/// `EWFlag` is a boolean flag where concurrent enables win over disables.
+///
+/// Every enable is witnessed by a dot, disabling the flag removes the
+/// dots seen by the disabling actor. The flag is enabled as long as
+/// any enabling dot survives.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EWFlag<A: Actor> {
+    enables: Orswot<(), A>
+}
+
+/// Op's define an edit to an EWFlag, they are the ops of the underlying
+/// Orswot of enabling dots.
+pub type Op<A> = orswot::Op<(), A>;
+
+impl<A: Actor> Default for EWFlag<A> {
+    fn default() -> Self {
+        EWFlag::new()
+    }
+}
+
+impl<A: Actor> CmRDT for EWFlag<A> {
+    type Op = Op<A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        self.enables.apply(op)
+    }
+}
+
+impl<A: Actor> CvRDT for EWFlag<A> {
+    fn merge(&mut self, other: &Self) {
+        self.enables.merge(&other.enables)
+    }
+}
+
+impl<A: Actor> Causal<A> for EWFlag<A> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        self.enables.truncate(clock)
+    }
+}
+
+impl<A: Actor> Canonical for EWFlag<A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.enables.encode_canonical(buf)
+    }
+}
+
+impl<A: Actor> EWFlag<A> {
+    /// Returns a new, disabled, `EWFlag`.
+    pub fn new() -> Self {
+        EWFlag { enables: Orswot::new() }
+    }
+
+    /// Enable the flag.
+    pub fn enable(&self, ctx: AddCtx<A>) -> Op<A> {
+        self.enables.add((), ctx)
+    }
+
+    /// Disable the flag, only the enables witnessed by the ctx are undone.
+    pub fn disable(&self, ctx: RmCtx<A>) -> Op<A> {
+        self.enables.remove((), ctx)
+    }
+
+    /// Read the state of the flag.
+    pub fn read(&self) -> ReadCtx<bool, A> {
+        self.enables.contains(&())
+    }
+}
@@ -0,0 +1,380 @@
+//! Maps and sets whose entries expire.
+//!
+//! An `ExpiringMap` is a `Map` where every entry carries the time it
+//! expires at, an `ExpiringSet` is the same for the members of a set. Time is anything `Ord`: wall clock seconds, a hybrid
+//! logical clock or a user defined counter. Replicas don't share a clock,
+//! so reads take the current time from the caller and hide the entries
+//! that expired by then.
+//!
+//! Expired entries stay in the map until they're collected: `gc` returns
+//! the ops removing every entry that expired by the given time. The
+//! removes carry the clocks of the expired entries, two replicas that
+//! collect the same entries produce the same ops, and an update that
+//! raced the collection survives it, just like any other remove of a
+//! `Map`. An entry's expiry only moves forward, updates that should keep
+//! an entry alive push its expiry past the time it will be read at.
+//!
+//! An expired entry is gone for good, even before it's collected:
+//! updating it removes the expired contents in the same op and starts
+//! over from an empty value.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{ExpiringMap, MVReg, CmRDT};
+//!
+//! let mut sessions: ExpiringMap<String, MVReg<String, u8>, u8, u64> = ExpiringMap::new();
+//! let op = sessions.update(
+//!     "alice",
+//!     sessions.get(&"alice".into(), &0).derive_add_ctx(1),
+//!     &0,
+//!     60,
+//!     |reg, ctx| reg.set("token", ctx)
+//! );
+//! sessions.apply(&op);
+//!
+//! assert!(sessions.get(&"alice".into(), &30).val.is_some());
+//! assert!(sessions.get(&"alice".into(), &60).val.is_none());
+//!
+//! for op in sessions.gc(&60) {
+//!     sessions.apply(&op);
+//! }
+//! assert_eq!(sessions.expiry(&"alice".into()), None);
+//! ```
+//!
+//! ```
+//! use crdts::{ExpiringSet, CmRDT};
+//!
+//! let mut online: ExpiringSet<String, u8, u64> = ExpiringSet::new();
+//! let op = online.add("bob", online.len(&0).derive_add_ctx(1), &0, 30);
+//! online.apply(&op);
+//!
+//! assert!(online.contains(&"bob".into(), &29).val);
+//! assert!(!online.contains(&"bob".into(), &30).val);
+//! ```
+
+use std::cmp;
+use std::fmt::Debug;
+
+use serde::de::DeserializeOwned;
+
+use traits::{Causal, CvRDT, CmRDT};
+use vclock::{VClock, Actor};
+use ctx::{ReadCtx, ReadCtxRef, AddCtx, RmCtx};
+use ewflag::EWFlag;
+use lwwreg::Marker;
+use map::{self, Map, Key, Val};
+use canonical::{self, Canonical};
+
+/// The ops of an `ExpiringMap`
+pub type Op<K, V, A, T> = map::Op<K, Entry<V, T>, A>;
+
+/// The ops of an `ExpiringSet`
+pub type SetOp<M, A, T> = Op<M, EWFlag<A>, A, T>;
+
+/// `ExpiringMap` is a `Map` whose entries expire at a time of their own.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpiringMap<K: Key, V: Val<A>, A: Actor, T: Marker> {
+    map: Map<K, Entry<V, T>, A>
+}
+
+/// The value of an `ExpiringMap` entry along with its expiry.
+#[serde(bound(deserialize = "V: DeserializeOwned"))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry<V, T: Marker> {
+    /// The nested CRDT
+    pub val: V,
+    /// The latest expiry of the entry, None if it was never set
+    pub expiry: Option<T>
+}
+
+/// An update of an `ExpiringMap` entry
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryOp<V: CmRDT, T: Marker> {
+    /// The operation to apply on the nested CRDT
+    pub op: V::Op,
+    /// The entry expires at this time, unless it was already set later
+    pub expiry: T
+}
+
+impl<V: Default, T: Marker> Default for Entry<V, T> {
+    fn default() -> Self {
+        Entry { val: V::default(), expiry: None }
+    }
+}
+
+impl<V: Causal<A>, A: Actor, T: Marker> Causal<A> for Entry<V, T> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        // the expiry of a partly removed entry is kept, the surviving
+        // edits may have set it.
+        self.val.truncate(clock);
+    }
+}
+
+impl<V: CmRDT + Debug + Clone, T: Marker> CmRDT for Entry<V, T> {
+    type Op = EntryOp<V, T>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        self.val.apply(&op.op);
+        self.expiry = cmp::max(self.expiry.take(), Some(op.expiry.clone()));
+    }
+}
+
+impl<V: CvRDT, T: Marker> CvRDT for Entry<V, T> {
+    fn merge(&mut self, other: &Self) {
+        self.val.merge(&other.val);
+        self.expiry = cmp::max(self.expiry.take(), other.expiry.clone());
+    }
+}
+
+impl<V: Canonical, T: Marker> Canonical for Entry<V, T> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.val.encode_canonical(buf);
+        canonical::encode(&self.expiry, buf);
+    }
+}
+
+impl<V, T: Marker> Entry<V, T> {
+    /// Returns true if the entry expired by now
+    pub fn is_expired(&self, now: &T) -> bool {
+        match self.expiry {
+            Some(ref expiry) => expiry <= now,
+            None => false
+        }
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor, T: Marker> Default for ExpiringMap<K, V, A, T> {
+    fn default() -> Self {
+        ExpiringMap::new()
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor, T: Marker> Causal<A> for ExpiringMap<K, V, A, T> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        self.map.truncate(clock);
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor, T: Marker> CmRDT for ExpiringMap<K, V, A, T> {
+    type Op = Op<K, V, A, T>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        self.map.apply(op);
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor, T: Marker> CvRDT for ExpiringMap<K, V, A, T> {
+    fn merge(&mut self, other: &Self) {
+        self.map.merge(&other.map);
+    }
+}
+
+impl<K: Key, V: Val<A> + Canonical, A: Actor, T: Marker> Canonical for ExpiringMap<K, V, A, T> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.map.encode_canonical(buf)
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor, T: Marker> ExpiringMap<K, V, A, T> {
+    /// Constructs an empty map
+    pub fn new() -> Self {
+        ExpiringMap { map: Map::new() }
+    }
+
+    /// Retrieve the value stored under key, None if it expired by now.
+    /// The rm_clock covers expired entries too.
+    pub fn get(&self, key: &K, now: &T) -> ReadCtx<Option<V>, A> {
+        let ReadCtx { add_clock, rm_clock, val } = self.map.get(key);
+        ReadCtx {
+            add_clock,
+            rm_clock,
+            val: val.and_then(|entry| {
+                if entry.is_expired(now) {
+                    None
+                } else {
+                    Some(entry.val)
+                }
+            })
+        }
+    }
+
+    /// The time the entry under key expires at, expired or not
+    pub fn expiry(&self, key: &K) -> Option<T> {
+        self.map.get_ref(key).val.and_then(|entry| entry.expiry.clone())
+    }
+
+    /// Returns the number of entries that haven't expired by now
+    pub fn len(&self, now: &T) -> ReadCtx<usize, A> {
+        let ReadCtxRef { add_clock, rm_clock, val } = self.iter(now);
+        ReadCtx {
+            add_clock: add_clock.into_owned(),
+            rm_clock: rm_clock.into_owned(),
+            val: val.count()
+        }
+    }
+
+    /// Returns an iterator over the entries that haven't expired by now,
+    /// ordered by key. The ReadCtxRef covers the whole traversal.
+    pub fn iter<'a>(&'a self, now: &'a T)
+        -> ReadCtxRef<'a, impl Iterator<Item=(&'a K, &'a V)>, A>
+    {
+        let ReadCtxRef { add_clock, rm_clock, val } = self.map.iter();
+        ReadCtxRef {
+            add_clock,
+            rm_clock,
+            val: val
+                .filter(move |(_, entry)| !entry.is_expired(now))
+                .map(|(key, entry)| (key, &entry.val))
+        }
+    }
+
+    /// Update the value under key and push its expiry to the given time.
+    /// If the entry expired by now, the op removes its expired contents
+    /// and the updater is given V::default().
+    pub fn update<F, I>(&self, key: I, ctx: AddCtx<A>, now: &T, expiry: T, f: F) -> Op<K, V, A, T>
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op,
+              I: Into<K>
+    {
+        let key = key.into();
+        let expired = self.map.get_ref(&key).val
+            .map(|entry| entry.is_expired(now))
+            .unwrap_or(false);
+        if !expired {
+            return self.map.update(key, ctx, |entry, ctx| EntryOp {
+                op: f(&entry.val, ctx),
+                expiry
+            });
+        }
+
+        let clock = self.map.get_ref(&key).rm_clock.into_owned();
+        let op = EntryOp {
+            op: f(&V::default(), ctx.clone()),
+            expiry
+        };
+        map::Op::Batch {
+            dot: ctx.dot,
+            updates: vec![(key.clone(), op)],
+            rms: vec![(key, clock)]
+        }
+    }
+
+    /// Remove an entry from the map
+    pub fn rm(&self, key: impl Into<K>, ctx: RmCtx<A>) -> Op<K, V, A, T> {
+        self.map.rm(key, ctx)
+    }
+
+    /// Returns the ops removing every entry that expired by now, ordered
+    /// by key. Applying them is idempotent, and replicas collecting the
+    /// same entries produce the same ops.
+    pub fn gc(&self, now: &T) -> Vec<Op<K, V, A, T>> {
+        self.map.iter().val
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| {
+                let clock = self.map.get_ref(key).rm_clock.into_owned();
+                map::Op::Rm { clock, key: key.clone() }
+            })
+            .collect()
+    }
+
+    /// The underlying map, including the expired entries
+    pub fn inner(&self) -> &Map<K, Entry<V, T>, A> {
+        &self.map
+    }
+}
+
+/// `ExpiringSet` is a set whose members expire at a time of their own.
+///
+/// Members are the keys of an `ExpiringMap` of enable-wins flags, they
+/// share its reads, removes and collection.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpiringSet<M: Key, A: Actor, T: Marker> {
+    map: ExpiringMap<M, EWFlag<A>, A, T>
+}
+
+impl<M: Key, A: Actor, T: Marker> Default for ExpiringSet<M, A, T> {
+    fn default() -> Self {
+        ExpiringSet::new()
+    }
+}
+
+impl<M: Key, A: Actor, T: Marker> Causal<A> for ExpiringSet<M, A, T> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        self.map.truncate(clock);
+    }
+}
+
+impl<M: Key, A: Actor, T: Marker> CmRDT for ExpiringSet<M, A, T> {
+    type Op = SetOp<M, A, T>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        self.map.apply(op);
+    }
+}
+
+impl<M: Key, A: Actor, T: Marker> CvRDT for ExpiringSet<M, A, T> {
+    fn merge(&mut self, other: &Self) {
+        self.map.merge(&other.map);
+    }
+}
+
+impl<M: Key, A: Actor, T: Marker> Canonical for ExpiringSet<M, A, T> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.map.encode_canonical(buf)
+    }
+}
+
+impl<M: Key, A: Actor, T: Marker> ExpiringSet<M, A, T> {
+    /// Constructs an empty set
+    pub fn new() -> Self {
+        ExpiringSet { map: ExpiringMap::new() }
+    }
+
+    /// Add a member, or push its expiry to the given time if it's
+    /// already in the set.
+    pub fn add(&self, member: impl Into<M>, ctx: AddCtx<A>, now: &T, expiry: T) -> SetOp<M, A, T> {
+        self.map.update(member, ctx, now, expiry, |flag, ctx| flag.enable(ctx))
+    }
+
+    /// Remove a member from the set
+    pub fn rm(&self, member: impl Into<M>, ctx: RmCtx<A>) -> SetOp<M, A, T> {
+        self.map.rm(member, ctx)
+    }
+
+    /// Check if a member is in the set and hasn't expired by now
+    pub fn contains(&self, member: &M, now: &T) -> ReadCtx<bool, A> {
+        let ReadCtx { add_clock, rm_clock, val } = self.map.get(member, now);
+        ReadCtx { add_clock, rm_clock, val: val.is_some() }
+    }
+
+    /// The time a member expires at, expired or not
+    pub fn expiry(&self, member: &M) -> Option<T> {
+        self.map.expiry(member)
+    }
+
+    /// Returns the number of members that haven't expired by now
+    pub fn len(&self, now: &T) -> ReadCtx<usize, A> {
+        self.map.len(now)
+    }
+
+    /// Returns an iterator over the members that haven't expired by now,
+    /// in order. The ReadCtxRef covers the whole traversal.
+    pub fn iter<'a>(&'a self, now: &'a T) -> ReadCtxRef<'a, impl Iterator<Item=&'a M>, A> {
+        let ReadCtxRef { add_clock, rm_clock, val } = self.map.iter(now);
+        ReadCtxRef { add_clock, rm_clock, val: val.map(|(member, _)| member) }
+    }
+
+    /// Returns the ops removing every member that expired by now, see
+    /// `ExpiringMap::gc`.
+    pub fn gc(&self, now: &T) -> Vec<SetOp<M, A, T>> {
+        self.map.gc(now)
+    }
+
+    /// The underlying map, including the expired members
+    pub fn inner(&self) -> &ExpiringMap<M, EWFlag<A>, A, T> {
+        &self.map
+    }
+}
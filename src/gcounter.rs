@@ -1,5 +1,6 @@
 use std::cmp::Ordering;
 use traits::{CvRDT, CmRDT};
+use canonical::Canonical;
 use vclock::{VClock, Actor, Dot};
 
 /// `GCounter` is a grow-only witnessed counter.
@@ -55,12 +56,24 @@ impl<A: Actor> CmRDT for GCounter<A> {
     }
 }
 
+impl<A: Actor> Default for GCounter<A> {
+    fn default() -> Self {
+        GCounter::new()
+    }
+}
+
 impl<A: Actor> CvRDT for GCounter<A> {
     fn merge(&mut self, other: &Self) {
         self.inner.merge(&other.inner);
     }
 }
 
+impl<A: Actor> Canonical for GCounter<A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.inner.encode_canonical(buf)
+    }
+}
+
 impl<A: Actor> GCounter<A> {
     /// Produces a new `GCounter`.
     pub fn new() -> GCounter<A> {
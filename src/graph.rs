@@ -0,0 +1,250 @@
+//! An add-wins directed graph.
+//!
+//! The vertices and edges of a `Graph` are the members of a single
+//! `Orswot`, so every vertex and every edge has observed-remove
+//! semantics: an add that wasn't seen by a concurrent remove survives it.
+//!
+//! The classic anomaly of a replicated graph is an edge added while one
+//! of its vertices is concurrently removed, leaving the edge dangling.
+//! Here an edge keeps both of its vertices in the graph: the vertices of
+//! a graph are the vertices added explicitly along with the endpoints of
+//! its edges. Removing a vertex removes the edges it's part of that the
+//! remover has seen, an edge added concurrently wins and brings the
+//! vertex back, so no edge ever points at a removed vertex.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Graph, CvRDT, CmRDT};
+//!
+//! let mut a: Graph<String, u8> = Graph::new();
+//! let op = a.add_edge("app", "lib", a.vertices().derive_add_ctx(1));
+//! a.apply(&op);
+//! let mut b = a.clone();
+//!
+//! // a removes lib while b makes another crate depend on it
+//! let op = a.remove_vertex("lib", a.contains_vertex(&"lib".into()).derive_rm_ctx());
+//! a.apply(&op);
+//! assert!(!a.contains_edge(&"app".into(), &"lib".into()).val);
+//! let op = b.add_edge("cli", "lib", b.vertices().derive_add_ctx(2));
+//! b.apply(&op);
+//!
+//! // the concurrent edge keeps lib in the graph
+//! a.merge(&b);
+//! assert!(a.contains_vertex(&"lib".into()).val);
+//! assert_eq!(a.neighbors(&"cli".into()).val, vec!["lib".to_string()].into_iter().collect());
+//! assert!(a.neighbors(&"app".into()).val.is_empty());
+//! ```
+
+use std::collections::HashSet;
+
+use traits::{CvRDT, CmRDT};
+use vclock::{VClock, Dot, Actor};
+use ctx::{ReadCtx, AddCtx, RmCtx};
+use orswot::{self, Orswot, Member};
+use canonical::Canonical;
+
+/// `Graph` is an add-wins directed graph.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Graph<V: Member, A: Actor> {
+    elems: Orswot<Elem<V>, A>
+}
+
+/// The members of the underlying set
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Elem<V: Member> {
+    Vertex(V),
+    Edge(V, V)
+}
+
+/// Op's define an edit to a `Graph`
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op<V: Member, A: Actor> {
+    /// Add a vertex
+    AddVertex {
+        /// Add witnessing dot
+        dot: Dot<A>,
+        /// Vertex to add
+        vertex: V
+    },
+    /// Add an edge, along with its vertices
+    AddEdge {
+        /// Add witnessing dot
+        dot: Dot<A>,
+        /// Vertex the edge starts at
+        from: V,
+        /// Vertex the edge ends at
+        to: V
+    },
+    /// Remove a vertex along with the edges it's part of
+    RmVertex {
+        /// Remove witnessing clock
+        clock: VClock<A>,
+        /// Vertex to remove
+        vertex: V,
+        /// The edges of the vertex seen by the remover
+        edges: Vec<(V, V)>
+    },
+    /// Remove an edge
+    RmEdge {
+        /// Remove witnessing clock
+        clock: VClock<A>,
+        /// Vertex the edge starts at
+        from: V,
+        /// Vertex the edge ends at
+        to: V
+    }
+}
+
+impl<V: Member, A: Actor> Default for Graph<V, A> {
+    fn default() -> Self {
+        Graph::new()
+    }
+}
+
+impl<V: Member, A: Actor> CmRDT for Graph<V, A> {
+    type Op = Op<V, A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        match op.clone() {
+            Op::AddVertex { dot, vertex } => {
+                self.elems.apply(&orswot::Op::Add { dot, member: Elem::Vertex(vertex) });
+            },
+            Op::AddEdge { dot, from, to } => {
+                self.elems.apply(&orswot::Op::Add { dot, member: Elem::Edge(from, to) });
+            },
+            Op::RmVertex { clock, vertex, edges } => {
+                for (from, to) in edges {
+                    self.elems.apply(&orswot::Op::Rm {
+                        clock: clock.clone(),
+                        member: Elem::Edge(from, to)
+                    });
+                }
+                self.elems.apply(&orswot::Op::Rm { clock, member: Elem::Vertex(vertex) });
+            },
+            Op::RmEdge { clock, from, to } => {
+                self.elems.apply(&orswot::Op::Rm { clock, member: Elem::Edge(from, to) });
+            }
+        }
+    }
+}
+
+impl<V: Member, A: Actor> CvRDT for Graph<V, A> {
+    fn merge(&mut self, other: &Self) {
+        self.elems.merge(&other.elems);
+    }
+}
+
+impl<V: Member, A: Actor> Canonical for Graph<V, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.elems.encode_canonical(buf)
+    }
+}
+
+impl<V: Member, A: Actor> Graph<V, A> {
+    /// Returns a new, empty graph
+    pub fn new() -> Self {
+        Graph { elems: Orswot::new() }
+    }
+
+    /// Add a vertex
+    pub fn add_vertex(&self, vertex: impl Into<V>, ctx: AddCtx<A>) -> Op<V, A> {
+        Op::AddVertex { dot: ctx.dot, vertex: vertex.into() }
+    }
+
+    /// Add an edge, its vertices are added along with it.
+    pub fn add_edge(&self, from: impl Into<V>, to: impl Into<V>, ctx: AddCtx<A>) -> Op<V, A> {
+        Op::AddEdge { dot: ctx.dot, from: from.into(), to: to.into() }
+    }
+
+    /// Remove a vertex and the edges it's part of, derive the ctx from
+    /// `contains_vertex`.
+    pub fn remove_vertex(&self, vertex: impl Into<V>, ctx: RmCtx<A>) -> Op<V, A> {
+        let vertex = vertex.into();
+        let edges = self.edges_of(&vertex)
+            .map(|(from, to)| (from.clone(), to.clone()))
+            .collect();
+        Op::RmVertex { clock: ctx.clock, vertex, edges }
+    }
+
+    /// Remove an edge, derive the ctx from `contains_edge`. The vertices
+    /// of the edge stay in the graph only if they were added explicitly
+    /// or have other edges.
+    pub fn remove_edge(&self, from: impl Into<V>, to: impl Into<V>, ctx: RmCtx<A>) -> Op<V, A> {
+        Op::RmEdge { clock: ctx.clock, from: from.into(), to: to.into() }
+    }
+
+    /// Check if the graph contains a vertex, the rm_clock covers the
+    /// vertex and its edges.
+    pub fn contains_vertex(&self, vertex: &V) -> ReadCtx<bool, A> {
+        let mut read_ctx = self.elems.contains(&Elem::Vertex(vertex.clone()));
+        for (from, to) in self.edges_of(vertex) {
+            let edge_ctx = self.elems.contains(&Elem::Edge(from.clone(), to.clone()));
+            read_ctx.rm_clock.merge(&edge_ctx.rm_clock);
+            read_ctx.val = true;
+        }
+        read_ctx
+    }
+
+    /// Check if the graph contains an edge
+    pub fn contains_edge(&self, from: &V, to: &V) -> ReadCtx<bool, A> {
+        self.elems.contains(&Elem::Edge(from.clone(), to.clone()))
+    }
+
+    /// The vertices of the graph
+    pub fn vertices(&self) -> ReadCtx<HashSet<V>, A> {
+        let ReadCtx { add_clock, rm_clock, .. } = self.elems.value();
+        let mut vertices = HashSet::new();
+        for elem in self.elems.iter().val {
+            match elem {
+                Elem::Vertex(vertex) => {
+                    vertices.insert(vertex.clone());
+                },
+                Elem::Edge(from, to) => {
+                    vertices.insert(from.clone());
+                    vertices.insert(to.clone());
+                }
+            }
+        }
+        ReadCtx { add_clock, rm_clock, val: vertices }
+    }
+
+    /// The edges of the graph
+    pub fn edges(&self) -> ReadCtx<HashSet<(V, V)>, A> {
+        let ReadCtx { add_clock, rm_clock, .. } = self.elems.value();
+        let edges = self.elems.iter().val
+            .filter_map(|elem| match elem {
+                Elem::Edge(from, to) => Some((from.clone(), to.clone())),
+                Elem::Vertex(_) => None
+            })
+            .collect();
+        ReadCtx { add_clock, rm_clock, val: edges }
+    }
+
+    /// The vertices the edges starting at vertex end at, the rm_clock
+    /// covers those edges.
+    pub fn neighbors(&self, vertex: &V) -> ReadCtx<HashSet<V>, A> {
+        let ReadCtx { add_clock, .. } = self.elems.value();
+        let mut rm_clock = VClock::new();
+        let mut neighbors = HashSet::new();
+        for (from, to) in self.edges_of(vertex) {
+            if from == vertex {
+                let edge_ctx = self.elems.contains(&Elem::Edge(from.clone(), to.clone()));
+                rm_clock.merge(&edge_ctx.rm_clock);
+                neighbors.insert(to.clone());
+            }
+        }
+        ReadCtx { add_clock, rm_clock, val: neighbors }
+    }
+
+    /// The edges starting or ending at vertex
+    fn edges_of<'a>(&'a self, vertex: &'a V) -> impl Iterator<Item=(&'a V, &'a V)> {
+        self.elems.iter().val.filter_map(move |elem| match elem {
+            Elem::Edge(from, to) if from == vertex || to == vertex => Some((from, to)),
+            _ => None
+        })
+    }
+}
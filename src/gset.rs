@@ -3,6 +3,7 @@ use super::*;
 use std::collections::BTreeSet;
 
 /// A `GSet` is a grow-only set.
+#[serde(bound(deserialize = ""))]
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GSet<A: Ord + Serialize + DeserializeOwned> {
     value: BTreeSet<A>,
@@ -61,3 +62,10 @@ impl<A: Ord + Serialize + DeserializeOwned> GSet<A> {
         self.value.contains(element)
     }
 }
+
+impl<A: Ord + Serialize + DeserializeOwned> Canonical for GSet<A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        // the members are kept in a BTreeSet, so they're already ordered
+        canonical::encode(self, buf)
+    }
+}
@@ -0,0 +1,120 @@
+//! Reads of past versions of a CRDT.
+//!
+//! A `History` wraps an op based CRDT and keeps every op applied to it,
+//! so the value the CRDT had at any `VClock` cut can be materialized by
+//! replaying the ops the cut covers. The result is returned as a
+//! `ReadCtx`, its clocks are the clocks of the historical state, so edits
+//! can be branched off that point.
+//!
+//! Removes carry no dot of their own, a `VClock` can't tell apart the
+//! versions right before and right after a remove. A remove (or any op
+//! that adds nothing new) is taken to happen right after the ops applied
+//! before it, it's only visible at cuts that cover an op applied after
+//! it. Reading at a version gives the state as it was when the version
+//! was reached, removes that followed without further edits are left
+//! out, read the current state with `state`.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Orswot, History, CmRDT};
+//!
+//! let mut set: History<Orswot<String, u8>> = History::new();
+//! let op = set.state().add("apple", set.state().value().derive_add_ctx(1));
+//! set.apply(&op);
+//! let version = set.state().value().add_clock;
+//!
+//! let op = set.state().add("banana", set.state().value().derive_add_ctx(1));
+//! set.apply(&op);
+//! let op = set.state().remove("apple", set.state().contains(&"apple".into()).derive_rm_ctx());
+//! set.apply(&op);
+//!
+//! let past = set.at(&version);
+//! assert_eq!(past.val.value().val, vec!["apple".to_string()].into_iter().collect());
+//! ```
+
+use traits::{CvRDT, CmRDT, CausalOp};
+use vclock::{Actor, VClock};
+use ctx::ReadCtx;
+
+/// `History` owns a CRDT along with every op applied to it.
+///
+/// Ops are kept forever, the memory used by a `History` grows with every
+/// op, even ops that were already applied.
+#[derive(Debug, Clone)]
+pub struct History<C: CmRDT> {
+    state: C,
+    ops: Vec<C::Op>
+}
+
+impl<C: CmRDT + Default> Default for History<C> {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl<C: CmRDT> CmRDT for History<C> {
+    type Op = C::Op;
+
+    fn apply(&mut self, op: &Self::Op) {
+        self.state.apply(op);
+        self.ops.push(op.clone());
+    }
+}
+
+impl<C: CmRDT + Default> History<C> {
+    /// Returns a history of a new CRDT.
+    pub fn new() -> Self {
+        History { state: C::default(), ops: Vec::new() }
+    }
+
+    /// Materialize the state of the CRDT at the given cut from the ops
+    /// the cut covers, replayed in the order they were applied. Ops that
+    /// add nothing to the ops applied before them are only covered if
+    /// the cut covers an op applied after them.
+    ///
+    /// The clocks of the returned `ReadCtx` are the clocks of the ops it
+    /// covers. Edits branched off a historical state should be made by an
+    /// actor of their own, the dots derived from it may already have been
+    /// used by the actor's later edits.
+    pub fn at<A: Actor>(&self, cut: &VClock<A>) -> ReadCtx<C, A>
+        where C::Op: CausalOp<A>
+    {
+        let mut state = C::default();
+        let mut clock = VClock::new();
+        // the clocks of all ops applied before the current op
+        let mut seen = VClock::new();
+        for op in self.ops.iter() {
+            let op_clock = op.clock();
+            let after_cut = op_clock <= seen && *cut <= seen;
+            if op_clock <= *cut && !after_cut {
+                state.apply(op);
+                clock.merge(&op_clock);
+            }
+            seen.merge(&op_clock);
+        }
+
+        ReadCtx {
+            add_clock: clock.clone(),
+            rm_clock: clock,
+            val: state
+        }
+    }
+}
+
+impl<C: CmRDT> History<C> {
+    /// The current state
+    pub fn state(&self) -> &C {
+        &self.state
+    }
+
+    /// Consumes the history, returning the current state.
+    pub fn into_state(self) -> C {
+        self.state
+    }
+
+    /// The ops applied so far, in the order they were applied
+    pub fn ops(&self) -> &[C::Op] {
+        &self.ops
+    }
+}
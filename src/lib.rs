@@ -5,20 +5,41 @@
 
 pub use error::{Result, Error};
 pub use gcounter::GCounter;
+pub use gset::GSet;
 pub use lwwreg::LWWReg;
+pub use lwwset::LWWSet;
+pub use lwwmap::LWWMap;
 pub use mvreg::MVReg;
 pub use orswot::Orswot;
 pub use pncounter::PNCounter;
 pub use map::Map;
-pub use ctx::{ReadCtx, AddCtx, RmCtx};
+pub use ormap::ORMap;
+pub use multimap::MultiMap;
+pub use tree::Tree;
+pub use graph::Graph;
+pub use list::List;
+pub use doc::Doc;
+pub use ewflag::EWFlag;
+pub use dwflag::DWFlag;
+pub use replica::Replica;
+pub use history::History;
+pub use expiring::{ExpiringMap, ExpiringSet};
+pub use ctx::{ReadCtx, ReadCtxRef, AddCtx, RmCtx};
 pub use vclock::{VClock, Dot, Actor};
-pub use traits::{CvRDT, CmRDT, Causal, FunkyCvRDT, FunkyCmRDT};
+pub use traits::{CvRDT, CmRDT, Causal, CausalOp, Diff, Undo, FunkyCvRDT, FunkyCmRDT};
+pub use canonical::Canonical;
 
 
 /// `traits` contains Trait commonly used when working with CRDT's
 pub mod traits;
 /// `lwwreg` contains the last-write-wins register.
 pub mod lwwreg;
+/// `gset` contains the grow-only set.
+pub mod gset;
+/// `lwwset` contains the last-write-wins element set.
+pub mod lwwset;
+/// `lwwmap` contains the last-write-wins map.
+pub mod lwwmap;
 /// `mvreg` contains the multi-value register.
 pub mod mvreg;
 /// `vclock` contains the vector clock.
@@ -31,8 +52,40 @@ pub mod gcounter;
 pub mod pncounter;
 /// `map` contains a map CRDT which allows nesting of CRDT's
 pub mod map;
+/// `ormap` contains a map CRDT with observed-remove semantics
+pub mod ormap;
+/// `multimap` contains an add-wins map from keys to sets of values
+pub mod multimap;
+/// `tree` contains a replicated tree with move operations
+pub mod tree;
+/// `graph` contains an add-wins directed graph
+pub mod graph;
+/// `list` contains a replicated list of nested CRDT's
+pub mod list;
+/// `doc` contains a JSON document CRDT
+pub mod doc;
+/// `ewflag` contains the enable-wins flag
+pub mod ewflag;
+/// `dwflag` contains the disable-wins flag
+pub mod dwflag;
 /// `ctx` contains the read and write contexts
 pub mod ctx;
+/// `deferred` contains the deferred removes and element merge shared by the add-wins CRDTs
+mod deferred;
+/// `canonical` contains the canonical encoding of CRDT state
+pub mod canonical;
+/// `merkle` contains Merkle tree summaries for anti-entropy
+pub mod merkle;
+/// `replica` contains a handle bundling a CRDT with its actor and outbox
+pub mod replica;
+/// `dotalloc` contains a crash-safe allocator of dots
+pub mod dotalloc;
+/// `oplog` contains a durable log of the ops applied to a CRDT
+pub mod oplog;
+/// `history` contains a CRDT wrapper that can read past versions
+pub mod history;
+/// `expiring` contains a map and a set whose entries expire
+pub mod expiring;
 
 /// `error` contains possible Error codes generated by CRDT operations
 pub mod error;
@@ -40,6 +93,7 @@ pub mod error;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
+extern crate serde_json;
 extern crate bincode;
 
 use bincode::{Infinite, deserialize, serialize};
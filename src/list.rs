@@ -0,0 +1,385 @@
+//! A replicated list of nested CRDT's.
+//!
+//! `List` is a Replicated Growable Array (RGA): every element is
+//! inserted right after an element the inserting replica has seen, or at
+//! the front of the list. Concurrent inserts after the same element are
+//! ordered by their sequence numbers, an insert is numbered past every
+//! element its replica has seen, so it lands right where it was inserted.
+//!
+//! Elements hold a nested CRDT. Removes win: a removed element stays
+//! removed, even if it was updated concurrently. Removed elements are
+//! kept as tombstones, later inserts may be anchored on them. Elements
+//! truncated by a clock are dropped instead, along with the removes of a
+//! `Map` key holding the list, inserts anchored on them are placed at the
+//! front of the list.
+//!
+//! Ops must be applied in the order they were produced, an insert
+//! anchored on an element this list hasn't seen is ignored until it's
+//! applied again after the element's insert.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{List, MVReg, CvRDT, CmRDT};
+//!
+//! let mut a: List<MVReg<String, u8>, u8> = List::new();
+//! let op = a.insert(0, a.len().derive_add_ctx(1), |reg, ctx| reg.set("milk", ctx));
+//! a.apply(&op);
+//! let mut b = a.clone();
+//!
+//! let op = a.insert(1, a.len().derive_add_ctx(1), |reg, ctx| reg.set("eggs", ctx));
+//! a.apply(&op);
+//! let op = b.insert(0, b.len().derive_add_ctx(2), |reg, ctx| reg.set("flour", ctx));
+//! b.apply(&op);
+//!
+//! a.merge(&b);
+//! let items: Vec<String> = a.iter().val.map(|reg| reg.read().val[0].clone()).collect();
+//! assert_eq!(items, vec!["flour", "milk", "eggs"]);
+//! ```
+
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
+use std::ops::Range;
+
+use traits::{Causal, CvRDT, CmRDT};
+use vclock::{Dot, VClock, Actor};
+use ctx::{ReadCtx, ReadCtxRef, AddCtx};
+use map::Val;
+use canonical::{self, Canonical};
+
+/// `List` is an ordered sequence of nested CRDT's.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct List<V: Val<A>, A: Actor> {
+    clock: VClock<A>,
+    // the greatest sequence number of the elements
+    seq: u64,
+    // every element ever inserted in list order, including tombstones
+    elems: Vec<Elem<V, A>>
+}
+
+/// Identifies an element of a `List`: the dot of the insert that
+/// created it and its offset among the elements of that insert.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ElemId<A: Actor> {
+    /// Dot of the insert
+    pub dot: Dot<A>,
+    /// Offset of the element in the insert
+    pub offset: u32
+}
+
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Elem<V: Val<A>, A: Actor> {
+    id: ElemId<A>,
+    seq: u64,
+    // the element this one was inserted after
+    after: Option<ElemId<A>>,
+    removed: bool,
+    val: V
+}
+
+/// Op's define an edit to a `List`
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op<V: Val<A>, A: Actor> {
+    /// Insert consecutive elements, each initialized by an op applied to
+    /// V::default()
+    Insert {
+        /// Insert witnessing dot
+        dot: Dot<A>,
+        /// Sequence number of the first element
+        seq: u64,
+        /// The element to insert after, None inserts at the front
+        after: Option<ElemId<A>>,
+        /// The ops initializing the new elements
+        ops: Vec<V::Op>
+    },
+    /// Update an element
+    Up {
+        /// Update witnessing dot
+        dot: Dot<A>,
+        /// Element to update
+        id: ElemId<A>,
+        /// The operation to apply on the element
+        op: V::Op
+    },
+    /// Remove elements
+    Rm {
+        /// Elements to remove
+        ids: Vec<ElemId<A>>
+    }
+}
+
+impl<V: Val<A>, A: Actor> Elem<V, A> {
+    /// Concurrent inserts after the same element are ordered by this key,
+    /// greatest first.
+    fn key(&self) -> (u64, &A, u64) {
+        (self.seq, &self.id.dot.actor, self.id.dot.counter)
+    }
+}
+
+impl<V: Val<A>, A: Actor> Default for List<V, A> {
+    fn default() -> Self {
+        List::new()
+    }
+}
+
+impl<V: Val<A>, A: Actor> CmRDT for List<V, A> {
+    type Op = Op<V, A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        match op {
+            Op::Insert { dot, seq, after, ops } => {
+                if self.clock.get(&dot.actor) >= dot.counter {
+                    // we've already seen this op
+                    return;
+                }
+                if let Some(ref after) = after {
+                    if self.index_of(after).is_none() && !seen(&self.clock, &after.dot) {
+                        // we haven't seen the anchor yet
+                        return;
+                    }
+                }
+
+                let mut after = after.clone();
+                for (offset, op) in ops.iter().enumerate() {
+                    let id = ElemId { dot: dot.clone(), offset: offset as u32 };
+                    let mut val = V::default();
+                    val.apply(op);
+                    self.integrate(Elem {
+                        id: id.clone(),
+                        seq: seq + offset as u64,
+                        after,
+                        removed: false,
+                        val
+                    });
+                    after = Some(id);
+                }
+                self.clock.apply(dot);
+                self.seq = cmp::max(self.seq, (seq + ops.len() as u64).saturating_sub(1));
+            },
+            Op::Up { dot, id, op } => {
+                if self.clock.get(&dot.actor) >= dot.counter {
+                    // we've already seen this op
+                    return;
+                }
+                let index = match self.index_of(id) {
+                    Some(index) => index,
+                    None => return // we haven't seen the element yet
+                };
+                self.elems[index].val.apply(op);
+                self.clock.apply(dot);
+            },
+            Op::Rm { ids } => {
+                for id in ids.iter() {
+                    if let Some(index) = self.index_of(id) {
+                        self.elems[index].removed = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<V: Val<A>, A: Actor> CvRDT for List<V, A> {
+    fn merge(&mut self, other: &Self) {
+        let mut other_index: HashMap<&ElemId<A>, usize> = other.elems.iter()
+            .enumerate()
+            .map(|(index, elem)| (&elem.id, index))
+            .collect();
+
+        // an element a list has seen the insert of and doesn't hold was
+        // truncated, it's dropped from the other list too
+        let len = self.elems.len();
+        self.elems.retain(|elem| {
+            other_index.contains_key(&elem.id) || !seen(&other.clock, &elem.id.dot)
+        });
+        if self.elems.len() < len {
+            self.reorder();
+        }
+
+        for elem in self.elems.iter_mut() {
+            if let Some(index) = other_index.remove(&elem.id) {
+                let other_elem = &other.elems[index];
+                elem.removed |= other_elem.removed;
+                elem.val.merge(&other_elem.val);
+            }
+        }
+
+        // other's elements come after the elements they were inserted
+        // after, so every anchor is integrated before its elements.
+        for other_elem in other.elems.iter() {
+            let novel = !seen(&self.clock, &other_elem.id.dot);
+            if novel && other_index.contains_key(&other_elem.id) {
+                self.integrate(other_elem.clone());
+            }
+        }
+
+        self.clock.merge(&other.clock);
+        self.seq = cmp::max(self.seq, other.seq);
+    }
+}
+
+impl<V: Val<A>, A: Actor> Causal<A> for List<V, A> {
+    /// Drops the elements inserted by the edits in the clock, the
+    /// elements inserted after them move to the front of the list.
+    fn truncate(&mut self, clock: &VClock<A>) {
+        let len = self.elems.len();
+        self.elems.retain(|elem| !seen(clock, &elem.id.dot));
+        for elem in self.elems.iter_mut() {
+            elem.val.truncate(clock);
+        }
+        self.clock.merge(clock);
+        if self.elems.len() < len {
+            self.reorder();
+        }
+    }
+}
+
+impl<V: Val<A> + Canonical, A: Actor> Canonical for List<V, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.clock.encode_canonical(buf);
+        canonical::encode(&self.seq, buf);
+
+        // the elements are kept in list order, which every replica agrees on
+        canonical::encode(&(self.elems.len() as u64), buf);
+        for elem in self.elems.iter() {
+            canonical::encode(&elem.id, buf);
+            canonical::encode(&elem.seq, buf);
+            canonical::encode(&elem.after, buf);
+            canonical::encode(&elem.removed, buf);
+            elem.val.encode_canonical(buf);
+        }
+    }
+}
+
+impl<V: Val<A>, A: Actor> List<V, A> {
+    /// Constructs an empty list
+    pub fn new() -> Self {
+        List {
+            clock: VClock::new(),
+            seq: 0,
+            elems: Vec::new()
+        }
+    }
+
+    /// Returns the number of elements in the list
+    pub fn len(&self) -> ReadCtx<usize, A> {
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: self.clock.clone(),
+            val: self.visible().count()
+        }
+    }
+
+    /// Retrieve the element at index
+    pub fn get(&self, index: usize) -> ReadCtx<Option<V>, A> {
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: self.clock.clone(),
+            val: self.visible().nth(index).map(|elem| elem.val.clone())
+        }
+    }
+
+    /// Returns an iterator over the elements of the list.
+    /// The ReadCtxRef covers the whole traversal.
+    pub fn iter(&self) -> ReadCtxRef<'_, impl Iterator<Item=&V>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.visible().map(|elem| &elem.val)
+        }
+    }
+
+    /// Insert an element at index, the updater is given V::default().
+    /// An index past the end of the list appends the element.
+    pub fn insert<F>(&self, index: usize, ctx: AddCtx<A>, f: F) -> Op<V, A>
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op
+    {
+        let op = f(&V::default(), ctx.clone());
+        self.insert_all(index, ctx, vec![op])
+    }
+
+    /// Insert consecutive elements at index, each initialized by one of
+    /// the ops. The ops should be built with the same ctx.
+    pub fn insert_all(&self, index: usize, ctx: AddCtx<A>, ops: Vec<V::Op>) -> Op<V, A> {
+        let after = if index == 0 {
+            None
+        } else {
+            self.visible()
+                .take(index)
+                .last()
+                .map(|elem| elem.id.clone())
+        };
+        Op::Insert { dot: ctx.dot, seq: self.seq + 1, after, ops }
+    }
+
+    /// Update the element at index, None if there's no such element.
+    pub fn update<F>(&self, index: usize, ctx: AddCtx<A>, f: F) -> Option<Op<V, A>>
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op
+    {
+        let elem = self.visible().nth(index)?;
+        Some(Op::Up {
+            dot: ctx.dot.clone(),
+            id: elem.id.clone(),
+            op: f(&elem.val, ctx)
+        })
+    }
+
+    /// Remove the element at index, None if there's no such element.
+    pub fn remove(&self, index: usize) -> Option<Op<V, A>> {
+        let elem = self.visible().nth(index)?;
+        Some(Op::Rm { ids: vec![elem.id.clone()] })
+    }
+
+    /// Remove the elements in the range of indices
+    pub fn remove_range(&self, range: Range<usize>) -> Op<V, A> {
+        let ids = self.visible()
+            .skip(range.start)
+            .take(range.end.saturating_sub(range.start))
+            .map(|elem| elem.id.clone())
+            .collect();
+        Op::Rm { ids }
+    }
+
+    fn visible(&self) -> impl Iterator<Item=&Elem<V, A>> {
+        self.elems.iter().filter(|elem| !elem.removed)
+    }
+
+    fn index_of(&self, id: &ElemId<A>) -> Option<usize> {
+        self.elems.iter().position(|elem| &elem.id == id)
+    }
+
+    /// Place the elements again, once some were dropped. Elements are
+    /// numbered past their anchors, so they're placed in key order.
+    fn reorder(&mut self) {
+        let mut elems = mem::take(&mut self.elems);
+        elems.sort_by(|a, b| a.key().cmp(&b.key()));
+        for elem in elems {
+            self.integrate(elem);
+        }
+    }
+
+    /// Place a new element right after its anchor, skipping the elements
+    /// inserted after the anchor with greater keys. An element whose
+    /// anchor was truncated is placed at the front.
+    fn integrate(&mut self, elem: Elem<V, A>) {
+        let mut index = match elem.after {
+            Some(ref after) => self.index_of(after).map(|index| index + 1).unwrap_or(0),
+            None => 0
+        };
+        while index < self.elems.len() && self.elems[index].key() > elem.key() {
+            index += 1;
+        }
+        self.elems.insert(index, elem);
+    }
+}
+
+/// Whether the clock has seen the edit of the dot
+fn seen<A: Actor>(clock: &VClock<A>, dot: &Dot<A>) -> bool {
+    clock.get(&dot.actor) >= dot.counter
+}
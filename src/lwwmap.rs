@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use error::{self, Error, Result};
+use canonical::{self, Canonical};
+use traits::{FunkyCvRDT, FunkyCmRDT};
+use lwwreg::{Val, Marker};
+use lwwset::Bias;
+use map::Key;
+
+/// `LWWMap` is a last-writer-wins map, the entry under every key is the
+/// one written with the greatest marker.
+///
+/// Markers must grow monotonically and two different writes to the same
+/// key must not share a marker, see `LWWReg`. A put and a remove with the
+/// same marker are resolved by the map's bias. Removed entries are kept as
+/// tombstones holding the marker of the remove, along with any value put
+/// under that same marker so a later put of a different value is still
+/// caught.
+///
+/// ```
+/// use crdts::{LWWMap, FunkyCmRDT, Error};
+///
+/// let mut map: LWWMap<String, u8, u64> = LWWMap::new();
+/// map.apply(&map.put("a", 1, 1)).unwrap();
+/// map.apply(&map.put("a", 2, 3)).unwrap();
+/// map.apply(&map.rm("a", 2)).unwrap();
+/// assert_eq!(map.get(&"a".into()), Some(&2));
+///
+/// // two different values written with the same marker
+/// assert_eq!(map.apply(&map.put("a", 4, 3)), Err(Error::ConflictingMarker));
+/// ```
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LWWMap<K: Key, V: Val, Mk: Marker> {
+    bias: Bias,
+    // the greatest marker written under every key, the value put with it
+    // and whether it was also used to remove the key
+    entries: BTreeMap<K, (Mk, Option<V>, bool)>
+}
+
+/// Op's that put or remove an entry of an `LWWMap`
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op<K: Key, V: Val, Mk: Marker> {
+    /// Put a value under a key
+    Put {
+        /// Key of the entry
+        key: K,
+        /// Value to put
+        val: V,
+        /// Marker of the put
+        marker: Mk
+    },
+    /// Remove the entry under a key
+    Rm {
+        /// Key of the entry
+        key: K,
+        /// Marker of the remove
+        marker: Mk
+    }
+}
+
+impl<K: Key, V: Val, Mk: Marker> Default for LWWMap<K, V, Mk> {
+    fn default() -> Self {
+        LWWMap::new()
+    }
+}
+
+impl<K: Key, V: Val, Mk: Marker> FunkyCmRDT for LWWMap<K, V, Mk> {
+    type Error = error::Error;
+    type Op = Op<K, V, Mk>;
+
+    /// Fails with `ConflictingMarker` if the op puts a value under the
+    /// marker of a different value, the map is left untouched.
+    fn apply(&mut self, op: &Self::Op) -> Result<()> {
+        match op {
+            Op::Put { key, val, marker } => {
+                self.check(key, marker, &Some(val.clone()))?;
+                self.write(key, marker, Some(val.clone()), false);
+            },
+            Op::Rm { key, marker } => self.write(key, marker, None, true)
+        }
+        Ok(())
+    }
+}
+
+impl<K: Key, V: Val, Mk: Marker> FunkyCvRDT for LWWMap<K, V, Mk> {
+    type Error = error::Error;
+
+    /// Fails with `ConflictingMarker` if both maps hold different values
+    /// written with the same marker, or with `MergeConflict` if the maps
+    /// break ties with different biases. The map is left untouched on
+    /// failure.
+    fn merge(&mut self, other: &Self) -> Result<()> {
+        if self.bias != other.bias {
+            return Err(Error::MergeConflict);
+        }
+        for (key, (marker, val, _)) in other.entries.iter() {
+            self.check(key, marker, val)?;
+        }
+        for (key, (marker, val, removed)) in other.entries.iter() {
+            self.write(key, marker, val.clone(), *removed);
+        }
+        Ok(())
+    }
+}
+
+impl<K: Key, V: Val, Mk: Marker> Canonical for LWWMap<K, V, Mk> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        // entries are kept in a BTreeMap, so they're already ordered
+        canonical::encode(self, buf)
+    }
+}
+
+impl<K: Key, V: Val, Mk: Marker> LWWMap<K, V, Mk> {
+    /// Returns a new map where puts win ties with removes.
+    pub fn new() -> Self {
+        LWWMap::with_bias(Bias::Add)
+    }
+
+    /// Returns a new map breaking ties between puts and removes with the
+    /// given bias. All replicas of a map must use the same bias.
+    pub fn with_bias(bias: Bias) -> Self {
+        LWWMap { bias, entries: BTreeMap::new() }
+    }
+
+    /// The bias used to break ties
+    pub fn bias(&self) -> Bias {
+        self.bias
+    }
+
+    /// Returns the op putting a value under key with the given marker.
+    pub fn put(&self, key: impl Into<K>, val: impl Into<V>, marker: Mk) -> Op<K, V, Mk> {
+        Op::Put { key: key.into(), val: val.into(), marker }
+    }
+
+    /// Returns the op removing the entry under key with the given marker.
+    pub fn rm(&self, key: impl Into<K>, marker: Mk) -> Op<K, V, Mk> {
+        Op::Rm { key: key.into(), marker }
+    }
+
+    /// Returns the value under key
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).and_then(|entry| self.visible(entry))
+    }
+
+    /// Returns the number of entries in the map
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns true if the map has no entries
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Iterate over the entries of the map, ordered by key
+    pub fn iter(&self) -> impl Iterator<Item=(&K, &V)> {
+        self.entries.iter()
+            .filter_map(move |(key, entry)| self.visible(entry).map(|val| (key, val)))
+    }
+
+    /// The value of an entry, unless a remove under its marker wins.
+    fn visible<'a>(&self, entry: &'a (Mk, Option<V>, bool)) -> Option<&'a V> {
+        let (_, val, removed) = entry;
+        if *removed && self.bias == Bias::Rm {
+            None
+        } else {
+            val.as_ref()
+        }
+    }
+
+    /// Check that a write doesn't reuse the marker of a different value.
+    fn check(&self, key: &K, marker: &Mk, val: &Option<V>) -> Result<()> {
+        match (self.entries.get(key), val) {
+            (Some((existing_marker, Some(existing), _)), Some(val)) => {
+                if existing_marker == marker && existing != val {
+                    Err(Error::ConflictingMarker)
+                } else {
+                    Ok(())
+                }
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Record a write of key, keeping it if its marker wins.
+    fn write(&mut self, key: &K, marker: &Mk, val: Option<V>, removed: bool) {
+        let entry = self.entries.entry(key.clone())
+            .or_insert_with(|| (marker.clone(), None, false));
+        if *marker > entry.0 {
+            *entry = (marker.clone(), val, removed);
+        } else if *marker == entry.0 {
+            // a put and a remove tie, the bias picks one when reading
+            if val.is_some() {
+                entry.1 = val;
+            }
+            entry.2 |= removed;
+        }
+    }
+}
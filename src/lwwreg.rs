@@ -7,6 +7,7 @@ use serde::de::DeserializeOwned;
 
 use error::{self, Error, Result};
 use traits::{FunkyCvRDT, FunkyCmRDT};
+use canonical::{self, Canonical};
 
 /// Trait bound alias for lwwreg vals
 pub trait Val: Debug + Clone + PartialEq + Send + Serialize + DeserializeOwned {}
@@ -76,6 +77,12 @@ impl<V: Val, M: Marker> FunkyCmRDT for LWWReg<V, M> {
     }
 }
 
+impl<V: Val, M: Marker> Canonical for LWWReg<V, M> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        canonical::encode(self, buf)
+    }
+}
+
 impl<V: Val, M: Marker> LWWReg<V, M> {
     /// Updates value witnessed by the given marker.
     /// An Err is returned if the given marker is exactly
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+
+use error::{self, Error, Result};
+use canonical::{self, Canonical};
+use traits::{FunkyCvRDT, FunkyCmRDT};
+use lwwreg::Marker;
+use orswot::Member;
+
+/// Which side wins when an add and a remove carry the same marker
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Bias {
+    /// Adds win ties
+    #[default]
+    Add,
+    /// Removes win ties
+    Rm
+}
+
+/// `LWWSet` is a last-writer-wins element set, every member is added or
+/// removed by the write with the greatest marker.
+///
+/// Markers must grow monotonically, see `LWWReg`. Removed members are
+/// kept as tombstones holding the marker of the remove, so a stale add
+/// can't bring them back.
+///
+/// Unlike `LWWMap`, the set never reports `ConflictingMarker`: a write
+/// only carries whether the member is in the set, so two writes with the
+/// same marker are either the same write or an add and a remove, and the
+/// bias settles every add and remove.
+///
+/// ```
+/// use crdts::{LWWSet, FunkyCmRDT};
+/// use crdts::lwwset::Bias;
+///
+/// let mut set: LWWSet<String, u64> = LWWSet::new();
+/// let op = set.add("apple", 1);
+/// set.apply(&op).unwrap();
+///
+/// // a stale remove is ignored
+/// let op = set.rm("apple", 0);
+/// set.apply(&op).unwrap();
+/// assert!(set.contains(&"apple".into()));
+///
+/// // with a remove bias, a remove wins an add with the same marker
+/// let mut set: LWWSet<String, u64> = LWWSet::with_bias(Bias::Rm);
+/// set.apply(&set.add("apple", 1)).unwrap();
+/// set.apply(&set.rm("apple", 1)).unwrap();
+/// assert!(!set.contains(&"apple".into()));
+/// ```
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LWWSet<M: Member, Mk: Marker> {
+    bias: Bias,
+    // the latest marker of every member, along with whether it's in the set
+    entries: HashMap<M, (Mk, bool)>
+}
+
+/// Op's that add or remove a member of an `LWWSet`
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op<M: Member, Mk: Marker> {
+    /// Add a member to the set
+    Add {
+        /// Member to add
+        member: M,
+        /// Marker of the add
+        marker: Mk
+    },
+    /// Remove a member from the set
+    Rm {
+        /// Member to remove
+        member: M,
+        /// Marker of the remove
+        marker: Mk
+    }
+}
+
+impl<M: Member, Mk: Marker> Default for LWWSet<M, Mk> {
+    fn default() -> Self {
+        LWWSet::new()
+    }
+}
+
+impl<M: Member, Mk: Marker> FunkyCmRDT for LWWSet<M, Mk> {
+    type Error = error::Error;
+    type Op = Op<M, Mk>;
+
+    /// Ties between adds and removes are broken by the bias, applying an
+    /// op never fails, there's no write the bias doesn't settle.
+    fn apply(&mut self, op: &Self::Op) -> Result<()> {
+        match op {
+            Op::Add { member, marker } => self.write(member, marker, true),
+            Op::Rm { member, marker } => self.write(member, marker, false)
+        }
+        Ok(())
+    }
+}
+
+impl<M: Member, Mk: Marker> FunkyCvRDT for LWWSet<M, Mk> {
+    type Error = error::Error;
+
+    /// Merge fails with `MergeConflict` if the sets break ties with
+    /// different biases, they would never converge.
+    fn merge(&mut self, other: &Self) -> Result<()> {
+        if self.bias != other.bias {
+            return Err(Error::MergeConflict);
+        }
+        for (member, (marker, present)) in other.entries.iter() {
+            self.write(member, marker, *present);
+        }
+        Ok(())
+    }
+}
+
+impl<M: Member, Mk: Marker> Canonical for LWWSet<M, Mk> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        canonical::encode(&self.bias, buf);
+
+        let entries = self.entries.iter()
+            .map(|(member, state)| {
+                let mut elem = Vec::new();
+                canonical::encode(member, &mut elem);
+                canonical::encode(state, &mut elem);
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(entries, buf);
+    }
+}
+
+impl<M: Member, Mk: Marker> LWWSet<M, Mk> {
+    /// Returns a new set where adds win ties.
+    pub fn new() -> Self {
+        LWWSet::with_bias(Bias::Add)
+    }
+
+    /// Returns a new set breaking ties with the given bias. All replicas
+    /// of a set must use the same bias.
+    pub fn with_bias(bias: Bias) -> Self {
+        LWWSet { bias, entries: HashMap::new() }
+    }
+
+    /// The bias used to break ties
+    pub fn bias(&self) -> Bias {
+        self.bias
+    }
+
+    /// Returns the op adding a member with the given marker.
+    pub fn add(&self, member: impl Into<M>, marker: Mk) -> Op<M, Mk> {
+        Op::Add { member: member.into(), marker }
+    }
+
+    /// Returns the op removing a member with the given marker.
+    pub fn rm(&self, member: impl Into<M>, marker: Mk) -> Op<M, Mk> {
+        Op::Rm { member: member.into(), marker }
+    }
+
+    /// Returns true if the member is in the set
+    pub fn contains(&self, member: &M) -> bool {
+        self.entries.get(member).map(|(_, present)| *present).unwrap_or(false)
+    }
+
+    /// The members of the set
+    pub fn value(&self) -> HashSet<M> {
+        self.iter().cloned().collect()
+    }
+
+    /// Iterate over the members of the set
+    pub fn iter(&self) -> impl Iterator<Item=&M> {
+        self.entries.iter()
+            .filter(|(_, (_, present))| *present)
+            .map(|(member, _)| member)
+    }
+
+    /// Record a write of member, keeping it if its marker wins.
+    fn write(&mut self, member: &M, marker: &Mk, present: bool) {
+        let bias = self.bias;
+        let entry = self.entries.entry(member.clone())
+            .or_insert_with(|| (marker.clone(), present));
+        if *marker > entry.0 {
+            *entry = (marker.clone(), present);
+        } else if *marker == entry.0 && present != entry.1 {
+            entry.1 = bias == Bias::Add;
+        }
+    }
+}
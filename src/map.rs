@@ -1,12 +1,20 @@
+use std::borrow::Cow;
+use std::cmp;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
+use std::mem;
+use std::ops::RangeBounds;
 
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
-use traits::{Causal, CvRDT, CmRDT};
+use traits::{Causal, CausalOp, CvRDT, CmRDT, Diff, Undo};
+use canonical::{self, Canonical};
 use vclock::{Dot, VClock, Actor};
-use ctx::{ReadCtx, AddCtx, RmCtx};
+use ctx::{ReadCtx, ReadCtxRef, AddCtx, RmCtx};
+use deferred::DeferredIndex;
+use merkle::{self, Merkle, MerkleTree, Delta};
+use error::{Error, Result};
 
 /// Key Trait alias to reduce redundancy in type decl.
 pub trait Key: Debug + Ord + Clone + Send + Serialize + DeserializeOwned {}
@@ -78,6 +86,9 @@ impl<A, T> Val<A> for T where
 ///     .map(|set| set.value().val);
 /// assert_eq!(alice_friends, Some(vec!["clyde".into()].into_iter().collect()));
 /// ```
+///
+/// If concurrent edits should keep all of the entries contents, see `ORMap`
+/// which accepts the same `Op`'s.
 #[serde(bound(deserialize = ""))]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Map<K: Key, V: Val<A>, A: Actor> {
@@ -85,7 +96,9 @@ pub struct Map<K: Key, V: Val<A>, A: Actor> {
     // be greator or equal to all Entry.clock's in the Map.
     clock: VClock<A>,
     entries: BTreeMap<K, Entry<V, A>>,
-    deferred: HashMap<VClock<A>, BTreeSet<K>>
+    deferred: HashMap<VClock<A>, BTreeSet<K>>,
+    #[serde(skip)]
+    deferred_index: DeferredIndex<A, K>
 }
 
 #[serde(bound(deserialize = ""))]
@@ -98,6 +111,45 @@ struct Entry<V: Val<A>, A: Actor> {
     val: V
 }
 
+/// The entries inserted, removed and updated between two states of a `Map`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes<K: Key, C> {
+    /// Keys that weren't in the map before
+    pub inserted: BTreeSet<K>,
+    /// Keys that are no longer in the map
+    pub removed: BTreeSet<K>,
+    /// Keys in both states along with the changes to their values
+    pub updated: BTreeMap<K, C>
+}
+
+/// Describes how to undo an op on a `Map`
+#[derive(Debug, Clone)]
+pub enum Inverse<K: Key, V: Val<A> + Undo<A>, A: Actor> {
+    /// Remove the edits of an entry witnessed by the clock
+    Rm {
+        /// Clock of the edits to remove
+        clock: VClock<A>,
+        /// Key of the entry
+        key: K
+    },
+    /// Undo an edit of the value under key
+    Up {
+        /// Key of the entry
+        key: K,
+        /// How to undo the edit of the value
+        inverse: V::Inverse
+    },
+    /// Add a removed entry back
+    Restore {
+        /// Key of the entry
+        key: K,
+        /// The value of the entry when it was removed
+        val: V
+    },
+    /// Undo the edits of a batch
+    Batch(Vec<Inverse<K, V, A>>)
+}
+
 /// Operations which can be applied to the Map CRDT
 #[serde(bound(deserialize = ""))]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -119,6 +171,196 @@ pub enum Op<K: Key, V: Val<A>, A: Actor> {
         key: K,
         /// The operation to apply on the value under `key`
         op: V::Op
+    },
+    /// Atomically update and remove many entries, the whole batch is
+    /// witnessed by a single dot. Removes are applied before updates.
+    Batch {
+        /// Actors version at the time of the batch
+        dot: Dot<A>,
+        /// Keys to update along with the operation to apply on their values
+        updates: Vec<(K, V::Op)>,
+        /// Keys to remove along with the clock under which we remove them
+        rms: Vec<(K, VClock<A>)>
+    }
+}
+
+/// Builds an `Op::Batch` for a `Map`, every update and remove in the
+/// batch is witnessed by the dot of a single AddCtx. A key can only be
+/// updated once per batch, the nested ops would share the batch's dot.
+///
+/// ``` rust
+/// use crdts::{Map, MVReg, CmRDT};
+///
+/// let mut m: Map<String, MVReg<u8, u8>, u8> = Map::new();
+/// let op = m.batch(m.len().derive_add_ctx(1))
+///     .update("a", |reg, ctx| reg.set(1, ctx))
+///     .update("b", |reg, ctx| reg.set(2, ctx))
+///     .build()
+///     .unwrap();
+/// m.apply(&op);
+/// assert_eq!(m.len().val, 2);
+///
+/// let op = m.batch(m.len().derive_add_ctx(1))
+///     .rm("a", m.get(&"a".to_string()).derive_rm_ctx())
+///     .update("c", |reg, ctx| reg.set(3, ctx))
+///     .build()
+///     .unwrap();
+/// m.apply(&op);
+/// assert_eq!(m.keys().val.cloned().collect::<Vec<_>>(), vec!["b", "c"]);
+///
+/// // the second update would be dropped as already seen
+/// let res = m.batch(m.len().derive_add_ctx(1))
+///     .update("a", |reg, ctx| reg.set(4, ctx))
+///     .update("a", |reg, ctx| reg.set(5, ctx))
+///     .build();
+/// assert!(res.is_err());
+/// ```
+pub struct BatchBuilder<'a, K: Key + 'a, V: Val<A> + 'a, A: Actor + 'a> {
+    map: &'a Map<K, V, A>,
+    ctx: AddCtx<A>,
+    updates: Vec<(K, V::Op)>,
+    rms: Vec<(K, VClock<A>)>,
+    // set when a key is updated more than once
+    repeated: bool
+}
+
+impl<'a, K: Key, V: Val<A>, A: Actor> BatchBuilder<'a, K, V, A> {
+    /// Update a value under some key, the updater is given the current
+    /// value or V::default() if the key is not present.
+    /// Updating a key that's already updated by the batch makes `build`
+    /// fail.
+    pub fn update<F, I>(mut self, key: I, f: F) -> Self
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op,
+              I: Into<K>
+    {
+        let key = key.into();
+        if self.updates.iter().any(|(updated, _)| updated == &key) {
+            self.repeated = true;
+            return self;
+        }
+        let op = if let Some(entry) = self.map.entries.get(&key) {
+            f(&entry.val, self.ctx.clone())
+        } else {
+            f(&V::default(), self.ctx.clone())
+        };
+        self.updates.push((key, op));
+        self
+    }
+
+    /// Remove an entry as part of the batch
+    pub fn rm(mut self, key: impl Into<K>, ctx: RmCtx<A>) -> Self {
+        self.rms.push((key.into(), ctx.clock));
+        self
+    }
+
+    /// Produces the batch op, fails with `Error::ConflictingMarker` if a
+    /// key was updated more than once.
+    pub fn build(self) -> Result<Op<K, V, A>> {
+        if self.repeated {
+            return Err(Error::ConflictingMarker);
+        }
+        Ok(Op::Batch { dot: self.ctx.dot, updates: self.updates, rms: self.rms })
+    }
+}
+
+/// A `Path` addresses a value nested arbitrarily deep inside a `Map`,
+/// it's built with `Map::at` and extended with `Path::at` for every level
+/// of nesting. An update at the end of the path is wrapped into a single
+/// nested `Op` for the root map, every level shares the dot derived from
+/// the root map's clock.
+///
+/// ``` rust
+/// use crdts::{Map, Orswot, CmRDT};
+///
+/// type Groups = Map<String, Map<String, Orswot<String, u8>, u8>, u8>;
+/// let mut groups: Groups = Map::new();
+///
+/// let op = groups.at("eng").at("rust").add("alice", 1);
+/// groups.apply(&op);
+///
+/// let op = groups.at("eng").at("rust").update(2, |set, ctx| set.add("bob", ctx));
+/// groups.apply(&op);
+///
+/// let read_ctx = groups.at("eng").at("rust").read();
+/// let members = read_ctx.val.map(|set| set.value().val);
+/// assert_eq!(
+///     members,
+///     Some(vec!["alice".to_string(), "bob".to_string()].into_iter().collect())
+/// );
+///
+/// let op = groups.at("eng").rm("rust", 1);
+/// groups.apply(&op);
+/// assert_eq!(groups.at("eng").at("rust").read().val, None);
+/// ```
+pub struct Path<'a, V: Val<A> + 'a, A: Actor + 'a, O> {
+    // the clock of the root map, all add contexts are derived from it
+    add_clock: &'a VClock<A>,
+    // the clock of the entry at the end of the path
+    rm_clock: Cow<'a, VClock<A>>,
+    val: Option<&'a V>,
+    // wraps an op on the value at the end of the path into an op on the root
+    wrap: Box<dyn FnOnce(Dot<A>, V::Op) -> O + 'a>,
+    // builds the root's no-op, used when the path doesn't lead anywhere
+    nop: fn() -> O
+}
+
+impl<'a, V: Val<A>, A: Actor, O> Path<'a, V, A, O> {
+    /// Read the value at the end of the path, the add clock is the root
+    /// map's clock and the rm clock is the clock of the innermost entry.
+    pub fn read(&self) -> ReadCtxRef<'a, Option<&'a V>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(self.add_clock),
+            rm_clock: self.rm_clock.clone(),
+            val: self.val
+        }
+    }
+
+    /// Update the value at the end of the path, the updater is given the
+    /// current value or V::default() if some key along the path is missing.
+    pub fn update<F>(self, actor: A, f: F) -> O
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op
+    {
+        let ctx = self.read().derive_add_ctx(actor);
+        let op = if let Some(val) = self.val {
+            f(val, ctx.clone())
+        } else {
+            f(&V::default(), ctx.clone())
+        };
+        (self.wrap)(ctx.dot, op)
+    }
+}
+
+impl<'a, K: Key, V: Val<A>, A: Actor, O: 'a> Path<'a, Map<K, V, A>, A, O> {
+    /// Extend the path into the entry under key in the nested map.
+    pub fn at(self, key: impl Into<K>) -> Path<'a, V, A, O> {
+        let key = key.into();
+        let entry_opt = self.val.and_then(|map| map.entries.get(&key));
+        let wrap = self.wrap;
+        Path {
+            add_clock: self.add_clock,
+            rm_clock: entry_opt
+                .map(|entry| Cow::Borrowed(&entry.clock))
+                .unwrap_or_else(|| Cow::Owned(VClock::new())),
+            val: entry_opt.map(|entry| &entry.val),
+            wrap: Box::new(move |dot: Dot<A>, op| {
+                wrap(dot.clone(), Op::Up { dot, key, op })
+            }),
+            nop: self.nop
+        }
+    }
+
+    /// Remove the entry under key from the nested map, the edits to the
+    /// entry that are visible through this path are removed. If the entry
+    /// or some key along the path is missing, the root's no-op is returned.
+    pub fn rm(self, key: impl Into<K>, actor: A) -> O {
+        let key = key.into();
+        if !self.val.map(|map| map.entries.contains_key(&key)).unwrap_or(false) {
+            return (self.nop)();
+        }
+        self.update(actor, |map, _| {
+            let ctx = map.get_ref(&key).derive_rm_ctx();
+            map.rm(key, ctx)
+        })
     }
 }
 
@@ -128,6 +370,182 @@ impl<K: Key, V: Val<A>, A: Actor> Default for Map<K, V, A> {
     }
 }
 
+impl<K: Key, V: Val<A> + Diff, A: Actor> Diff for Map<K, V, A> {
+    type Changes = Changes<K, V::Changes>;
+
+    fn diff(&self, other: &Self) -> Option<Self::Changes> {
+        let mut changes = Changes {
+            inserted: BTreeSet::new(),
+            removed: BTreeSet::new(),
+            updated: BTreeMap::new()
+        };
+        for (key, entry) in other.entries.iter() {
+            match self.entries.get(key) {
+                Some(our_entry) => {
+                    if let Some(val_changes) = our_entry.val.diff(&entry.val) {
+                        changes.updated.insert(key.clone(), val_changes);
+                    }
+                },
+                None => {
+                    changes.inserted.insert(key.clone());
+                }
+            }
+        }
+        for key in self.entries.keys() {
+            if !other.entries.contains_key(key) {
+                changes.removed.insert(key.clone());
+            }
+        }
+
+        if changes.inserted.is_empty() && changes.removed.is_empty() && changes.updated.is_empty() {
+            None
+        } else {
+            Some(changes)
+        }
+    }
+}
+
+impl<K: Key, V: Val<A> + Undo<A>, A: Actor> Undo<A> for Map<K, V, A> {
+    type Inverse = Inverse<K, V, A>;
+
+    /// An update of a new entry is undone by removing the edits made by
+    /// the update and the later edits of the same actor, an update of an
+    /// existing entry by undoing the edit of its value.
+    ///
+    /// A remove of a whole entry is undone by adding the removed value
+    /// back with new edits, see `Undo::restore`. Removes that leave
+    /// concurrent edits of the entry behind, and removes of values that
+    /// can't be restored, aren't undone.
+    fn inverse(&self, op: &Op<K, V, A>) -> Option<Inverse<K, V, A>> {
+        match op {
+            Op::Nop => None,
+            Op::Rm { clock, key } => self.inverse_rm(key, clock),
+            Op::Up { dot, key, op } => {
+                if self.clock.get(&dot.actor) >= dot.counter {
+                    // we've seen this op already
+                    return None;
+                }
+                self.inverse_up(dot, key, op, false)
+            },
+            Op::Batch { dot, updates, rms } => {
+                if self.clock.get(&dot.actor) >= dot.counter {
+                    // we've seen this op already
+                    return None;
+                }
+                let mut inverses: Vec<_> = rms.iter()
+                    .filter_map(|(key, clock)| self.inverse_rm(key, clock))
+                    .collect();
+                inverses.extend(updates.iter().filter_map(|(key, op)| {
+                    let reset = rms.iter().any(|(rm_key, _)| rm_key == key);
+                    self.inverse_up(dot, key, op, reset)
+                }));
+                if inverses.is_empty() {
+                    None
+                } else {
+                    Some(Inverse::Batch(inverses))
+                }
+            }
+        }
+    }
+
+    fn undo(&self, inverse: &Inverse<K, V, A>, actor: A) -> Op<K, V, A> {
+        match inverse {
+            Inverse::Rm { clock, key } => {
+                Op::Rm { clock: self.undo_rm_clock(clock, key), key: key.clone() }
+            },
+            Inverse::Up { key, inverse } => {
+                let dot = self.clock.inc(actor.clone());
+                Op::Up { dot, key: key.clone(), op: self.undo_val(key, inverse, actor) }
+            },
+            Inverse::Restore { key, val } => {
+                let ctx = self.len().derive_add_ctx(actor);
+                match val.restore(ctx.clone()) {
+                    Some(op) => Op::Up { dot: ctx.dot, key: key.clone(), op },
+                    None => Op::Nop
+                }
+            },
+            Inverse::Batch(inverses) => {
+                let ctx = self.len().derive_add_ctx(actor.clone());
+                let dot = ctx.dot.clone();
+                let mut updates = Vec::new();
+                let mut rms = Vec::new();
+                for inverse in inverses.iter() {
+                    match inverse {
+                        Inverse::Rm { clock, key } => {
+                            rms.push((key.clone(), self.undo_rm_clock(clock, key)))
+                        },
+                        Inverse::Up { key, inverse } => {
+                            updates.push((key.clone(), self.undo_val(key, inverse, actor.clone())))
+                        },
+                        Inverse::Restore { key, val } => {
+                            if let Some(op) = val.restore(ctx.clone()) {
+                                updates.push((key.clone(), op))
+                            }
+                        },
+                        Inverse::Batch(_) => unreachable!("batches are never nested")
+                    }
+                }
+                Op::Batch { dot, updates, rms }
+            }
+        }
+    }
+
+    /// Every entry of the map has to be restorable.
+    fn restore(&self, ctx: AddCtx<A>) -> Option<Op<K, V, A>> {
+        let updates = self.entries.iter()
+            .map(|(key, entry)| Some((key.clone(), entry.val.restore(ctx.clone())?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Op::Batch { dot: ctx.dot, updates, rms: Vec::new() })
+    }
+}
+
+impl<K: Key, V: Val<A> + Undo<A>, A: Actor> Map<K, V, A> {
+    /// How to undo the update of key with the nested op, reset is set if
+    /// the entry is removed before the update is applied.
+    fn inverse_up(&self, dot: &Dot<A>, key: &K, op: &V::Op, reset: bool) -> Option<Inverse<K, V, A>> {
+        match self.entries.get(key) {
+            Some(entry) if !reset => {
+                entry.val.inverse(op).map(|inverse| Inverse::Up { key: key.clone(), inverse })
+            },
+            _ => Some(Inverse::Rm { clock: dot.clone().into(), key: key.clone() })
+        }
+    }
+
+    /// How to undo the remove of key, only removes of the whole entry
+    /// whose value can be restored are undone.
+    fn inverse_rm(&self, key: &K, clock: &VClock<A>) -> Option<Inverse<K, V, A>> {
+        let entry = self.entries.get(key)?;
+        if entry.clock > *clock || entry.clock.concurrent(clock) {
+            return None;
+        }
+        // the ctx is only used to check that the value can be restored
+        let (actor, _) = entry.clock.iter().next()?;
+        entry.val.restore(self.len().derive_add_ctx(actor.clone()))?;
+        Some(Inverse::Restore { key: key.clone(), val: entry.val.clone() })
+    }
+
+    /// Extend the clock of an undone update to the later edits of key
+    /// by the same actors.
+    fn undo_rm_clock(&self, clock: &VClock<A>, key: &K) -> VClock<A> {
+        match self.entries.get(key) {
+            Some(entry) => {
+                clock.iter()
+                    .map(|(actor, counter)| (actor.clone(), entry.clock.get(actor).max(*counter)))
+                    .collect()
+            },
+            None => clock.clone()
+        }
+    }
+
+    /// The nested op undoing an edit of the value under key
+    fn undo_val(&self, key: &K, inverse: &V::Inverse, actor: A) -> V::Op {
+        match self.entries.get(key) {
+            Some(entry) => entry.val.undo(inverse, actor),
+            None => V::default().undo(inverse, actor)
+        }
+    }
+}
+
 impl<K: Key, V: Val<A>, A: Actor> Causal<A> for Map<K, V, A> {
     fn truncate(&mut self, clock: &VClock<A>) {
         let mut to_remove: Vec<K> = Vec::new();
@@ -152,11 +570,141 @@ impl<K: Key, V: Val<A>, A: Actor> Causal<A> for Map<K, V, A> {
             }
         }
         self.deferred = deferred;
+        self.deferred_index.invalidate();
 
         self.clock.subtract(&clock);
     }
 }
 
+impl<K: Key, V: Val<A> + Canonical, A: Actor> Canonical for Map<K, V, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.clock.encode_canonical(buf);
+
+        // entries are kept in a BTreeMap, so they're already ordered
+        canonical::encode(&(self.entries.len() as u64), buf);
+        for (key, entry) in self.entries.iter() {
+            canonical::encode(key, buf);
+            entry.clock.encode_canonical(buf);
+            entry.val.encode_canonical(buf);
+        }
+
+        self.encode_deferred(buf);
+    }
+}
+
+impl<K: Key, V: Val<A> + Canonical, A: Actor> Merkle for Map<K, V, A> {
+    /// Splits the keys into `2^depth` ranges holding about as many keys
+    /// each.
+    fn merkle_tree(&self, depth: u8) -> MerkleTree {
+        let depth = cmp::min(depth, merkle::MAX_DEPTH);
+        let num_buckets = 1usize << depth;
+        let keys: Vec<&K> = self.entries.keys().collect();
+        let mut bounds = Vec::new();
+        let mut last = 0;
+        for bucket in 1..num_buckets {
+            let i = bucket * keys.len() / num_buckets;
+            if i > last {
+                bounds.push(keys[i].clone());
+                last = i;
+            }
+        }
+        self.range_tree(depth, &bounds)
+    }
+
+    /// Also fails with `Error::InvalidTree` if the bounds of the tree
+    /// aren't increasing keys of this map.
+    fn merkle_tree_like(&self, tree: &MerkleTree) -> Result<MerkleTree> {
+        tree.check()?;
+        let bounds = Self::decode_bounds(tree.bounds())?;
+        Ok(self.range_tree(tree.depth(), &bounds))
+    }
+
+    fn delta(&self, tree: &MerkleTree, buckets: &[usize]) -> Result<Delta<Self>> {
+        tree.check()?;
+        let bounds = Self::decode_bounds(tree.bounds())?;
+        let buckets: BTreeSet<usize> = buckets.iter().cloned().collect();
+        let entries = self.entries.iter()
+            .filter(|(key, _)| buckets.contains(&merkle::range_bucket_of(&bounds, key)))
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+
+        Ok(Delta {
+            depth: tree.depth(),
+            bounds: tree.bounds().to_vec(),
+            buckets,
+            state: Map {
+                clock: self.clock.clone(),
+                entries,
+                deferred: self.deferred.clone(),
+                deferred_index: DeferredIndex::default()
+            }
+        })
+    }
+
+    fn merge_delta(&mut self, delta: &Delta<Self>) -> Result<()> {
+        delta.check()?;
+        let bounds = Self::decode_bounds(&delta.bounds)?;
+        self.merge_scoped(&delta.state, |key| {
+            delta.buckets.contains(&merkle::range_bucket_of(&bounds, key))
+        });
+        Ok(())
+    }
+}
+
+impl<K: Key, V: Val<A> + Canonical, A: Actor> Map<K, V, A> {
+    fn range_tree(&self, depth: u8, bounds: &[K]) -> MerkleTree {
+        let entries = self.entries.iter()
+            .map(|(key, entry)| {
+                let mut entry_buf = Vec::new();
+                canonical::encode(key, &mut entry_buf);
+                entry.clock.encode_canonical(&mut entry_buf);
+                entry.val.encode_canonical(&mut entry_buf);
+                (merkle::range_bucket_of(bounds, key), entry_buf)
+            });
+
+        let encoded_bounds = bounds.iter()
+            .map(|bound| {
+                let mut buf = Vec::new();
+                canonical::encode(bound, &mut buf);
+                buf
+            })
+            .collect();
+
+        let mut meta = Vec::new();
+        self.clock.encode_canonical(&mut meta);
+        self.encode_deferred(&mut meta);
+
+        MerkleTree::build_ranges(depth, encoded_bounds, entries, canonical::fnv1a(&meta))
+    }
+
+    fn decode_bounds(bounds: &[Vec<u8>]) -> Result<Vec<K>> {
+        let bounds: Vec<K> = bounds.iter()
+            .map(|bound| ::from_binary(bound.clone()).map_err(|_| Error::InvalidTree))
+            .collect::<Result<_>>()?;
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(Error::InvalidTree);
+        }
+        Ok(bounds)
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor> CausalOp<A> for Op<K, V, A> {
+    fn clock(&self) -> VClock<A> {
+        match self {
+            Op::Nop => VClock::new(),
+            Op::Rm { clock, .. } => clock.clone(),
+            Op::Up { dot, .. } => VClock::from(dot.clone()),
+            Op::Batch { dot, rms, .. } => {
+                let mut clock = VClock::from(dot.clone());
+                for (_, rm_clock) in rms.iter() {
+                    clock.merge(rm_clock);
+                }
+                clock
+            }
+        }
+    }
+}
+
 impl<K: Key, V: Val<A>, A: Actor> CmRDT for Map<K, V, A> {
     type Op = Op<K, V, A>;
 
@@ -172,18 +720,31 @@ impl<K: Key, V: Val<A>, A: Actor> CmRDT for Map<K, V, A> {
                     return;
                 }
 
-                let mut entry = self.entries.remove(&key)
-                    .unwrap_or_else(|| Entry {
-                        clock: VClock::new(),
-                        val: V::default()
-                    });
+                self.apply_up(&actor, counter, key.clone(), &op);
+                self.clock.witness(actor.clone(), counter);
+                self.apply_deferred_key(&key);
+                self.apply_unblocked(&Dot { actor, counter });
+            },
+            Op::Batch { dot: Dot { actor, counter }, updates, rms } => {
+                if self.clock.get(&actor) >= counter {
+                    // we've seen this batch already
+                    return;
+                }
 
-                entry.clock.witness(actor.clone(), counter);
-                entry.val.apply(&op);
-                self.entries.insert(key.clone(), entry);
+                for (key, clock) in rms {
+                    self.apply_rm(key, &clock);
+                }
+                let mut keys = Vec::new();
+                for (key, op) in updates {
+                    self.apply_up(&actor, counter, key.clone(), &op);
+                    keys.push(key);
+                }
 
-                self.clock.witness(actor, counter);
-                self.apply_deferred();
+                self.clock.witness(actor.clone(), counter);
+                for key in keys.iter() {
+                    self.apply_deferred_key(key);
+                }
+                self.apply_unblocked(&Dot { actor, counter });
             }
         }
     }
@@ -191,9 +752,199 @@ impl<K: Key, V: Val<A>, A: Actor> CmRDT for Map<K, V, A> {
 
 impl<K: Key, V: Val<A>, A: Actor> CvRDT for Map<K, V, A> {
     fn merge(&mut self, other: &Self) {
+        self.merge_scoped(other, |_| true)
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor> Map<K, V, A> {
+    /// Constructs an empty Map
+    pub fn new() -> Map<K, V, A> {
+        Map {
+            clock: VClock::new(),
+            entries: BTreeMap::new(),
+            deferred: HashMap::new(),
+            deferred_index: DeferredIndex::default()
+         }
+    }
+
+    /// Returns the number of entries in the Map
+    pub fn len(&self) -> ReadCtx<usize, A> {
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: self.clock.clone(),
+            val: self.entries.len()
+        }
+    }
+
+    /// Retrieve value stored under a key
+    pub fn get(&self, key: &K) -> ReadCtx<Option<V>, A> {
+        let add_clock = self.clock.clone();
+        let entry_opt = self.entries.get(&key);
+        ReadCtx {
+            add_clock: add_clock,
+            rm_clock: entry_opt
+                .map(|map_entry| map_entry.clock.clone())
+                .unwrap_or_else(|| VClock::new()),
+            val: entry_opt
+                .map(|map_entry| map_entry.val.clone())
+        }
+    }
+
+    /// Returns an iterator over the keys in the Map, ordered by key.
+    /// The ReadCtxRef covers the whole traversal.
+    ///
+    /// ``` rust
+    /// use crdts::{Map, MVReg, CmRDT};
+    ///
+    /// let mut m: Map<u8, MVReg<String, u8>, u8> = Map::new();
+    /// for key in vec![3, 1, 2] {
+    ///     let op = m.update(key, m.len().derive_add_ctx(7), |reg, ctx| reg.set("x", ctx));
+    ///     m.apply(&op);
+    /// }
+    /// assert_eq!(m.keys().val.cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+    /// ```
+    pub fn keys(&self) -> ReadCtxRef<'_, impl Iterator<Item=&K>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.entries.keys()
+        }
+    }
+
+    /// Returns an iterator over the values in the Map, ordered by key.
+    /// The ReadCtxRef covers the whole traversal.
+    pub fn values(&self) -> ReadCtxRef<'_, impl Iterator<Item=&V>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.entries.values().map(|entry| &entry.val)
+        }
+    }
+
+    /// Returns an iterator over the entries in the Map, ordered by key.
+    /// The ReadCtxRef covers the whole traversal.
+    pub fn iter(&self) -> ReadCtxRef<'_, impl Iterator<Item=(&K, &V)>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.entries.iter().map(|(key, entry)| (key, &entry.val))
+        }
+    }
+
+    /// Returns an iterator over the entries whose keys fall in the given range,
+    /// this is useful for paging through large Maps.
+    /// The ReadCtxRef covers the whole traversal.
+    ///
+    /// ``` rust
+    /// use crdts::{Map, MVReg, CmRDT};
+    ///
+    /// let mut m: Map<u8, MVReg<String, u8>, u8> = Map::new();
+    /// for key in 0..10 {
+    ///     let op = m.update(key, m.len().derive_add_ctx(7), |reg, ctx| reg.set("x", ctx));
+    ///     m.apply(&op);
+    /// }
+    /// let page: Vec<u8> = m.range(3..6).val.map(|(k, _)| *k).collect();
+    /// assert_eq!(page, vec![3, 4, 5]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R)
+        -> ReadCtxRef<'_, impl Iterator<Item=(&K, &V)>, A>
+    {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.entries.range(range).map(|(key, entry)| (key, &entry.val))
+        }
+    }
+
+    /// Retrieve a reference to the value stored under a key, unlike `get`
+    /// this doesn't clone the value or the clocks.
+    ///
+    /// ``` rust
+    /// use crdts::{Map, Orswot, CmRDT};
+    ///
+    /// let mut m: Map<u8, Orswot<String, u8>, u8> = Map::new();
+    /// let op = m.update(1, m.get_ref(&1).derive_add_ctx(7), |set, ctx| set.add("a", ctx));
+    /// m.apply(&op);
+    ///
+    /// let read_ctx = m.get_ref(&1);
+    /// assert!(read_ctx.val.unwrap().contains_ref(&"a".to_string()).val);
+    /// ```
+    pub fn get_ref(&self, key: &K) -> ReadCtxRef<'_, Option<&V>, A> {
+        let entry_opt = self.entries.get(key);
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: entry_opt
+                .map(|map_entry| Cow::Borrowed(&map_entry.clock))
+                .unwrap_or_else(|| Cow::Owned(VClock::new())),
+            val: entry_opt
+                .map(|map_entry| &map_entry.val)
+        }
+    }
+
+    /// Update a value under some key, if the key is not present in the map,
+    /// the updater will be given the result of V::default().
+    pub fn update<F, I>(&self, key: I, ctx: AddCtx<A>, f: F) -> Op<K, V, A>
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op,
+              I: Into<K>
+    {
+        let key = key.into();
+        let op = if let Some(entry) = self.entries.get(&key) {
+            f(&entry.val, ctx.clone())
+        } else {
+            f(&V::default(), ctx.clone())
+        };
+        Op::Up { dot: ctx.dot, key, op }
+    }
+
+    /// Remove an entry from the Map
+    pub fn rm(&self, key: impl Into<K>, ctx: RmCtx<A>) -> Op<K, V, A> {
+        Op::Rm { clock: ctx.clock, key: key.into() }
+    }
+
+    /// Address the value under key, see `Path` for building ops on
+    /// values nested deeper in the Map.
+    pub fn at(&self, key: impl Into<K>) -> Path<'_, V, A, Op<K, V, A>> {
+        let key = key.into();
+        let entry_opt = self.entries.get(&key);
+        Path {
+            add_clock: &self.clock,
+            rm_clock: entry_opt
+                .map(|entry| Cow::Borrowed(&entry.clock))
+                .unwrap_or_else(|| Cow::Owned(VClock::new())),
+            val: entry_opt.map(|entry| &entry.val),
+            wrap: Box::new(move |dot, op| Op::Up { dot, key, op }),
+            nop: || Op::Nop
+        }
+    }
+
+    /// Start building a batch of updates and removes that will be applied
+    /// atomically under the dot of the given ctx.
+    pub fn batch(&self, ctx: AddCtx<A>) -> BatchBuilder<'_, K, V, A> {
+        BatchBuilder {
+            map: self,
+            ctx,
+            updates: Vec::new(),
+            rms: Vec::new(),
+            repeated: false
+        }
+    }
+
+    /// Returns the number of removes waiting on dots this map hasn't seen.
+    pub fn deferred_len(&self) -> usize {
+        self.deferred.values().map(|keys| keys.len()).sum()
+    }
+
+    /// Merge other into self, only the entries that are in scope are
+    /// merged, the entries out of scope are left as they are.
+    fn merge_scoped<F: Fn(&K) -> bool>(&mut self, other: &Self, in_scope: F) {
         let mut other_remaining = other.entries.clone();
         let mut keep = BTreeMap::new();
         for (key, mut entry) in self.entries.clone().into_iter() {
+            if !in_scope(&key) {
+                // other may not hold this entry, leave it alone
+                keep.insert(key, entry);
+                continue;
+            }
             match other.entries.get(&key).cloned() {
                 None => {
                     // other doesn't contain this entry because it:
@@ -203,10 +954,10 @@ impl<K: Key, V: Val<A>, A: Actor> CvRDT for Map<K, V, A> {
                     if entry.clock.is_empty() {
                         // other has seen this entry and dropped it
                     } else {
-                        // the other map has not seen this entry, so add it
-                        let mut actors_who_have_deleted_this_entry = other.clock.clone();
-                        actors_who_have_deleted_this_entry.subtract(&entry.clock);
-                        entry.val.truncate(&actors_who_have_deleted_this_entry);
+                        // the other map has not seen this entry, so add it.
+                        // other holds none of the entry's edits, the ones
+                        // it has seen were removed by it.
+                        entry.val.truncate(&other.clock);
                         keep.insert(key, entry);
                     }
                 }
@@ -245,9 +996,7 @@ impl<K: Key, V: Val<A>, A: Actor> CvRDT for Map<K, V, A> {
             entry.clock.subtract(&self.clock);
             if !entry.clock.is_empty() {
                 // other has witnessed a novel addition, so add it
-                let mut actors_who_deleted_this_entry = self.clock.clone();
-                actors_who_deleted_this_entry.subtract(&entry.clock);
-                entry.val.truncate(&actors_who_deleted_this_entry);
+                entry.val.truncate(&self.clock);
                 keep.insert(key, entry);
             }
         }
@@ -255,7 +1004,7 @@ impl<K: Key, V: Val<A>, A: Actor> CvRDT for Map<K, V, A> {
         // merge deferred removals
         for (clock, deferred) in other.deferred.iter() {
             for key in deferred {
-                self.apply_rm(key.clone(), &clock);
+                self.apply_rm(key.clone(), clock);
             }
         }
 
@@ -266,78 +1015,93 @@ impl<K: Key, V: Val<A>, A: Actor> CvRDT for Map<K, V, A> {
 
         self.apply_deferred();
     }
-}
 
-impl<K: Key, V: Val<A>, A: Actor> Map<K, V, A> {
-    /// Constructs an empty Map
-    pub fn new() -> Map<K, V, A> {
-        Map {
-            clock: VClock::new(),
-            entries: BTreeMap::new(),
-            deferred: HashMap::new()
-         }
-    }
-
-    /// Returns the number of entries in the Map
-    pub fn len(&self) -> ReadCtx<usize, A> {
-        ReadCtx {
-            add_clock: self.clock.clone(),
-            rm_clock: self.clock.clone(),
-            val: self.entries.len()
+    /// Re-apply all deferred removes, used after the entries or the
+    /// deferred removes have been changed wholesale by a merge.
+    fn apply_deferred(&mut self) {
+        let deferred = mem::take(&mut self.deferred);
+        self.deferred_index.reset();
+        for (clock, keys) in deferred {
+            for key in keys {
+                self.apply_rm(key, &clock);
+            }
         }
     }
 
-    /// Retrieve value stored under a key
-    pub fn get(&self, key: &K) -> ReadCtx<Option<V>, A> {
-        let add_clock = self.clock.clone();
-        let entry_opt = self.entries.get(&key);
-        ReadCtx {
-            add_clock: add_clock,
-            rm_clock: entry_opt
-                .map(|map_entry| map_entry.clock.clone())
-                .unwrap_or_else(|| VClock::new()),
-            val: entry_opt
-                .map(|map_entry| map_entry.val.clone())
+    /// Re-apply the deferred removes of a key after its entry was updated.
+    fn apply_deferred_key(&mut self, key: &K) {
+        self.build_deferred_index();
+        for clock in self.deferred_index.keyed(key) {
+            self.apply_rm(key.clone(), &clock);
         }
     }
 
-    /// Update a value under some key, if the key is not present in the map,
-    /// the updater will be given the result of V::default().
-    pub fn update<F, I>(&self, key: I, ctx: AddCtx<A>, f: F) -> Op<K, V, A>
-        where F: FnOnce(&V, AddCtx<A>) -> V::Op,
-              I: Into<K>
-    {
-        let key = key.into();
-        let op = if let Some(entry) = self.entries.get(&key) {
-            f(&entry.val, ctx.clone())
-        } else {
-            f(&V::default(), ctx.clone())
-        };
-        Op::Up { dot: ctx.dot, key, op }
+    /// Apply the deferred removes that were only waiting on the given dot.
+    fn apply_unblocked(&mut self, dot: &Dot<A>) {
+        self.build_deferred_index();
+        for clock in self.deferred_index.unblocked(dot) {
+            if clock <= self.clock {
+                if let Some(keys) = self.deferred.remove(&clock) {
+                    for key in keys {
+                        self.deferred_index.remove_key(&key, &clock);
+                        self.apply_rm(key, &clock);
+                    }
+                }
+            }
+        }
     }
 
-    /// Remove an entry from the Map
-    pub fn rm(&self, key: impl Into<K>, ctx: RmCtx<A>) -> Op<K, V, A> {
-        Op::Rm { clock: ctx.clock, key: key.into() }
+    fn encode_deferred(&self, buf: &mut Vec<u8>) {
+        let deferred = self.deferred.iter()
+            .map(|(clock, keys)| {
+                let mut elem = Vec::new();
+                clock.encode_canonical(&mut elem);
+                canonical::encode(keys, &mut elem);
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(deferred, buf);
     }
 
-    /// apply the pending deferred removes 
-    fn apply_deferred(&mut self) {
-        let deferred = self.deferred.clone();
-        self.deferred = HashMap::new();
-        for (clock, keys) in deferred {
-            for key in keys {
-                self.apply_rm(key, &clock);
+    fn build_deferred_index(&mut self) {
+        if !self.deferred_index.is_built() {
+            self.deferred_index.reset();
+            for (clock, keys) in self.deferred.iter() {
+                self.deferred_index.insert(clock);
+                for key in keys {
+                    self.deferred_index.insert_key(key.clone(), clock);
+                }
             }
         }
     }
 
+    /// Apply a nested op to the entry under key, witnessed by the given dot.
+    fn apply_up(&mut self, actor: &A, counter: u64, key: K, op: &V::Op) {
+        let clock = &self.clock;
+        let mut entry = self.entries.remove(&key)
+            .unwrap_or_else(|| {
+                // the edits of the key seen so far were all removed
+                let mut val = V::default();
+                val.truncate(clock);
+                Entry { clock: VClock::new(), val }
+            });
+
+        entry.clock.witness(actor.clone(), counter);
+        entry.val.apply(op);
+        self.entries.insert(key, entry);
+    }
+
     /// Apply a key removal given a clock.
     fn apply_rm(&mut self, key: K, clock: &VClock<A>) {
         if !(clock <= &self.clock) {
-            let deferred_set = self.deferred.entry(clock.clone())
-                .or_insert_with(|| BTreeSet::new());
-            deferred_set.insert(key.clone());
+            self.build_deferred_index();
+            if !self.deferred.contains_key(clock) {
+                self.deferred_index.insert(clock);
+            }
+            self.deferred_index.insert_key(key.clone(), clock);
+            self.deferred.entry(clock.clone())
+                .or_insert_with(|| BTreeSet::new())
+                .insert(key.clone());
         }
 
         if let Some(mut existing_entry) = self.entries.remove(&key) {
@@ -0,0 +1,281 @@
+//! Merkle tree summaries for anti-entropy of large CRDT's.
+//!
+//! Exchanging the whole state of a large `Orswot` or `Map` to find out that
+//! only a handful of entries differ is wasteful. Instead, each replica
+//! splits its entries into `2^depth` buckets and builds a Merkle tree over
+//! the buckets. Replicas exchange trees, walk them to find the buckets that
+//! differ, and then only exchange the entries in those buckets.
+//!
+//! An `Orswot` hashes its members into buckets over the hash space of the
+//! members. A `Map` splits its keys into ranges over their order, the
+//! replica starting the exchange picks the ranges from its own keys and
+//! the other replica builds its tree over the same ranges with
+//! `Merkle::merkle_tree_like`.
+//!
+//! Trees and deltas come from other replicas, so they aren't trusted: a
+//! malformed one is rejected with `Error::InvalidTree`.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Orswot, CmRDT};
+//! use crdts::merkle::Merkle;
+//!
+//! let mut a: Orswot<u32, u8> = Orswot::new();
+//! for member in 0..1000u32 {
+//!     let op = a.add(member, a.value().derive_add_ctx(1));
+//!     a.apply(&op);
+//! }
+//! let mut b = a.clone();
+//! let op = b.add(1000u32, b.value().derive_add_ctx(2));
+//! b.apply(&op);
+//!
+//! let a_tree = a.merkle_tree(8);
+//! let b_tree = b.merkle_tree_like(&a_tree).unwrap();
+//! assert_ne!(a_tree.root(), b_tree.root());
+//!
+//! // only a single bucket of b needs to be sent to a
+//! let buckets = a_tree.diff(&b_tree).unwrap();
+//! assert_eq!(buckets.len(), 1);
+//!
+//! let delta = b.delta(&a_tree, &buckets).unwrap();
+//! a.merge_delta(&delta).unwrap();
+//! assert_eq!(a, b);
+//! ```
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use canonical::{self, fnv1a};
+use error::{Error, Result};
+
+/// The maximum depth of a `MerkleTree`
+pub const MAX_DEPTH: u8 = 24;
+
+/// A Merkle tree over the buckets of a CRDT's entries.
+///
+/// The leaves hold the hash of the canonical encoding of the entries in
+/// each bucket, the tree also carries a hash of the CRDT's metadata
+/// (clock and deferred removes) which isn't tied to any bucket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleTree {
+    depth: u8,
+    // the encoded first key of every bucket after the first one for a
+    // tree over key ranges, empty for a tree over hash buckets
+    bounds: Vec<Vec<u8>>,
+    meta: u64,
+    // nodes of a complete binary tree, the children of node i are
+    // 2i + 1 and 2i + 2, the last 2^depth nodes are the leaves.
+    nodes: Vec<u64>
+}
+
+/// A partial state of a CRDT, holding only the entries in some buckets.
+///
+/// The clock and deferred removes of the state are complete, so the
+/// delta can be merged with `Merkle::merge_delta` as if it was the
+/// full state.
+#[serde(bound(deserialize = "C: ::serde::de::DeserializeOwned"))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delta<C> {
+    /// The depth of the trees the buckets were taken from
+    pub depth: u8,
+    /// The key range bounds of the trees the buckets were taken from,
+    /// see `MerkleTree::bounds`
+    pub bounds: Vec<Vec<u8>>,
+    /// The buckets included in this delta
+    pub buckets: BTreeSet<usize>,
+    /// The partial state
+    pub state: C
+}
+
+/// Merkle is implemented by CRDT's that can be reconciled bucket by bucket.
+pub trait Merkle: Sized {
+    /// Build a Merkle tree with `2^depth` buckets over this CRDT's entries,
+    /// a depth larger than `MAX_DEPTH` is taken as `MAX_DEPTH`.
+    fn merkle_tree(&self, depth: u8) -> MerkleTree;
+
+    /// Build a Merkle tree over the same buckets as a tree of another
+    /// replica, so the two trees can be diffed. Fails with
+    /// `Error::InvalidTree` if the tree is malformed.
+    fn merkle_tree_like(&self, tree: &MerkleTree) -> Result<MerkleTree> {
+        tree.check()?;
+        Ok(self.merkle_tree(tree.depth()))
+    }
+
+    /// Returns the partial state holding only the entries in the given
+    /// buckets of the tree. Fails with `Error::InvalidTree` if the tree
+    /// is malformed.
+    fn delta(&self, tree: &MerkleTree, buckets: &[usize]) -> Result<Delta<Self>>;
+
+    /// Merge a partial state. Entries outside of the delta's buckets are
+    /// left as they are, so this is equivalent to merging the full state
+    /// only if the entries in all other buckets are equal on both sides.
+    ///
+    /// Fails with `Error::InvalidTree` if the delta is malformed, nothing
+    /// is merged then.
+    fn merge_delta(&mut self, delta: &Delta<Self>) -> Result<()>;
+}
+
+impl MerkleTree {
+    /// Build a tree from the encoded entries of a CRDT. Entries are given
+    /// as pairs of the encoded key (used to pick the bucket) and the
+    /// canonical encoding of the whole entry.
+    ///
+    /// # Panics
+    ///
+    /// Panics if depth is larger than `MAX_DEPTH`.
+    pub fn build<I>(depth: u8, entries: I, meta: u64) -> MerkleTree
+        where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+    {
+        let entries = entries.into_iter()
+            .map(|(key, entry)| (bucket(&key, depth), entry));
+        MerkleTree::build_ranges(depth, Vec::new(), entries, meta)
+    }
+
+    /// Build a tree over key ranges from the encoded entries of a CRDT.
+    /// The bounds are the encoded first keys of every bucket after the
+    /// first one, entries are given as pairs of their bucket and their
+    /// canonical encoding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if depth is larger than `MAX_DEPTH`, if there are more
+    /// bounds than buckets or if an entry is outside of the buckets.
+    pub fn build_ranges<I>(depth: u8, bounds: Vec<Vec<u8>>, entries: I, meta: u64) -> MerkleTree
+        where I: IntoIterator<Item=(usize, Vec<u8>)>
+    {
+        assert!(depth <= MAX_DEPTH, "merkle tree depth {} is larger than {}", depth, MAX_DEPTH);
+
+        let num_buckets = 1 << depth;
+        assert!(bounds.len() < num_buckets, "{} bounds for {} buckets", bounds.len(), num_buckets);
+        let mut buckets: Vec<Vec<Vec<u8>>> = vec![Vec::new(); num_buckets];
+        for (bucket, entry) in entries {
+            buckets[bucket].push(entry);
+        }
+
+        let mut nodes = vec![0; 2 * num_buckets - 1];
+        for (i, mut entries) in buckets.into_iter().enumerate() {
+            entries.sort();
+            let mut buf = Vec::new();
+            for entry in entries {
+                buf.extend(entry);
+            }
+            nodes[num_buckets - 1 + i] = fnv1a(&buf);
+        }
+        for i in (0..num_buckets - 1).rev() {
+            nodes[i] = hash_pair(nodes[2 * i + 1], nodes[2 * i + 2]);
+        }
+
+        MerkleTree { depth, bounds, meta, nodes }
+    }
+
+    /// Check that a tree received from another replica is well formed:
+    /// its depth is at most `MAX_DEPTH`, it has fewer bounds than buckets
+    /// and a node for every bucket.
+    pub fn check(&self) -> Result<()> {
+        if self.depth > MAX_DEPTH {
+            return Err(Error::InvalidTree);
+        }
+        let num_buckets = 1usize << self.depth;
+        if self.bounds.len() >= num_buckets || self.nodes.len() != 2 * num_buckets - 1 {
+            return Err(Error::InvalidTree);
+        }
+        Ok(())
+    }
+
+    /// The depth of the tree, it has `2^depth` buckets.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// The encoded first key of every bucket after the first one, bucket
+    /// `i` holds the keys from `bounds[i - 1]` up to `bounds[i]`. The
+    /// bucket of the last bound holds every key from it on, the buckets
+    /// after it are empty. Trees over hash buckets have no bounds.
+    pub fn bounds(&self) -> &[Vec<u8>] {
+        &self.bounds
+    }
+
+    /// The hash of the whole state, replicas with equal state have
+    /// equal roots.
+    pub fn root(&self) -> u64 {
+        hash_pair(self.nodes[0], self.meta)
+    }
+
+    /// Walk both trees from the root, descending only into the subtrees
+    /// that differ, and return the buckets whose hashes differ.
+    ///
+    /// An empty result with differing roots means only the metadata
+    /// differs, an empty delta still carries it.
+    ///
+    /// Fails with `Error::InvalidTree` if either tree is malformed or if
+    /// the trees have a different depth or different bounds.
+    pub fn diff(&self, other: &MerkleTree) -> Result<Vec<usize>> {
+        self.check()?;
+        other.check()?;
+        if self.depth != other.depth || self.bounds != other.bounds {
+            return Err(Error::InvalidTree);
+        }
+
+        let first_leaf = self.nodes.len() / 2;
+        let mut buckets = Vec::new();
+        let mut to_visit = vec![0];
+        while let Some(i) = to_visit.pop() {
+            if self.nodes[i] == other.nodes[i] {
+                continue;
+            }
+            if i >= first_leaf {
+                buckets.push(i - first_leaf);
+            } else {
+                to_visit.push(2 * i + 2);
+                to_visit.push(2 * i + 1);
+            }
+        }
+        Ok(buckets)
+    }
+}
+
+impl<C> Delta<C> {
+    /// Check that a delta received from another replica is well formed:
+    /// its depth is at most `MAX_DEPTH` and it has fewer bounds than
+    /// buckets.
+    pub fn check(&self) -> Result<()> {
+        if self.depth > MAX_DEPTH || self.bounds.len() >= 1usize << self.depth {
+            return Err(Error::InvalidTree);
+        }
+        Ok(())
+    }
+}
+
+/// The bucket an encoded key falls in for a tree of the given depth.
+pub fn bucket(key: &[u8], depth: u8) -> usize {
+    if depth == 0 {
+        0
+    } else {
+        (fnv1a(key) >> (64 - depth as u32)) as usize
+    }
+}
+
+/// The bucket a key falls in for a tree of the given depth.
+pub fn bucket_of<K: Serialize>(key: &K, depth: u8) -> usize {
+    let mut buf = Vec::new();
+    canonical::encode(key, &mut buf);
+    bucket(&buf, depth)
+}
+
+/// The bucket a key falls in for a tree over key ranges with the given
+/// (decoded) bounds, see `MerkleTree::bounds`.
+pub fn range_bucket_of<K: Ord>(bounds: &[K], key: &K) -> usize {
+    match bounds.binary_search(key) {
+        Ok(i) => i + 1,
+        Err(i) => i
+    }
+}
+
+fn hash_pair(left: u64, right: u64) -> u64 {
+    let mut buf = Vec::with_capacity(16);
+    canonical::encode(&left, &mut buf);
+    canonical::encode(&right, &mut buf);
+    fnv1a(&buf)
+}
@@ -0,0 +1,422 @@
+//! An add-wins multimap.
+//!
+//! A `MultiMap` maps every key to a set of values. Every (key, value)
+//! pair is an element of its own with observed-remove semantics: an
+//! insert that wasn't seen by a concurrent remove survives it, while the
+//! other values of the key are unaffected.
+//!
+//! Unlike a `Map` of `Orswot`s there is no nested CRDT to reset, a pair
+//! is only held by the dots of its inserts and one clock tracks the whole
+//! map, so removing the last value of a key drops the key.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{MultiMap, CvRDT, CmRDT};
+//!
+//! let mut tags: MultiMap<String, String, u8> = MultiMap::new();
+//! let op = tags.insert("doc", "draft", tags.get(&"doc".into()).derive_add_ctx(1));
+//! tags.apply(&op);
+//!
+//! let mut replica = tags.clone();
+//! let op = tags.remove_all("doc", tags.get(&"doc".into()).derive_rm_ctx());
+//! tags.apply(&op);
+//! let op = replica.insert("doc", "urgent", replica.get(&"doc".into()).derive_add_ctx(2));
+//! replica.apply(&op);
+//!
+//! // the concurrent insert survives the remove, the draft tag doesn't
+//! tags.merge(&replica);
+//! assert_eq!(
+//!     tags.get(&"doc".into()).val,
+//!     vec!["urgent".to_string()].into_iter().collect()
+//! );
+//! ```
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use traits::{Causal, CausalOp, CvRDT, CmRDT};
+use vclock::{Dot, VClock, Actor};
+use ctx::{ReadCtx, ReadCtxRef, AddCtx, RmCtx};
+use deferred::{self, Deferred};
+use canonical::{self, Canonical};
+use map::Key;
+use orswot::Member;
+
+/// `MultiMap` is an add-wins map from keys to sets of values.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiMap<K: Key, V: Member, A: Actor> {
+    clock: VClock<A>,
+    // key -> value -> the dots of the inserts of the pair
+    entries: BTreeMap<K, HashMap<V, VClock<A>>>,
+    // removes waiting on dots we haven't seen, None removes every value
+    // of the key
+    deferred: Deferred<A, BTreeMap<K, Option<HashSet<V>>>>
+}
+
+/// Op's define an edit to a `MultiMap`
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op<K: Key, V: Member, A: Actor> {
+    /// Insert a value under a key
+    Insert {
+        /// Insert witnessing dot
+        dot: Dot<A>,
+        /// Key to insert under
+        key: K,
+        /// Value to insert
+        val: V
+    },
+    /// Remove values of a key
+    Rm {
+        /// Remove witnessing clock
+        clock: VClock<A>,
+        /// Key to remove from
+        key: K,
+        /// Value to remove, None removes every value of the key
+        val: Option<V>
+    }
+}
+
+impl<K: Key, V: Member, A: Actor> Default for MultiMap<K, V, A> {
+    fn default() -> Self {
+        MultiMap::new()
+    }
+}
+
+impl<K: Key, V: Member, A: Actor> CmRDT for MultiMap<K, V, A> {
+    type Op = Op<K, V, A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        match op {
+            Op::Insert { dot, key, val } => {
+                if self.clock.get(&dot.actor) >= dot.counter {
+                    // we've already seen this op
+                    return;
+                }
+                self.entries.entry(key.clone())
+                    .or_default()
+                    .entry(val.clone())
+                    .or_insert_with(VClock::new)
+                    .apply(dot);
+                self.clock.apply(dot);
+                self.apply_deferred_dot(dot, key);
+            },
+            Op::Rm { clock, key, val } => {
+                self.apply_remove(key, val.as_ref(), clock);
+            }
+        }
+    }
+}
+
+impl<K: Key, V: Member, A: Actor> CausalOp<A> for Op<K, V, A> {
+    fn clock(&self) -> VClock<A> {
+        match self {
+            Op::Insert { dot, .. } => VClock::from(dot.clone()),
+            Op::Rm { clock, .. } => clock.clone()
+        }
+    }
+}
+
+impl<K: Key, V: Member, A: Actor> CvRDT for MultiMap<K, V, A> {
+    /// Merge combines another `MultiMap` with this one, every pair is
+    /// merged the way an `Orswot` merges its members.
+    fn merge(&mut self, other: &Self) {
+        // collect the pairs other has witnessed that we haven't seen
+        let mut novel = Vec::new();
+        for (key, vals) in other.entries.iter() {
+            for (val, other_clock) in vals.iter() {
+                let known = self.entries.get(key)
+                    .map(|vals| vals.contains_key(val))
+                    .unwrap_or(false);
+                if !known {
+                    let mut clock = VClock::new();
+                    deferred::merge_dots(&mut clock, other_clock, &self.clock, &other.clock);
+                    if !clock.is_empty() {
+                        novel.push((key.clone(), val.clone(), clock));
+                    }
+                }
+            }
+        }
+
+        let self_clock = &self.clock;
+        let empty = VClock::new();
+        for (key, vals) in self.entries.iter_mut() {
+            let other_vals = other.entries.get(key);
+            vals.retain(|val, clock| {
+                let other_clock = other_vals.and_then(|vals| vals.get(val))
+                    .unwrap_or(&empty);
+                deferred::merge_dots(clock, other_clock, self_clock, &other.clock);
+                !clock.is_empty()
+            });
+        }
+        self.entries.retain(|_, vals| !vals.is_empty());
+
+        for (key, val, clock) in novel {
+            self.entries.entry(key)
+                .or_default()
+                .insert(val, clock);
+        }
+
+        for (clock, keys) in other.deferred.iter() {
+            for (key, removed) in keys.iter() {
+                match removed {
+                    Some(vals) => for val in vals.iter() {
+                        self.defer(clock, key, Some(val));
+                    },
+                    None => self.defer(clock, key, None)
+                }
+            }
+        }
+
+        self.clock.merge(&other.clock);
+        self.apply_deferred();
+    }
+}
+
+impl<K: Key, V: Member, A: Actor> Causal<A> for MultiMap<K, V, A> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        for vals in self.entries.values_mut() {
+            vals.retain(|_, val_clock| {
+                val_clock.subtract(clock);
+                !val_clock.is_empty()
+            });
+        }
+        self.entries.retain(|_, vals| !vals.is_empty());
+
+        self.deferred.truncate(clock, |keys, more| {
+            for (key, removed) in more {
+                match removed {
+                    Some(vals) => for val in vals.iter() {
+                        record_remove(keys, &key, Some(val));
+                    },
+                    None => record_remove(keys, &key, None)
+                }
+            }
+        });
+
+        self.clock.subtract(clock);
+    }
+}
+
+impl<K: Key, V: Member, A: Actor> Canonical for MultiMap<K, V, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.clock.encode_canonical(buf);
+
+        // keys are kept in a BTreeMap, the values of a key aren't
+        canonical::encode(&(self.entries.len() as u64), buf);
+        for (key, vals) in self.entries.iter() {
+            canonical::encode(key, buf);
+            let vals = vals.iter()
+                .map(|(val, clock)| {
+                    let mut elem = Vec::new();
+                    canonical::encode(val, &mut elem);
+                    clock.encode_canonical(&mut elem);
+                    elem
+                })
+                .collect();
+            canonical::encode_sorted(vals, buf);
+        }
+
+        let deferred = self.deferred.iter()
+            .map(|(clock, keys)| {
+                let mut elem = Vec::new();
+                clock.encode_canonical(&mut elem);
+                canonical::encode(&(keys.len() as u64), &mut elem);
+                for (key, removed) in keys.iter() {
+                    canonical::encode(key, &mut elem);
+                    match *removed {
+                        None => canonical::encode(&None::<()>, &mut elem),
+                        Some(ref vals) => {
+                            // the tag of Some, followed by the sorted values
+                            canonical::encode(&Some(()), &mut elem);
+                            let vals = vals.iter()
+                                .map(|val| {
+                                    let mut val_elem = Vec::new();
+                                    canonical::encode(val, &mut val_elem);
+                                    val_elem
+                                })
+                                .collect();
+                            canonical::encode_sorted(vals, &mut elem);
+                        }
+                    }
+                }
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(deferred, buf);
+    }
+}
+
+impl<K: Key, V: Member, A: Actor> MultiMap<K, V, A> {
+    /// Returns a new, empty `MultiMap`
+    pub fn new() -> Self {
+        MultiMap {
+            clock: VClock::new(),
+            entries: BTreeMap::new(),
+            deferred: Deferred::new()
+        }
+    }
+
+    /// Insert a value under a key
+    pub fn insert(&self, key: impl Into<K>, val: impl Into<V>, ctx: AddCtx<A>) -> Op<K, V, A> {
+        Op::Insert { dot: ctx.dot, key: key.into(), val: val.into() }
+    }
+
+    /// Remove a value of a key, derive the ctx from `contains`
+    pub fn remove(&self, key: impl Into<K>, val: impl Into<V>, ctx: RmCtx<A>) -> Op<K, V, A> {
+        Op::Rm { clock: ctx.clock, key: key.into(), val: Some(val.into()) }
+    }
+
+    /// Remove every value of a key, derive the ctx from `get`
+    pub fn remove_all(&self, key: impl Into<K>, ctx: RmCtx<A>) -> Op<K, V, A> {
+        Op::Rm { clock: ctx.clock, key: key.into(), val: None }
+    }
+
+    /// Retrieve the values of a key
+    pub fn get(&self, key: &K) -> ReadCtx<HashSet<V>, A> {
+        let mut rm_clock = VClock::new();
+        let mut vals = HashSet::new();
+        if let Some(entry) = self.entries.get(key) {
+            for (val, clock) in entry.iter() {
+                rm_clock.merge(clock);
+                vals.insert(val.clone());
+            }
+        }
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock,
+            val: vals
+        }
+    }
+
+    /// Check if a value is stored under a key
+    pub fn contains(&self, key: &K, val: &V) -> ReadCtx<bool, A> {
+        let clock_opt = self.entries.get(key).and_then(|vals| vals.get(val));
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: clock_opt.cloned().unwrap_or_else(VClock::new),
+            val: clock_opt.is_some()
+        }
+    }
+
+    /// Returns the number of keys with at least one value
+    pub fn len(&self) -> ReadCtx<usize, A> {
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: self.clock.clone(),
+            val: self.entries.len()
+        }
+    }
+
+    /// Returns an iterator over the keys, ordered by key.
+    /// The ReadCtxRef covers the whole traversal.
+    pub fn keys(&self) -> ReadCtxRef<'_, impl Iterator<Item=&K>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.entries.keys()
+        }
+    }
+
+    /// Returns an iterator over the (key, value) pairs, ordered by key.
+    /// The ReadCtxRef covers the whole traversal.
+    pub fn iter(&self) -> ReadCtxRef<'_, impl Iterator<Item=(&K, &V)>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.entries.iter()
+                .flat_map(|(key, vals)| vals.keys().map(move |val| (key, val)))
+        }
+    }
+
+    /// Returns the number of removes waiting on dots this map hasn't seen.
+    pub fn deferred_len(&self) -> usize {
+        self.deferred.iter()
+            .flat_map(|(_, keys)| keys.values())
+            .map(|removed| removed.as_ref().map(|vals| vals.len()).unwrap_or(1))
+            .sum()
+    }
+
+    /// Remove the value of a key, or all of them, using a witnessing
+    /// clock. The remove is deferred if we haven't seen the whole clock.
+    fn apply_remove(&mut self, key: &K, val: Option<&V>, clock: &VClock<A>) {
+        if *clock > self.clock || clock.concurrent(&self.clock) {
+            self.defer(clock, key, val);
+        }
+
+        let now_empty = if let Some(vals) = self.entries.get_mut(key) {
+            vals.retain(|existing, existing_clock| {
+                if val.map(|val| val == existing).unwrap_or(true) {
+                    existing_clock.subtract(clock);
+                }
+                !existing_clock.is_empty()
+            });
+            vals.is_empty()
+        } else {
+            false
+        };
+        if now_empty {
+            self.entries.remove(key);
+        }
+    }
+
+    /// Apply the removes a deferred clock holds for a key.
+    fn apply_removed(&mut self, key: &K, removed: Option<HashSet<V>>, clock: &VClock<A>) {
+        match removed {
+            Some(vals) => for val in vals.iter() {
+                self.apply_remove(key, Some(val), clock);
+            },
+            None => self.apply_remove(key, None, clock)
+        }
+    }
+
+    /// Record a deferred remove.
+    fn defer(&mut self, clock: &VClock<A>, key: &K, val: Option<&V>) {
+        record_remove(self.deferred.entry(clock), key, val);
+    }
+
+    /// Re-apply all deferred removes, used after a merge.
+    fn apply_deferred(&mut self) {
+        for (clock, keys) in self.deferred.take_all() {
+            for (key, removed) in keys {
+                self.apply_removed(&key, removed, &clock);
+            }
+        }
+    }
+
+    /// Apply the deferred removes affected by inserting a dot under key,
+    /// the removes that cover the dot and the removes that were waiting
+    /// on the dot.
+    fn apply_deferred_dot(&mut self, dot: &Dot<A>, key: &K) {
+        for clock in self.deferred.covering(dot) {
+            let removed = self.deferred.get(&clock)
+                .and_then(|keys| keys.get(key))
+                .cloned();
+            if let Some(removed) = removed {
+                self.apply_removed(key, removed, &clock);
+            }
+        }
+
+        for (clock, keys) in self.deferred.take_unblocked(dot, &self.clock) {
+            for (key, removed) in keys {
+                self.apply_removed(&key, removed, &clock);
+            }
+        }
+    }
+}
+
+/// Record the remove of a value of a key, or of all of them, among the
+/// removes of a deferred clock. A remove of every value of the key
+/// subsumes the removes of single values.
+fn record_remove<K: Key, V: Member>(keys: &mut BTreeMap<K, Option<HashSet<V>>>, key: &K, val: Option<&V>) {
+    let removed = keys.entry(key.clone())
+        .or_insert_with(|| Some(HashSet::new()));
+    match val {
+        Some(val) => if let Some(vals) = removed {
+            vals.insert(val.clone());
+        },
+        None => *removed = None
+    }
+}
@@ -5,12 +5,78 @@ use std::fmt::{self, Debug, Display};
 
 use vclock::{VClock, Actor};
 use ctx::{ReadCtx, AddCtx};
-use traits::{Causal, CmRDT, CvRDT};
+use traits::{Causal, CausalOp, CmRDT, CvRDT, Diff, Undo};
+use canonical::{self, Canonical};
+use error::{Error, Result};
 
 /// A Trait alias for the possible values MVReg's may hold
 pub trait Val: Debug + Clone + Send + Serialize + DeserializeOwned {}
 impl<T: Debug + Clone + Send + Serialize + DeserializeOwned> Val for T {}
 
+/// Resolvers pick a single value out of the concurrent values of an
+/// `MVReg`.
+///
+/// Replicas see the concurrent values in different orders, a resolver must
+/// pick the same value no matter the order of the values to converge.
+/// Closures `Fn(&[V]) -> V` are resolvers.
+pub trait Resolver<V> {
+    /// Resolve the concurrent values into one, vals is never empty.
+    fn resolve(&self, vals: &[V]) -> V;
+}
+
+impl<V, F: Fn(&[V]) -> V> Resolver<V> for F {
+    fn resolve(&self, vals: &[V]) -> V {
+        self(vals)
+    }
+}
+
+/// Resolves to the greatest of the concurrent values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Max;
+
+impl<V: Ord + Clone> Resolver<V> for Max {
+    fn resolve(&self, vals: &[V]) -> V {
+        vals.iter().max().unwrap().clone()
+    }
+}
+
+/// Resolves to the least of the concurrent values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Min;
+
+impl<V: Ord + Clone> Resolver<V> for Min {
+    fn resolve(&self, vals: &[V]) -> V {
+        vals.iter().min().unwrap().clone()
+    }
+}
+
+/// Resolves to the last write, ordered by a marker embedded in the values
+/// (e.g. a timestamp). The markers of concurrent values must be unique,
+/// see `LWWReg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByMarker<F>(pub F);
+
+impl<V: Clone, M: Ord, F: Fn(&V) -> M> Resolver<V> for ByMarker<F> {
+    fn resolve(&self, vals: &[V]) -> V {
+        vals.iter().max_by_key(|val| (self.0)(val)).unwrap().clone()
+    }
+}
+
+/// Resolves by merging the concurrent values, for values that are
+/// CRDT's themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Merge;
+
+impl<V: CvRDT + Clone> Resolver<V> for Merge {
+    fn resolve(&self, vals: &[V]) -> V {
+        let mut merged = vals[0].clone();
+        for val in vals[1..].iter() {
+            merged.merge(val);
+        }
+        merged
+    }
+}
+
 /// MVReg (Multi-Value Register)
 /// On concurrent writes, we will keep all values for which
 /// we can't establish a causal history.
@@ -58,6 +124,27 @@ pub enum Op<V: Val, A: Actor> {
     }
 }
 
+/// The values written and overwritten between two states of an `MVReg`.
+///
+/// Values are told apart by the write that produced them, setting the
+/// register to the value it already holds shows up in both sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes<V: Val> {
+    /// Values that weren't in the register before
+    pub added: Vec<V>,
+    /// Values that are no longer in the register
+    pub removed: Vec<V>
+}
+
+/// Describes how to undo a `Put` on an `MVReg`
+#[derive(Debug, Clone)]
+pub struct Inverse<V: Val, A: Actor> {
+    /// The clock of the put to undo
+    pub clock: VClock<A>,
+    /// The value the put overwrote
+    pub val: V
+}
+
 impl<V: Val + Display, A: Actor + Display> Display for MVReg<V, A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "|")?;
@@ -152,6 +239,117 @@ impl<V: Val, A: Actor> CvRDT for MVReg<V, A> {
     }
 }
 
+impl<V: Val, A: Actor> Canonical for MVReg<V, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        let vals = self.vals.iter()
+            .map(|(clock, val)| {
+                let mut elem = Vec::new();
+                clock.encode_canonical(&mut elem);
+                canonical::encode(val, &mut elem);
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(vals, buf)
+    }
+}
+
+impl<V: Val, A: Actor> Diff for MVReg<V, A> {
+    type Changes = Changes<V>;
+
+    fn diff(&self, other: &Self) -> Option<Changes<V>> {
+        let not_in = |vals: &Vec<(VClock<A>, V)>, clock: &VClock<A>| {
+            !vals.iter().any(|(c, _)| c == clock)
+        };
+        let added: Vec<V> = other.vals.iter()
+            .filter(|(clock, _)| not_in(&self.vals, clock))
+            .map(|(_, val)| val.clone())
+            .collect();
+        let removed: Vec<V> = self.vals.iter()
+            .filter(|(clock, _)| not_in(&other.vals, clock))
+            .map(|(_, val)| val.clone())
+            .collect();
+
+        if added.is_empty() && removed.is_empty() {
+            None
+        } else {
+            Some(Changes { added, removed })
+        }
+    }
+
+    /// The changes are worked out from the incoming values, rather than
+    /// from a copy of the register taken before the merge.
+    fn merge_with_changes(&mut self, other: &Self) -> Option<Changes<V>> {
+        // our values other has overwritten
+        let removed: Vec<V> = self.vals.iter()
+            .filter(|(clock, _)| other.vals.iter().any(|(c, _)| clock < c))
+            .map(|(_, val)| val.clone())
+            .collect();
+        // values of other we haven't seen or overwritten
+        let added: Vec<V> = other.vals.iter()
+            .filter(|(clock, _)| !self.vals.iter().any(|(c, _)| clock <= c))
+            .map(|(_, val)| val.clone())
+            .collect();
+
+        self.merge(other);
+
+        if added.is_empty() && removed.is_empty() {
+            None
+        } else {
+            Some(Changes { added, removed })
+        }
+    }
+}
+
+impl<V: Val, A: Actor> CausalOp<A> for Op<V, A> {
+    fn clock(&self) -> VClock<A> {
+        match self {
+            Op::Put { clock, .. } => clock.clone()
+        }
+    }
+}
+
+impl<V: Val, A: Actor> Undo<A> for MVReg<V, A> {
+    type Inverse = Inverse<V, A>;
+
+    /// A put is undone by putting back the value it overwrote, with a
+    /// clock that only overwrites the value of the put. Values written
+    /// concurrently with the undone put survive.
+    ///
+    /// Only puts that overwrote a single value can be undone, a register
+    /// can't be set back to empty or to several concurrent values.
+    fn inverse(&self, op: &Op<V, A>) -> Option<Inverse<V, A>> {
+        match op {
+            Op::Put { clock, .. } => {
+                if self.vals.iter().any(|(val_clock, _)| val_clock >= clock) {
+                    // we've seen this op already
+                    return None;
+                }
+                match self.vals.as_slice() {
+                    [(val_clock, val)] if val_clock <= clock => {
+                        Some(Inverse { clock: clock.clone(), val: val.clone() })
+                    },
+                    _ => None
+                }
+            }
+        }
+    }
+
+    fn undo(&self, inverse: &Inverse<V, A>, actor: A) -> Op<V, A> {
+        let dot = self.read().add_clock.inc(actor);
+        let mut clock = inverse.clock.clone();
+        clock.apply(&dot);
+        Op::Put { clock, val: inverse.val.clone() }
+    }
+
+    /// Only a register holding a single value can be restored.
+    fn restore(&self, ctx: AddCtx<A>) -> Option<Op<V, A>> {
+        match self.vals.as_slice() {
+            [(_, val)] => Some(self.set(val.clone(), ctx)),
+            _ => None
+        }
+    }
+}
+
 impl<V: Val, A: Actor> CmRDT for MVReg<V, A> {
     type Op = Op<V, A>;
 
@@ -167,7 +365,7 @@ impl<V: Val, A: Actor> CmRDT for MVReg<V, A> {
                 // TAI: in the case were the Op has a context that already was present,
                 //      the above line would remove that value, the next lines would
                 //      keep the val from the Op, so.. a malformed Op could break
-                //      comutativity.
+                //      comutativity. `checked_apply` rejects these Op's.
                 
                 // now check if we've already seen this op
                 let mut should_add = true;
@@ -192,6 +390,40 @@ impl<V: Val, A: Actor> MVReg<V, A> {
         MVReg::default()
     }
 
+    /// Apply an op, first checking that it's well formed. A put reusing
+    /// the clock of a value in the register for a different value would
+    /// break commutativity, it's rejected with `Error::ConflictingMarker`
+    /// and the register is left untouched.
+    ///
+    /// ```rust
+    /// use crdts::{MVReg, VClock, Dot, Error};
+    /// use crdts::mvreg::Op;
+    ///
+    /// let mut reg = MVReg::<u8, u8>::new();
+    /// let clock: VClock<u8> = Dot { actor: 1, counter: 1 }.into();
+    /// assert!(reg.checked_apply(&Op::Put { clock: clock.clone(), val: 1 }).is_ok());
+    /// // re-delivering the same op is fine
+    /// assert!(reg.checked_apply(&Op::Put { clock: clock.clone(), val: 1 }).is_ok());
+    /// assert_eq!(
+    ///     reg.checked_apply(&Op::Put { clock, val: 2 }),
+    ///     Err(Error::ConflictingMarker)
+    /// );
+    /// assert_eq!(reg.read().val, vec![1]);
+    /// ```
+    pub fn checked_apply(&mut self, op: &Op<V, A>) -> Result<()> where V: PartialEq {
+        match op {
+            Op::Put { clock, val } => {
+                let conflict = self.vals.iter()
+                    .any(|(val_clock, existing)| val_clock == clock && existing != val);
+                if conflict {
+                    return Err(Error::ConflictingMarker);
+                }
+            }
+        }
+        self.apply(op);
+        Ok(())
+    }
+
     /// Set the value of the register
     pub fn set(&self, val: impl Into<V>, ctx: AddCtx<A>) -> Op<V, A> {
         Op::Put { clock: ctx.clock, val: val.into() }
@@ -212,6 +444,56 @@ impl<V: Val, A: Actor> MVReg<V, A> {
         }
     }
 
+    /// Resolve the concurrent values into one, None if the register
+    /// was never set.
+    ///
+    /// ```rust
+    /// use crdts::{CmRDT, MVReg};
+    /// use crdts::mvreg::Max;
+    ///
+    /// let mut r1 = MVReg::<u8, u8>::new();
+    /// let mut r2 = r1.clone();
+    /// let op1 = r1.set(3, r1.read().derive_add_ctx(1));
+    /// let op2 = r2.set(7, r2.read().derive_add_ctx(2));
+    /// r1.apply(&op1);
+    /// r1.apply(&op2);
+    ///
+    /// assert_eq!(r1.resolve(&Max).val, Some(7));
+    /// assert_eq!(r1.resolve(&|vals: &[u8]| vals.iter().sum()).val, Some(10));
+    ///
+    /// // collapse the siblings so every replica reads the resolved value
+    /// let op = r1.collapse(&Max, 1).unwrap();
+    /// r1.apply(&op);
+    /// assert_eq!(r1.read().val, vec![7]);
+    /// assert!(r1.collapse(&Max, 1).is_none());
+    /// ```
+    pub fn resolve<R: Resolver<V>>(&self, resolver: &R) -> ReadCtx<Option<V>, A> {
+        let ctx = self.read();
+        let val = if ctx.val.is_empty() {
+            None
+        } else {
+            Some(resolver.resolve(&ctx.val))
+        };
+        ReadCtx {
+            add_clock: ctx.add_clock,
+            rm_clock: ctx.rm_clock,
+            val
+        }
+    }
+
+    /// Returns the op setting the register to the resolved value, it
+    /// overwrites all concurrent values. None if the register doesn't
+    /// hold concurrent values. Replicas collapsing concurrently leave
+    /// siblings that all hold the resolved value.
+    pub fn collapse<R: Resolver<V>>(&self, resolver: &R, actor: A) -> Option<Op<V, A>> {
+        if self.vals.len() < 2 {
+            return None;
+        }
+        let ctx = self.resolve(resolver);
+        let add_ctx = ctx.derive_add_ctx(actor);
+        ctx.val.map(|val| self.set(val, add_ctx))
+    }
+
     /// A clock with latest versions of all actors operating on this register
     fn clock(&self) -> VClock<A> {
         self.vals.iter()
@@ -0,0 +1,333 @@
+//! Durable storage of an op based CRDT.
+//!
+//! An `OpLog` keeps the state of a `CmRDT` in memory and every op applied
+//! to it in an append-only log on local disk. Every so often the state is
+//! written to a snapshot and the log entries covered by the snapshot are
+//! dropped, so the log doesn't grow without bound. On startup the
+//! snapshot is loaded and the remaining log entries are replayed.
+//!
+//! Log entries are numbered, a snapshot covers every entry up to the
+//! last one applied before it was taken. The clock of an op isn't enough
+//! to tell if it's covered: a remove appended after the snapshot may
+//! carry a clock the snapshot's state already dominates.
+//!
+//! Both live in a directory of their own: the log in `<dir>/oplog` and
+//! the snapshot in `<dir>/snapshot`. Every log record and the snapshot
+//! are framed with a header holding their length and checksum, the
+//! header has a checksum of its own. A crash in the middle of an append
+//! leaves a torn record at the end of the log: its header is cut short
+//! or says the record runs past the end of the file. It's dropped when
+//! the log is opened again, the op it held was never applied so it's as
+//! if the append never happened. Any other corrupt record isn't the
+//! result of a crash, opening the log fails rather than dropping the
+//! records that follow it.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Orswot, CmRDT};
+//! use crdts::oplog::OpLog;
+//!
+//! let dir = std::env::temp_dir().join(format!("crdts-doc-oplog-{}", std::process::id()));
+//! # let _ = std::fs::remove_dir_all(&dir);
+//! let mut log: OpLog<Orswot<String, u8>> = OpLog::open(&dir, 100).unwrap();
+//! let op = log.state().add("bob", log.state().value().derive_add_ctx(1));
+//! log.append(op).unwrap();
+//! drop(log);
+//!
+//! let log: OpLog<Orswot<String, u8>> = OpLog::open(&dir, 100).unwrap();
+//! assert!(log.state().contains(&"bob".to_string()).val);
+//! # std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use traits::CmRDT;
+use canonical::fnv1a;
+use super::{to_binary, from_binary};
+
+const LOG_FILE: &str = "oplog";
+const SNAPSHOT_FILE: &str = "snapshot";
+
+// every frame starts with the length and checksum of its payload,
+// followed by the checksum of those two
+const HEADER_LEN: usize = 24;
+
+/// The on-disk snapshot of the state
+#[serde(bound(deserialize = "C: DeserializeOwned"))]
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot<C> {
+    // the last log entry included in the state
+    seq: u64,
+    state: C
+}
+
+/// `OpLog` owns the state of a CRDT and persists every op applied to it.
+#[derive(Debug)]
+pub struct OpLog<C: CmRDT> {
+    dir: PathBuf,
+    log: File,
+    state: C,
+    snapshot_every: usize,
+    // the last log entry included in the snapshot
+    snapshot_seq: u64,
+    // the last log entry applied to the state
+    seq: u64,
+    // the length of the log file, up to the end of the last record
+    log_len: u64,
+    // set if a failed append couldn't be rolled back
+    poisoned: bool
+}
+
+impl<C> OpLog<C> where C: CmRDT + Default + Serialize + DeserializeOwned {
+    /// Open the log in the given directory, creating it if needed.
+    /// A snapshot is taken every snapshot_every appends, pass 0 to only
+    /// take snapshots with `snapshot`.
+    ///
+    /// The state is restored from the last snapshot and the log entries
+    /// it doesn't cover. A torn record at the end of the log is dropped,
+    /// fails with `InvalidData` if the snapshot or any other record is
+    /// corrupt.
+    pub fn open(dir: impl AsRef<Path>, snapshot_every: usize) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let snapshot: Snapshot<C> = match read_file(&dir.join(SNAPSHOT_FILE))? {
+            Some(bytes) => {
+                match read_frame(&bytes) {
+                    Frame::Valid(payload, _) => from_binary(payload.to_vec()).map_err(invalid_data)?,
+                    _ => return Err(invalid_data("the oplog snapshot is corrupt"))
+                }
+            },
+            None => Snapshot { seq: 0, state: C::default() }
+        };
+
+        let mut state = snapshot.state;
+        let mut seq = snapshot.seq;
+        let mut covered = 0;
+        let log_path = dir.join(LOG_FILE);
+        let bytes = read_file(&log_path)?.unwrap_or_default();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let rest = &bytes[offset..];
+            let (payload, frame_len) = match read_frame(rest) {
+                Frame::Valid(payload, frame_len) => (payload, frame_len),
+                // a crash in the middle of an append can only tear the
+                // last record
+                Frame::Torn => break,
+                Frame::Corrupt => {
+                    return Err(invalid_data(format!("the oplog record at {} is corrupt", offset)));
+                }
+            };
+            let (op_seq, op): (u64, C::Op) = from_binary(payload.to_vec())
+                .map_err(invalid_data)?;
+            offset += frame_len;
+            if op_seq <= snapshot.seq {
+                // the snapshot was taken but the log wasn't compacted
+                covered += 1;
+                continue;
+            }
+            state.apply(&op);
+            seq = op_seq;
+        }
+
+        let log = OpenOptions::new().create(true).append(true).open(&log_path)?;
+        if offset < bytes.len() {
+            // drop the torn record at the end of the log
+            log.set_len(offset as u64)?;
+            log.sync_all()?;
+        }
+
+        let mut oplog = OpLog {
+            dir,
+            log,
+            state,
+            snapshot_every,
+            snapshot_seq: snapshot.seq,
+            seq,
+            log_len: offset as u64,
+            poisoned: false
+        };
+        if covered > 0 {
+            oplog.compact()?;
+        }
+        Ok(oplog)
+    }
+
+    /// The current state
+    pub fn state(&self) -> &C {
+        &self.state
+    }
+
+    /// Consumes the log, returning the state.
+    pub fn into_state(self) -> C {
+        self.state
+    }
+
+    /// The number of log entries that aren't covered by the snapshot
+    pub fn len(&self) -> usize {
+        (self.seq - self.snapshot_seq) as usize
+    }
+
+    /// Returns true if every log entry is covered by the snapshot
+    pub fn is_empty(&self) -> bool {
+        self.seq == self.snapshot_seq
+    }
+
+    /// Durably append an op to the log, then apply it to the state.
+    /// Takes a snapshot if snapshot_every ops were appended since the last.
+    ///
+    /// If the append fails the log is truncated back to its last record.
+    /// If that fails as well the log is poisoned, every later append
+    /// fails and the log has to be opened again.
+    pub fn append(&mut self, op: C::Op) -> io::Result<()> {
+        if self.poisoned {
+            return Err(io::Error::other("the oplog couldn't roll back a failed append"));
+        }
+
+        let seq = self.seq + 1;
+        let record = frame(&to_binary(&(seq, &op)));
+        let written = self.log.write_all(&record).and_then(|_| self.log.sync_data());
+        if let Err(err) = written {
+            // don't leave a partial record for later appends to follow
+            let log_len = self.log_len;
+            let rolled_back = self.log.set_len(log_len).and_then(|_| self.log.sync_all());
+            self.poisoned = rolled_back.is_err();
+            return Err(err);
+        }
+        self.log_len += record.len() as u64;
+
+        self.state.apply(&op);
+        self.seq = seq;
+        if self.snapshot_every > 0 && self.len() >= self.snapshot_every {
+            self.snapshot()?;
+        }
+        Ok(())
+    }
+
+    /// Write the state to the snapshot and drop the log entries it covers.
+    pub fn snapshot(&mut self) -> io::Result<()> {
+        let snapshot = Snapshot { seq: self.seq, state: &self.state };
+        self.replace(SNAPSHOT_FILE, &frame(&to_binary(&snapshot)))?;
+        self.snapshot_seq = self.seq;
+        self.compact()
+    }
+
+    /// Drop the log entries covered by the snapshot.
+    fn compact(&mut self) -> io::Result<()> {
+        let log_path = self.dir.join(LOG_FILE);
+        let bytes = read_file(&log_path)?.unwrap_or_default();
+        let mut kept = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (payload, frame_len) = match read_frame(&bytes[offset..]) {
+                Frame::Valid(payload, frame_len) => (payload, frame_len),
+                _ => return Err(invalid_data(format!("the oplog record at {} is corrupt", offset)))
+            };
+            let (op_seq, _): (u64, C::Op) = from_binary(payload.to_vec())
+                .map_err(invalid_data)?;
+            if op_seq > self.snapshot_seq {
+                kept.extend_from_slice(&bytes[offset..offset + frame_len]);
+            }
+            offset += frame_len;
+        }
+
+        self.replace(LOG_FILE, &kept)?;
+        self.log = OpenOptions::new().append(true).open(&log_path)?;
+        self.log_len = kept.len() as u64;
+        Ok(())
+    }
+
+    /// Atomically replace a file in the log directory: write a temporary
+    /// file, sync it, then rename it over the old file.
+    fn replace(&self, name: &str, bytes: &[u8]) -> io::Result<()> {
+        let path = self.dir.join(name);
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(bytes)?;
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+
+        // make the rename itself durable, not every platform lets us
+        // open a directory so this is best effort.
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    }
+}
+
+/// Frame a payload with its length and checksum.
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + payload.len());
+    buf.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&fnv1a(payload).to_le_bytes());
+    let header_checksum = fnv1a(&buf);
+    buf.extend_from_slice(&header_checksum.to_le_bytes());
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// A frame read from the start of some bytes
+enum Frame<'a> {
+    /// The payload and the length of the whole frame
+    Valid(&'a [u8], usize),
+    /// The bytes end before the frame does
+    Torn,
+    /// A checksum doesn't match the header or the payload
+    Corrupt
+}
+
+/// Read the frame at the start of bytes.
+fn read_frame(bytes: &[u8]) -> Frame<'_> {
+    if bytes.len() < HEADER_LEN {
+        return Frame::Torn;
+    }
+    let mut len = [0u8; 8];
+    let mut checksum = [0u8; 8];
+    let mut header_checksum = [0u8; 8];
+    len.copy_from_slice(&bytes[..8]);
+    checksum.copy_from_slice(&bytes[8..16]);
+    header_checksum.copy_from_slice(&bytes[16..HEADER_LEN]);
+    // don't trust the length unless the header is intact, a corrupt
+    // length would pass for a torn record and hide the ones after it
+    if fnv1a(&bytes[..16]) != u64::from_le_bytes(header_checksum) {
+        return Frame::Corrupt;
+    }
+    let len = u64::from_le_bytes(len);
+    if len > (bytes.len() - HEADER_LEN) as u64 {
+        return Frame::Torn;
+    }
+    let payload = &bytes[HEADER_LEN..HEADER_LEN + len as usize];
+    if fnv1a(payload) != u64::from_le_bytes(checksum) {
+        return Frame::Corrupt;
+    }
+    Frame::Valid(payload, HEADER_LEN + payload.len())
+}
+
+fn read_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match File::open(path) {
+        Ok(mut file) => {
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            Ok(Some(buf))
+        },
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err)
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+    where E: Into<Box<dyn Error + Send + Sync>>
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use traits::{Causal, CvRDT, CmRDT};
+use canonical::{self, Canonical};
+use vclock::{Dot, VClock, Actor};
+use ctx::{ReadCtx, AddCtx, RmCtx};
+use map::{Key, Val, Op};
+use deferred::{self, Deferred};
+
+/// ORMap CRDT - Supports Composition of CRDT's with observed-remove semantics
+/// that keep the full contents of an entry.
+///
+/// `ORMap` shares its `Op`'s with `Map`, the difference is in how a remove
+/// interacts with a concurrent update of the same key:
+///
+/// - `Map` (reset-remove): the entry survives, but every edit seen by the
+///   removing actor is truncated away from the nested CRDT.
+/// - `ORMap` (add-wins, keep contents): the entry survives with *all* of its
+///   contents, including the edits the removing actor had seen.
+///
+/// To make this converge under both op and state replication, `ORMap`
+/// keeps the nested CRDT of a removed entry around as a hidden tombstone
+/// so a concurrent update can resurrect it in full. Re-adding a key after
+/// it was removed starts over from empty contents: `update` sends a batch
+/// that removes and updates the key, which resets the contents the
+/// updater has seen. Tombstones are dropped by `gc` once their removes
+/// are causally stable.
+///
+/// A remove-wins map can't be offered over the same `Op` shape, an
+/// `Op::Up` only carries the dot of the update so replicas can't tell
+/// whether it was concurrent with a remove.
+///
+/// ``` rust
+/// use crdts::{ORMap, Orswot, CvRDT, CmRDT};
+///
+/// let mut friends: ORMap<String, Orswot<String, u8>, u8> = ORMap::new();
+///
+/// let op = friends.update(
+///     "alice",
+///     friends.get(&"alice".to_string()).derive_add_ctx(1),
+///     |set, ctx| set.add("bob", ctx)
+/// );
+/// friends.apply(&op);
+///
+/// let mut friends_replica = friends.clone();
+///
+/// let rm_op = friends.rm("alice", friends.get(&"alice".to_string()).derive_rm_ctx());
+/// friends.apply(&rm_op);
+///
+/// let replica_op = friends_replica.update(
+///     "alice",
+///     friends_replica.get(&"alice".into()).derive_add_ctx(2),
+///     |set, ctx| set.add("clyde", ctx)
+/// );
+/// friends_replica.apply(&replica_op);
+///
+/// friends.merge(&friends_replica);
+///
+/// // unlike Map, "bob" is still one of alice's friends
+/// let alice_friends = friends.get(&"alice".into()).val
+///     .map(|set| set.value().val);
+/// assert_eq!(
+///     alice_friends,
+///     Some(vec!["bob".to_string(), "clyde".to_string()].into_iter().collect())
+/// );
+/// ```
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ORMap<K: Key, V: Val<A>, A: Actor> {
+    // This clock stores the current version of the Map, it should
+    // be greator or equal to all Entry.clock's in the Map.
+    clock: VClock<A>,
+    entries: BTreeMap<K, Entry<V, A>>,
+    deferred: Deferred<A, BTreeSet<K>>
+}
+
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry<V: Val<A>, A: Actor> {
+    // The entry clock tells us which actors edited this entry,
+    // an empty clock marks a removed entry.
+    clock: VClock<A>,
+
+    // The nested CRDT, retained after the entry is removed
+    val: V,
+
+    // The edits of the nested CRDT discarded by re-adding the entry
+    reset: VClock<A>,
+
+    // Every edit of the entry, kept after it's removed
+    dots: VClock<A>
+}
+
+impl<V: Val<A>, A: Actor> Entry<V, A> {
+    fn new() -> Self {
+        Entry {
+            clock: VClock::new(),
+            val: V::default(),
+            reset: VClock::new(),
+            dots: VClock::new()
+        }
+    }
+
+    /// Discard the edits of the nested CRDT covered by the reset clock
+    fn apply_reset(&mut self) {
+        if !self.reset.is_empty() {
+            self.val.truncate(&self.reset);
+        }
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor> Default for ORMap<K, V, A> {
+    fn default() -> Self {
+        ORMap::new()
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor> Causal<A> for ORMap<K, V, A> {
+    fn truncate(&mut self, clock: &VClock<A>) {
+        for entry in self.entries.values_mut() {
+            entry.clock.subtract(clock);
+            entry.val.truncate(clock);
+        }
+
+        self.deferred.truncate(clock, |keys, more| keys.extend(more));
+
+        self.clock.subtract(clock);
+    }
+}
+
+impl<K: Key, V: Val<A> + Canonical, A: Actor> Canonical for ORMap<K, V, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.clock.encode_canonical(buf);
+
+        // entries are kept in a BTreeMap, so they're already ordered
+        canonical::encode(&(self.entries.len() as u64), buf);
+        for (key, entry) in self.entries.iter() {
+            canonical::encode(key, buf);
+            entry.clock.encode_canonical(buf);
+            entry.val.encode_canonical(buf);
+            entry.reset.encode_canonical(buf);
+            entry.dots.encode_canonical(buf);
+        }
+
+        let deferred = self.deferred.iter()
+            .map(|(clock, keys)| {
+                let mut elem = Vec::new();
+                clock.encode_canonical(&mut elem);
+                canonical::encode(keys, &mut elem);
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(deferred, buf);
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor> CmRDT for ORMap<K, V, A> {
+    type Op = Op<K, V, A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        match op.clone() {
+            Op::Nop => {/* do nothing */},
+            Op::Rm { clock, key } => {
+                self.apply_rm(key, &clock);
+            },
+            Op::Up { dot: Dot { actor, counter }, key, op } => {
+                if self.clock.get(&actor) >= counter {
+                    // we've seen this op already
+                    return;
+                }
+
+                let dot = Dot { actor, counter };
+                self.apply_up(&dot.actor, counter, key.clone(), &op);
+                self.clock.apply(&dot);
+                self.apply_deferred_dot(&dot, &[key]);
+            },
+            Op::Batch { dot: Dot { actor, counter }, updates, rms } => {
+                if self.clock.get(&actor) >= counter {
+                    // we've seen this batch already
+                    return;
+                }
+
+                for (key, clock) in rms.iter() {
+                    self.apply_rm(key.clone(), clock);
+                    if updates.iter().any(|(up_key, _)| up_key == key) {
+                        // the key is replaced, start over from empty contents
+                        let entry = self.entries.entry(key.clone())
+                            .or_insert_with(Entry::new);
+                        entry.reset.merge(clock);
+                        entry.apply_reset();
+                    }
+                }
+                let dot = Dot { actor, counter };
+                let mut keys = Vec::new();
+                for (key, op) in updates {
+                    self.apply_up(&dot.actor, counter, key.clone(), &op);
+                    keys.push(key);
+                }
+
+                self.clock.apply(&dot);
+                self.apply_deferred_dot(&dot, &keys);
+            }
+        }
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor> CvRDT for ORMap<K, V, A> {
+    fn merge(&mut self, other: &Self) {
+        for (key, entry) in self.entries.iter_mut() {
+            match other.entries.get(key) {
+                None => {
+                    // other has either never seen this entry, or it has
+                    // witnessed some of its dots and dropped them
+                    entry.clock.subtract(&other.clock);
+                }
+                Some(other_entry) => {
+                    // same dot logic as the orswot, but we never drop the
+                    // nested CRDT, so it's merged even if no dots survive
+                    deferred::merge_dots(&mut entry.clock, &other_entry.clock, &self.clock, &other.clock);
+                    entry.val.merge(&other_entry.val);
+                    entry.reset.merge(&other_entry.reset);
+                    entry.dots.merge(&other_entry.dots);
+                    entry.apply_reset();
+                }
+            }
+        }
+
+        for (key, other_entry) in other.entries.iter() {
+            if !self.entries.contains_key(key) {
+                // other has witnessed a novel entry, so add it
+                let mut entry = other_entry.clone();
+                entry.clock.subtract(&self.clock);
+                self.entries.insert(key.clone(), entry);
+            }
+        }
+
+        // merge deferred removals
+        for (clock, deferred) in other.deferred.iter() {
+            for key in deferred {
+                self.apply_rm(key.clone(), clock);
+            }
+        }
+
+        // merge vclocks
+        self.clock.merge(&other.clock);
+
+        self.apply_deferred();
+    }
+}
+
+impl<K: Key, V: Val<A>, A: Actor> ORMap<K, V, A> {
+    /// Constructs an empty ORMap
+    pub fn new() -> ORMap<K, V, A> {
+        ORMap {
+            clock: VClock::new(),
+            entries: BTreeMap::new(),
+            deferred: Deferred::new()
+        }
+    }
+
+    /// Returns the number of visible entries in the ORMap
+    pub fn len(&self) -> ReadCtx<usize, A> {
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: self.clock.clone(),
+            val: self.entries.values()
+                .filter(|entry| !entry.clock.is_empty())
+                .count()
+        }
+    }
+
+    /// Retrieve value stored under a key
+    pub fn get(&self, key: &K) -> ReadCtx<Option<V>, A> {
+        let entry_opt = self.entries.get(key)
+            .filter(|entry| !entry.clock.is_empty());
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: entry_opt
+                .map(|entry| entry.clock.clone())
+                .unwrap_or_else(|| VClock::new()),
+            val: entry_opt
+                .map(|entry| entry.val.clone())
+        }
+    }
+
+    /// Update a value under some key, the updater is given the current
+    /// value or V::default() if the key is not present.
+    ///
+    /// Updating a key that isn't present re-adds it with empty contents,
+    /// the op is a batch that removes and updates the key, discarding
+    /// every edit of removed contents this map has seen.
+    pub fn update<F, I>(&self, key: I, ctx: AddCtx<A>, f: F) -> Op<K, V, A>
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op,
+              I: Into<K>
+    {
+        let key = key.into();
+        match self.entries.get(&key) {
+            Some(entry) if !entry.clock.is_empty() => {
+                let op = f(&entry.val, ctx.clone());
+                Op::Up { dot: ctx.dot, key, op }
+            },
+            // the key may have been removed and its tombstone dropped by
+            // `gc` here but not yet on other replicas, reset it regardless
+            _ => {
+                let op = f(&V::default(), ctx.clone());
+                Op::Batch {
+                    dot: ctx.dot,
+                    updates: vec![(key.clone(), op)],
+                    rms: vec![(key, self.clock.clone())]
+                }
+            }
+        }
+    }
+
+    /// Remove an entry from the ORMap
+    pub fn rm(&self, key: impl Into<K>, ctx: RmCtx<A>) -> Op<K, V, A> {
+        Op::Rm { clock: ctx.clock, key: key.into() }
+    }
+
+    /// Drop the tombstones of removed entries whose edits are all under
+    /// `stable`. The clock must be causally stable: every replica has
+    /// applied the edits under it along with their removes, and every
+    /// update concurrent with those removes was delivered to this map.
+    pub fn gc(&mut self, stable: &VClock<A>) {
+        self.entries.retain(|_, entry| {
+            !entry.clock.is_empty() || entry.dots > *stable || entry.dots.concurrent(stable)
+        });
+    }
+
+    /// Returns the number of removes waiting on dots this map hasn't seen.
+    pub fn deferred_len(&self) -> usize {
+        self.deferred.iter().map(|(_, keys)| keys.len()).sum()
+    }
+
+    /// Re-apply all deferred removes, used after the entries or the
+    /// deferred removes have been changed wholesale by a merge.
+    fn apply_deferred(&mut self) {
+        for (clock, keys) in self.deferred.take_all() {
+            for key in keys {
+                self.apply_rm(key, &clock);
+            }
+        }
+    }
+
+    /// Apply the deferred removes affected by adding a dot to the entries
+    /// under keys, the removes that cover the dot and the removes that were
+    /// waiting on the dot.
+    fn apply_deferred_dot(&mut self, dot: &Dot<A>, keys: &[K]) {
+        for clock in self.deferred.covering(dot) {
+            for key in keys {
+                let removes_key = self.deferred.get(&clock)
+                    .map(|deferred_keys| deferred_keys.contains(key))
+                    .unwrap_or(false);
+                if removes_key {
+                    self.apply_rm(key.clone(), &clock);
+                }
+            }
+        }
+
+        for (clock, keys) in self.deferred.take_unblocked(dot, &self.clock) {
+            for key in keys {
+                self.apply_rm(key, &clock);
+            }
+        }
+    }
+
+    /// Apply a nested op to the entry under key, witnessed by the given dot.
+    fn apply_up(&mut self, actor: &A, counter: u64, key: K, op: &V::Op) {
+        let entry = self.entries.entry(key)
+            .or_insert_with(Entry::new);
+
+        entry.clock.witness(actor.clone(), counter);
+        entry.dots.witness(actor.clone(), counter);
+        entry.val.apply(op);
+        entry.apply_reset();
+    }
+
+    /// Apply a key removal given a clock.
+    fn apply_rm(&mut self, key: K, clock: &VClock<A>) {
+        if *clock > self.clock || clock.concurrent(&self.clock) {
+            self.deferred.entry(clock).insert(key.clone());
+        }
+
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.clock.subtract(clock);
+        }
+    }
+}
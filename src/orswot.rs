@@ -4,16 +4,23 @@
 //! # Examples
 //!
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
 
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
-use traits::{CvRDT, CmRDT, Causal};
+use traits::{CvRDT, CmRDT, Causal, CausalOp, Diff, Undo};
+use canonical::{self, Canonical};
 use vclock::{VClock, Dot, Actor};
-use ctx::{ReadCtx, AddCtx, RmCtx};
+use ctx::{ReadCtx, ReadCtxRef, AddCtx, RmCtx};
+use map::Path;
+use deferred::{self, Deferred};
+use merkle::{self, Merkle, MerkleTree, Delta};
+use error::Result;
 
 /// Trait bound alias for members in a set
 pub trait Member: Debug + Clone + Hash + Eq + Send + Serialize + DeserializeOwned {}
@@ -26,15 +33,19 @@ impl<T: Debug + Clone + Hash + Eq + Send + Serialize + DeserializeOwned> Member
 pub struct Orswot<M: Member, A: Actor> {
     clock: VClock<A>,
     entries: HashMap<M, VClock<A>>,
-    deferred: HashMap<VClock<A>, HashSet<M>>,
+    deferred: Deferred<A, HashSet<M>>
 }
 
 /// Op's define an edit to an Orswot, Op's must be replayed in the exact order
 /// they were produced to guarantee convergence.
 ///
 /// Op's are idempotent, that is, applying an Op twice will not have an effect
+///
+/// Variants may be added in later versions, matches on an `Op` outside of
+/// this crate need a wildcard arm.
 #[serde(bound(deserialize = ""))]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Op<M: Member, A: Actor> {
     /// Add a member to the set
     Add {
@@ -43,6 +54,13 @@ pub enum Op<M: Member, A: Actor> {
         /// Member to add
         member: M
     },
+    /// Add many members to the set under a single dot
+    AddAll {
+        /// Add witnessing dot
+        dot: Dot<A>,
+        /// Members to add
+        members: Vec<M>
+    },
     /// Remove a member from the set
     Rm {
         /// Remove witnessing clock
@@ -52,6 +70,32 @@ pub enum Op<M: Member, A: Actor> {
     }
 }
 
+/// The members added and removed between two states of an `Orswot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes<M: Member> {
+    /// Members that weren't in the set before
+    pub added: HashSet<M>,
+    /// Members that are no longer in the set
+    pub removed: HashSet<M>
+}
+
+/// Describes how to undo an op on an `Orswot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inverse<M: Member, A: Actor> {
+    /// Remove the additions of a member witnessed by the clock
+    Rm {
+        /// Clock of the additions to remove
+        clock: VClock<A>,
+        /// Member to remove
+        member: M
+    },
+    /// Add a removed member back
+    Add {
+        /// Member to add
+        member: M
+    }
+}
+
 impl<M: Member, A: Actor> Default for Orswot<M, A> {
     fn default() -> Self {
         Orswot::new()
@@ -64,18 +108,10 @@ impl<M: Member, A: Actor> CmRDT for Orswot<M, A> {
     fn apply(&mut self, op: &Self::Op) {
         match op.clone() {
             Op::Add { dot, member } => {
-                if self.clock.get(&dot.actor) >= dot.counter {
-                    // we've already seen this op
-                    return;
-                }
-                {
-                    let mut member_vclock = self.entries.entry(member)
-                        .or_insert_with(|| VClock::new());
-
-                    member_vclock.apply(&dot);
-                }
-                self.clock.apply(&dot);
-                self.apply_deferred();
+                self.apply_add(&dot, vec![member]);
+            },
+            Op::AddAll { dot, members } => {
+                self.apply_add(&dot, members);
             },
             Op::Rm { clock, member } => {
                 self.apply_remove(member, &clock);
@@ -84,75 +120,99 @@ impl<M: Member, A: Actor> CmRDT for Orswot<M, A> {
     }
 }
 
+impl<M: Member, A: Actor> CausalOp<A> for Op<M, A> {
+    fn clock(&self) -> VClock<A> {
+        match self {
+            Op::Add { dot, .. } | Op::AddAll { dot, .. } => VClock::from(dot.clone()),
+            Op::Rm { clock, .. } => clock.clone()
+        }
+    }
+}
+
 impl<M: Member, A: Actor> CvRDT for Orswot<M, A> {
     /// Merge combines another `Orswot` with this one.
+    ///
+    /// The merge happens in place, entries that are the same on both
+    /// sides are left untouched and only the entries novel to other
+    /// are cloned.
     fn merge(&mut self, other: &Self) {
-        let mut other_remaining = other.entries.clone();
-        let mut keep = HashMap::new();
-        for (entry, mut clock) in self.entries.clone().into_iter() {
-            match other.entries.get(&entry).cloned() {
-                None => {
-                    // other doesn't contain this entry because it:
-                    //  1. has witnessed it and dropped it
-                    //  2. hasn't witnessed it
-                    if clock <= other.clock {
-                        // other has seen this entry and dropped it
-                    } else {
-                        // other has not seen this entry, so add it
-                        keep.insert(entry, clock);
-                    }
-                }
-                Some(mut other_entry_clock) => {
-                    // SUBTLE: this entry is present in both orswots, BUT that doesn't mean we
-                    // shouldn't drop it!
-
-                    let mut common = clock.intersection(&other_entry_clock);
-                    clock.subtract(&common);
-                    other_entry_clock.subtract(&common);
-                    clock.subtract(&other.clock);
-                    other_entry_clock.subtract(&self.clock);
-
-                    common.merge(&clock);
-                    common.merge(&other_entry_clock);
-
-                    // Perfectly possible that an item present in both sets
-                    // is dropped
-                    if common.is_empty() {
-                        // we should drop, there are no common dots
-                    } else {
-                        // we should not drop, as there are common clocks
-                        keep.insert(entry.clone(), common);
-                    }
-                    // don't want to consider this again below
-                    other_remaining.remove(&entry).unwrap();
-                }
-            }
+        self.merge_scoped(other, |_| true)
+    }
+}
+
+impl<M: Member, A: Actor> Diff for Orswot<M, A> {
+    type Changes = Changes<M>;
+
+    fn diff(&self, other: &Self) -> Option<Changes<M>> {
+        let added: HashSet<M> = other.entries.keys()
+            .filter(|member| !self.entries.contains_key(member))
+            .cloned()
+            .collect();
+        let removed: HashSet<M> = self.entries.keys()
+            .filter(|member| !other.entries.contains_key(member))
+            .cloned()
+            .collect();
+
+        if added.is_empty() && removed.is_empty() {
+            None
+        } else {
+            Some(Changes { added, removed })
         }
+    }
+}
 
-        for (entry, mut clock) in other_remaining.into_iter() {
-            clock.subtract(&self.clock);
-            if !clock.is_empty() {
-                // other has witnessed a novel addition, so add it
-                keep.insert(entry, clock);
+impl<M: Member, A: Actor> Undo<A> for Orswot<M, A> {
+    type Inverse = Inverse<M, A>;
+
+    /// An add is undone by removing the addition made by the op and the
+    /// later additions of the same actor, concurrent additions of the
+    /// member by other actors survive. A remove is undone by adding the
+    /// member back.
+    ///
+    /// Adds of many members are only used to restore a set, they aren't
+    /// undone.
+    fn inverse(&self, op: &Op<M, A>) -> Option<Inverse<M, A>> {
+        match op {
+            Op::AddAll { .. } => None,
+            Op::Add { dot, member } => {
+                if self.clock.get(&dot.actor) >= dot.counter || self.entries.contains_key(member) {
+                    None
+                } else {
+                    Some(Inverse::Rm { clock: dot.clone().into(), member: member.clone() })
+                }
+            },
+            Op::Rm { clock, member } => {
+                match self.entries.get(member) {
+                    Some(member_clock) if member_clock <= clock => {
+                        Some(Inverse::Add { member: member.clone() })
+                    },
+                    _ => None
+                }
             }
         }
+    }
 
-        // merge deferred removals
-        for (clock, deferred) in other.deferred.iter() {
-            let mut our_deferred =
-                self.deferred.remove(&clock).unwrap_or(HashSet::new());
-            for e in deferred.iter() {
-                our_deferred.insert(e.clone());
+    fn undo(&self, inverse: &Inverse<M, A>, actor: A) -> Op<M, A> {
+        match inverse {
+            Inverse::Rm { clock, member } => {
+                let mut clock = clock.clone();
+                if let Some(member_clock) = self.entries.get(member) {
+                    clock = clock.iter()
+                        .map(|(actor, counter)| {
+                            (actor.clone(), member_clock.get(actor).max(*counter))
+                        })
+                        .collect();
+                }
+                Op::Rm { clock, member: member.clone() }
+            },
+            Inverse::Add { member } => {
+                self.add(member.clone(), self.iter().derive_add_ctx(actor))
             }
-            self.deferred.insert(clock.clone(), our_deferred);
         }
+    }
 
-        self.entries = keep;
-
-        // merge vclocks
-        self.clock.merge(&other.clock);
-
-        self.apply_deferred();
+    fn restore(&self, ctx: AddCtx<A>) -> Option<Op<M, A>> {
+        Some(self.add_all(self.entries.keys().cloned(), ctx))
     }
 }
 
@@ -165,19 +225,88 @@ impl<M: Member, A: Actor> Causal<A> for Orswot<M, A> {
         self.merge(&empty_set);
         self.clock.subtract(&clock);
 
+        // dots the deferred removes were waiting on may be unseen again
+        self.deferred.invalidate();
+
         for (_, member_clock) in self.entries.iter_mut() {
             member_clock.subtract(&clock);
         }
     }
 }
 
+impl<M: Member, A: Actor> Canonical for Orswot<M, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.clock.encode_canonical(buf);
+
+        let entries = self.entries.iter()
+            .map(|(member, clock)| {
+                let mut elem = Vec::new();
+                canonical::encode(member, &mut elem);
+                clock.encode_canonical(&mut elem);
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(entries, buf);
+
+        self.encode_deferred(buf);
+    }
+}
+
+impl<M: Member, A: Actor> Merkle for Orswot<M, A> {
+    fn merkle_tree(&self, depth: u8) -> MerkleTree {
+        let entries = self.entries.iter()
+            .map(|(member, clock)| {
+                let mut key = Vec::new();
+                canonical::encode(member, &mut key);
+                let mut entry = key.clone();
+                clock.encode_canonical(&mut entry);
+                (key, entry)
+            });
+
+        let mut meta = Vec::new();
+        self.clock.encode_canonical(&mut meta);
+        self.encode_deferred(&mut meta);
+
+        MerkleTree::build(cmp::min(depth, merkle::MAX_DEPTH), entries, canonical::fnv1a(&meta))
+    }
+
+    fn delta(&self, tree: &MerkleTree, buckets: &[usize]) -> Result<Delta<Self>> {
+        tree.check()?;
+        let depth = tree.depth();
+        let buckets: BTreeSet<usize> = buckets.iter().cloned().collect();
+        let entries = self.entries.iter()
+            .filter(|(member, _)| buckets.contains(&merkle::bucket_of(member, depth)))
+            .map(|(member, clock)| (member.clone(), clock.clone()))
+            .collect();
+
+        Ok(Delta {
+            depth,
+            bounds: Vec::new(),
+            buckets,
+            state: Orswot {
+                clock: self.clock.clone(),
+                entries,
+                deferred: self.deferred.clone()
+            }
+        })
+    }
+
+    fn merge_delta(&mut self, delta: &Delta<Self>) -> Result<()> {
+        delta.check()?;
+        self.merge_scoped(&delta.state, |member| {
+            delta.buckets.contains(&merkle::bucket_of(member, delta.depth))
+        });
+        Ok(())
+    }
+}
+
 impl<M: Member, A: Actor> Orswot<M, A> {
     /// Returns a new `Orswot` instance.
     pub fn new() -> Self {
         Orswot {
             clock: VClock::new(),
             entries: HashMap::new(),
-            deferred: HashMap::new(),
+            deferred: Deferred::new()
         }
     }
 
@@ -186,20 +315,40 @@ impl<M: Member, A: Actor> Orswot<M, A> {
         Op::Add { dot: ctx.dot, member: member.into() }
     }
 
+    /// Add many members with a single dot.
+    pub fn add_all<I>(&self, members: I, ctx: AddCtx<A>) -> Op<M, A>
+        where I: IntoIterator, I::Item: Into<M>
+    {
+        Op::AddAll { dot: ctx.dot, members: members.into_iter().map(Into::into).collect() }
+    }
+
     /// Remove a member with a witnessing ctx.
     pub fn remove(&self, member: impl Into<M>, ctx: RmCtx<A>) -> Op<M, A> {
         Op::Rm { clock: ctx.clock, member: member.into() }
     }
 
+    /// Add members with a witnessing dot.
+    fn apply_add(&mut self, dot: &Dot<A>, members: Vec<M>) {
+        if self.clock.get(&dot.actor) >= dot.counter {
+            // we've already seen this op
+            return;
+        }
+        for member in members.iter() {
+            self.entries.entry(member.clone())
+                .or_insert_with(VClock::new)
+                .apply(dot);
+        }
+        self.clock.apply(dot);
+        for member in members.iter() {
+            self.apply_deferred_dot(dot, member);
+        }
+    }
+
     /// Remove a member using a witnessing clock.
     fn apply_remove(&mut self, member: impl Into<M>, clock: &VClock<A>) {
         let member: M = member.into();
         if !(clock <= &self.clock) {
-            let mut deferred_drops = self.deferred
-                .remove(&clock)
-                .unwrap_or_else(|| HashSet::new());
-            deferred_drops.insert(member.clone());
-            self.deferred.insert(clock.clone(), deferred_drops);
+            self.deferred.entry(clock).insert(member.clone());
         }
 
         if let Some(mut existing_clock) = self.entries.remove(&member) {
@@ -232,15 +381,155 @@ impl<M: Member, A: Actor> Orswot<M, A> {
         }
     }
 
+    /// Check if the set contains a member without cloning any clocks.
+    pub fn contains_ref(&self, member: &M) -> ReadCtxRef<'_, bool, A> {
+        let member_clock_opt = self.entries.get(member);
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: member_clock_opt
+                .map(Cow::Borrowed)
+                .unwrap_or_else(|| Cow::Owned(VClock::new())),
+            val: member_clock_opt.is_some()
+        }
+    }
+
+    /// Returns an iterator over the current members, unlike `value`
+    /// this doesn't clone the members or the clocks.
+    ///
+    /// ```
+    /// use crdts::{Orswot, CmRDT};
+    /// let mut a: Orswot<String, u8> = Orswot::new();
+    /// let op = a.add("alice", a.iter().derive_add_ctx(1));
+    /// a.apply(&op);
+    /// let members: Vec<&String> = a.iter().val.collect();
+    /// assert_eq!(members, vec![&"alice".to_string()]);
+    /// ```
+    pub fn iter(&self) -> ReadCtxRef<'_, impl Iterator<Item=&M>, A> {
+        ReadCtxRef {
+            add_clock: Cow::Borrowed(&self.clock),
+            rm_clock: Cow::Borrowed(&self.clock),
+            val: self.entries.keys()
+        }
+    }
+
+    /// Returns the number of removes waiting on dots this set hasn't seen.
+    pub fn deferred_len(&self) -> usize {
+        self.deferred.iter().map(|(_, members)| members.len()).sum()
+    }
+
+    /// Merge other into self, only the entries that are in scope are
+    /// merged, the entries out of scope are left as they are.
+    fn merge_scoped<F: Fn(&M) -> bool>(&mut self, other: &Self, in_scope: F) {
+        // collect the entries other has witnessed that we haven't seen,
+        // these can't be inserted until we're done walking our entries.
+        let mut novel = Vec::new();
+        let empty = VClock::new();
+        for (member, other_clock) in other.entries.iter() {
+            if !self.entries.contains_key(member) {
+                let mut clock = VClock::new();
+                deferred::merge_dots(&mut clock, other_clock, &self.clock, &other.clock);
+                if !clock.is_empty() {
+                    // other has witnessed a novel addition, so add it
+                    novel.push((member.clone(), clock));
+                }
+            }
+        }
+
+        let self_clock = &self.clock;
+        self.entries.retain(|member, clock| {
+            if !in_scope(member) {
+                // other may not hold this entry, leave it alone
+                return true;
+            }
+            // other may not contain this entry because it:
+            //  1. has witnessed it and dropped it
+            //  2. hasn't witnessed it
+            // we keep only the dots other has not seen, the dots it has
+            // seen were removed by it and must not block a later remove
+            // of the remaining dots.
+            let other_clock = other.entries.get(member).unwrap_or(&empty);
+            deferred::merge_dots(clock, other_clock, self_clock, &other.clock);
+            !clock.is_empty()
+        });
+
+        self.entries.extend(novel);
+
+        // merge deferred removals
+        for (clock, deferred) in other.deferred.iter() {
+            self.deferred.entry(clock).extend(deferred.iter().cloned());
+        }
+
+        // merge vclocks
+        self.clock.merge(&other.clock);
+
+        self.apply_deferred();
+    }
+
+    /// Re-apply all deferred removes, used after the entries or the
+    /// deferred removes have been changed wholesale by a merge.
     fn apply_deferred(&mut self) {
-        let deferred = self.deferred.clone();
-        self.deferred = HashMap::new();
-        for (clock, entries) in deferred.into_iter() {
-            entries.into_iter()
-                .map(|member| self.apply_remove(member, &clock))
-                .collect()
+        for (clock, members) in self.deferred.take_all() {
+            for member in members {
+                self.apply_remove(member, &clock);
+            }
         }
     }
+
+    /// Apply the deferred removes affected by adding a dot to member,
+    /// the removes that cover the dot and the removes that were waiting
+    /// on the dot.
+    fn apply_deferred_dot(&mut self, dot: &Dot<A>, member: &M) {
+        for clock in self.deferred.covering(dot) {
+            let removes_member = self.deferred.get(&clock)
+                .map(|members| members.contains(member))
+                .unwrap_or(false);
+            if removes_member {
+                self.apply_remove(member.clone(), &clock);
+            }
+        }
+
+        for (clock, members) in self.deferred.take_unblocked(dot, &self.clock) {
+            for member in members {
+                self.apply_remove(member, &clock);
+            }
+        }
+    }
+
+    fn encode_deferred(&self, buf: &mut Vec<u8>) {
+        let deferred = self.deferred.iter()
+            .map(|(clock, members)| {
+                let mut elem = Vec::new();
+                clock.encode_canonical(&mut elem);
+                let members = members.iter()
+                    .map(|member| {
+                        let mut member_elem = Vec::new();
+                        canonical::encode(member, &mut member_elem);
+                        member_elem
+                    })
+                    .collect();
+                canonical::encode_sorted(members, &mut elem);
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(deferred, buf);
+    }
+}
+
+impl<'a, M: Member, A: Actor, O> Path<'a, Orswot<M, A>, A, O> {
+    /// Add a member to the set at the end of the path.
+    pub fn add(self, member: impl Into<M>, actor: A) -> O {
+        self.update(actor, |set, ctx| set.add(member, ctx))
+    }
+
+    /// Remove a member from the set at the end of the path, only the
+    /// additions visible through this path are removed.
+    pub fn remove(self, member: impl Into<M>, actor: A) -> O {
+        let member = member.into();
+        self.update(actor, |set, _| {
+            let ctx = set.contains_ref(&member).derive_rm_ctx();
+            set.remove(member, ctx)
+        })
+    }
 }
 
 #[cfg(test)]
@@ -3,6 +3,7 @@ use std::cmp::Ordering;
 use vclock::{Actor, Dot};
 use gcounter::GCounter;
 use traits::{CvRDT, CmRDT};
+use canonical::Canonical;
 
 /// `PNCounter` allows the counter to be both incremented and decremented
 /// by representing the increments (P) and the decrements (N) in separate
@@ -87,6 +88,12 @@ impl<A: Actor> CmRDT for PNCounter<A> {
     }
 }
 
+impl<A: Actor> Default for PNCounter<A> {
+    fn default() -> Self {
+        PNCounter::new()
+    }
+}
+
 impl<A: Actor> CvRDT for PNCounter<A> {
     fn merge(&mut self, other: &Self) {
         self.p.merge(&other.p);
@@ -94,6 +101,13 @@ impl<A: Actor> CvRDT for PNCounter<A> {
     }
 }
 
+impl<A: Actor> Canonical for PNCounter<A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.p.encode_canonical(buf);
+        self.n.encode_canonical(buf);
+    }
+}
+
 impl<A: Actor> PNCounter<A> {
     /// Produces a new `PNCounter`.
     pub fn new() -> PNCounter<A> {
@@ -0,0 +1,501 @@
+//! A replica bundles a CRDT with the actor editing it and an outbox of the
+//! ops this actor produced that still have to be broadcast to other replicas.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Orswot, Replica};
+//!
+//! let mut alice: Replica<Orswot<String, u8>, u8> = Replica::new(1);
+//! let mut bob: Replica<Orswot<String, u8>, u8> = Replica::new(2);
+//!
+//! alice.add("apple");
+//! bob.add("banana");
+//!
+//! for op in alice.drain_outbox() {
+//!     bob.apply_remote(&op);
+//! }
+//! for op in bob.drain_outbox() {
+//!     alice.apply_remote(&op);
+//! }
+//!
+//! assert_eq!(alice.state(), bob.state());
+//! assert_eq!(alice.state().value().val.len(), 2);
+//! ```
+//!
+//! Subscribers registered on a replica are called with the changes every
+//! local edit, remote op or merge makes to the value of the replica:
+//!
+//! ```
+//! use std::sync::{Arc, Mutex};
+//! use crdts::{Orswot, Replica};
+//!
+//! let mut alice: Replica<Orswot<String, u8>, u8> = Replica::new(1);
+//! let mut bob: Replica<Orswot<String, u8>, u8> = Replica::new(2);
+//!
+//! let added = Arc::new(Mutex::new(Vec::new()));
+//! let added_ref = added.clone();
+//! bob.on_member_added(move |member| added_ref.lock().unwrap().push(member.clone()));
+//!
+//! alice.add("apple");
+//! let ops: Vec<_> = alice.drain_outbox().collect();
+//! for op in ops.iter() {
+//!     bob.apply_remote(op);
+//! }
+//! // ops that were already applied don't change anything
+//! for op in ops.iter() {
+//!     bob.apply_remote(op);
+//! }
+//! assert_eq!(*added.lock().unwrap(), vec!["apple".to_string()]);
+//! ```
+
+use std::any::Any;
+use std::collections::VecDeque;
+use std::collections::vec_deque::Drain;
+use std::fmt;
+
+use traits::{CvRDT, CmRDT, Diff, Undo};
+use vclock::{Dot, Actor};
+use ctx::AddCtx;
+use orswot::{self, Orswot};
+use map::{self, Map};
+use ormap::ORMap;
+use mvreg::{self, MVReg};
+use gcounter::GCounter;
+use pncounter::{self, PNCounter};
+use ewflag::{self, EWFlag};
+use dwflag::{self, DWFlag};
+
+/// `Replica` owns a CRDT and the actor editing it.
+///
+/// Ops produced through the replica are applied locally and queued
+/// in the outbox, they should be drained and broadcast to the other
+/// replicas, which apply them with `apply_remote`.
+pub struct Replica<C: CmRDT, A: Actor> {
+    actor: A,
+    crdt: C,
+    outbox: VecDeque<C::Op>,
+    // notified of every change, set by the first subscriber
+    subscribers: Option<Box<dyn Subscribers<C>>>,
+    // the undo and redo stacks of local edits, set by `enable_undo`
+    undo_log: Option<Box<dyn UndoLog<C, A>>>
+}
+
+/// The undo and redo stacks of a replica, the trait hides the inverses
+/// so `Replica` doesn't require its CRDT to implement `Undo`.
+trait UndoLog<C: CmRDT, A: Actor>: Send {
+    /// Record a local edit, before is the state op is applied to.
+    fn record(&mut self, before: &C, op: &C::Op);
+
+    /// Pop the last edit, returning the op undoing it.
+    fn undo(&mut self, state: &C, actor: A) -> Option<C::Op>;
+
+    /// Pop the last undo, returning the op redoing the edit.
+    fn redo(&mut self, state: &C, actor: A) -> Option<C::Op>;
+
+    /// The number of edits that can be undone and redone
+    fn len(&self) -> (usize, usize);
+}
+
+/// The subscribers of a replica, the trait hides the changes so `Replica`
+/// doesn't require its CRDT to implement `Diff`.
+trait Subscribers<C>: Send {
+    /// Copy the state before a change.
+    fn snapshot(&self, state: &C) -> C;
+
+    /// Hand the changes between the states to every subscriber.
+    fn notify(&mut self, before: &C, after: &C);
+
+    /// The number of subscribers
+    fn len(&self) -> usize;
+
+    /// Used to add subscribers to the list behind the trait.
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+type Subscriber<C> = Box<dyn FnMut(&<C as Diff>::Changes) + Send>;
+
+struct SubscriberList<C: Diff> {
+    subscribers: Vec<Subscriber<C>>
+}
+
+impl<C: Diff + Clone + 'static> Subscribers<C> for SubscriberList<C> {
+    fn snapshot(&self, state: &C) -> C {
+        state.clone()
+    }
+
+    fn notify(&mut self, before: &C, after: &C) {
+        if let Some(changes) = before.diff(after) {
+            for subscriber in self.subscribers.iter_mut() {
+                subscriber(&changes);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+struct UndoStack<C: Undo<A>, A: Actor> {
+    undo: Vec<C::Inverse>,
+    redo: Vec<C::Inverse>
+}
+
+impl<C: Undo<A>, A: Actor> UndoLog<C, A> for UndoStack<C, A> {
+    fn record(&mut self, before: &C, op: &C::Op) {
+        if let Some(inverse) = before.inverse(op) {
+            self.undo.push(inverse);
+            self.redo.clear();
+        }
+    }
+
+    fn undo(&mut self, state: &C, actor: A) -> Option<C::Op> {
+        let op = state.undo(&self.undo.pop()?, actor);
+        self.redo.extend(state.inverse(&op));
+        Some(op)
+    }
+
+    fn redo(&mut self, state: &C, actor: A) -> Option<C::Op> {
+        let op = state.undo(&self.redo.pop()?, actor);
+        self.undo.extend(state.inverse(&op));
+        Some(op)
+    }
+
+    fn len(&self) -> (usize, usize) {
+        (self.undo.len(), self.redo.len())
+    }
+}
+
+impl<C: CmRDT + fmt::Debug, A: Actor> fmt::Debug for Replica<C, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Replica")
+            .field("actor", &self.actor)
+            .field("crdt", &self.crdt)
+            .field("outbox", &self.outbox)
+            .field("subscribers", &self.subscribers.as_ref().map_or(0, |subs| subs.len()))
+            .field("undo", &self.undo_log.as_ref().map(|log| log.len()))
+            .finish()
+    }
+}
+
+/// Cloning a replica doesn't clone its subscribers or undo stacks.
+impl<C: CmRDT + Clone, A: Actor> Clone for Replica<C, A> {
+    fn clone(&self) -> Self {
+        Replica {
+            actor: self.actor.clone(),
+            crdt: self.crdt.clone(),
+            outbox: self.outbox.clone(),
+            subscribers: None,
+            undo_log: None
+        }
+    }
+}
+
+impl<C: CmRDT + Default, A: Actor> Replica<C, A> {
+    /// Returns a replica of a new CRDT edited by the given actor.
+    pub fn new(actor: A) -> Self {
+        Replica::with_state(actor, C::default())
+    }
+}
+
+impl<C: CmRDT, A: Actor> Replica<C, A> {
+    /// Returns a replica of an existing CRDT edited by the given actor.
+    pub fn with_state(actor: A, crdt: C) -> Self {
+        Replica {
+            actor,
+            crdt,
+            outbox: VecDeque::new(),
+            subscribers: None,
+            undo_log: None
+        }
+    }
+
+    /// The actor editing this replica
+    pub fn actor(&self) -> &A {
+        &self.actor
+    }
+
+    /// The current state of the replica
+    pub fn state(&self) -> &C {
+        &self.crdt
+    }
+
+    /// Consumes the replica, returning the CRDT. Ops still in the
+    /// outbox are dropped.
+    pub fn into_state(self) -> C {
+        self.crdt
+    }
+
+    /// Build an op from the current state and this replica's actor,
+    /// apply it locally and queue it in the outbox.
+    pub fn mutate<F>(&mut self, f: F) -> &C::Op
+        where F: FnOnce(&C, A) -> C::Op
+    {
+        let op = f(&self.crdt, self.actor.clone());
+        if let Some(log) = self.undo_log.as_mut() {
+            log.record(&self.crdt, &op);
+        }
+        self.push_local(op)
+    }
+
+    /// Keep an undo stack of the edits made through this replica from
+    /// now on. Edits that don't change the value aren't recorded, new
+    /// edits clear the redo stack.
+    pub fn enable_undo(&mut self) where C: Undo<A> + 'static, A: 'static {
+        if self.undo_log.is_none() {
+            let stack: UndoStack<C, A> = UndoStack { undo: Vec::new(), redo: Vec::new() };
+            self.undo_log = Some(Box::new(stack));
+        }
+    }
+
+    /// Undo the last edit made through this replica that wasn't undone
+    /// yet, the op undoing it is applied and queued like any other edit.
+    /// Returns None if there's nothing to undo or undo isn't enabled.
+    pub fn undo(&mut self) -> Option<&C::Op> {
+        let op = self.undo_log.as_mut()?.undo(&self.crdt, self.actor.clone())?;
+        Some(self.push_local(op))
+    }
+
+    /// Redo the last undone edit, returns None if there's nothing to redo.
+    pub fn redo(&mut self) -> Option<&C::Op> {
+        let op = self.undo_log.as_mut()?.redo(&self.crdt, self.actor.clone())?;
+        Some(self.push_local(op))
+    }
+
+    /// Apply a local op and queue it in the outbox.
+    fn push_local(&mut self, op: C::Op) -> &C::Op {
+        self.change(|crdt| crdt.apply(&op));
+        self.outbox.push_back(op);
+        self.outbox.back().unwrap()
+    }
+
+    /// Apply an op received from another replica, it's not queued.
+    pub fn apply_remote(&mut self, op: &C::Op) {
+        self.change(|crdt| crdt.apply(op));
+    }
+
+    /// Merge the state of another replica.
+    pub fn merge_remote(&mut self, other: &C) where C: CvRDT {
+        self.change(|crdt| crdt.merge(other));
+    }
+
+    /// Register a subscriber, it's called with the changes to the value
+    /// of the replica made by every local edit, remote op and merge.
+    /// Edits that don't change the value, e.g. ops that were already
+    /// applied, don't call it.
+    ///
+    /// While there are subscribers, every change clones the state to
+    /// compare it against the state after the change. The changes are
+    /// worked out once and handed to every subscriber.
+    pub fn subscribe<F>(&mut self, f: F)
+        where C: Diff + Clone + 'static, F: FnMut(&C::Changes) + Send + 'static
+    {
+        let subscribers = self.subscribers.get_or_insert_with(|| {
+            let list: SubscriberList<C> = SubscriberList { subscribers: Vec::new() };
+            Box::new(list)
+        });
+        // only subscribe creates the list, it's always a SubscriberList<C>
+        subscribers.as_any()
+            .downcast_mut::<SubscriberList<C>>()
+            .expect("the subscribers of a replica are a SubscriberList")
+            .subscribers
+            .push(Box::new(f));
+    }
+
+    /// Apply a change to the state, notifying the subscribers.
+    fn change<F: FnOnce(&mut C)>(&mut self, f: F) {
+        match self.subscribers.as_mut() {
+            Some(subscribers) => {
+                let before = subscribers.snapshot(&self.crdt);
+                f(&mut self.crdt);
+                subscribers.notify(&before, &self.crdt);
+            },
+            None => f(&mut self.crdt)
+        }
+    }
+
+    /// The number of ops waiting to be broadcast
+    pub fn outbox_len(&self) -> usize {
+        self.outbox.len()
+    }
+
+    /// Removes the queued ops from the outbox in the order they were
+    /// produced, they must be delivered to other replicas in this order.
+    pub fn drain_outbox(&mut self) -> Drain<'_, C::Op> {
+        self.outbox.drain(..)
+    }
+}
+
+impl<M: orswot::Member, A: Actor> Replica<Orswot<M, A>, A> {
+    /// Add a member to the set.
+    pub fn add(&mut self, member: impl Into<M>) -> &orswot::Op<M, A> {
+        self.mutate(|set, actor| {
+            set.add(member, set.iter().derive_add_ctx(actor))
+        })
+    }
+
+    /// Remove a member from the set, only the additions this replica
+    /// has seen are removed.
+    pub fn remove(&mut self, member: impl Into<M>) -> &orswot::Op<M, A> {
+        let member = member.into();
+        self.mutate(|set, _| {
+            let ctx = set.contains_ref(&member).derive_rm_ctx();
+            set.remove(member, ctx)
+        })
+    }
+}
+
+impl<K: map::Key, V: map::Val<A>, A: Actor> Replica<Map<K, V, A>, A> {
+    /// Update the value under key, see `Map::update`.
+    pub fn update<F>(&mut self, key: impl Into<K>, f: F) -> &map::Op<K, V, A>
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op
+    {
+        self.mutate(|m, actor| m.update(key, m.len().derive_add_ctx(actor), f))
+    }
+
+    /// Remove the entry under key, only the edits this replica has
+    /// seen are removed.
+    pub fn rm(&mut self, key: impl Into<K>) -> &map::Op<K, V, A> {
+        let key = key.into();
+        self.mutate(|m, _| {
+            let ctx = m.get_ref(&key).derive_rm_ctx();
+            m.rm(key, ctx)
+        })
+    }
+}
+
+impl<M: orswot::Member + 'static, A: Actor + 'static> Replica<Orswot<M, A>, A> {
+    /// Register a subscriber called with every member added to the set.
+    pub fn on_member_added<F>(&mut self, mut f: F) where F: FnMut(&M) + Send + 'static {
+        self.subscribe(move |changes: &orswot::Changes<M>| {
+            for member in changes.added.iter() {
+                f(member);
+            }
+        })
+    }
+
+    /// Register a subscriber called with every member removed from the set.
+    pub fn on_member_removed<F>(&mut self, mut f: F) where F: FnMut(&M) + Send + 'static {
+        self.subscribe(move |changes: &orswot::Changes<M>| {
+            for member in changes.removed.iter() {
+                f(member);
+            }
+        })
+    }
+}
+
+impl<K, V, A> Replica<Map<K, V, A>, A>
+    where K: map::Key + 'static, V: map::Val<A> + Diff + 'static, A: Actor + 'static
+{
+    /// Register a subscriber called with every key inserted in the map.
+    pub fn on_key_inserted<F>(&mut self, mut f: F) where F: FnMut(&K) + Send + 'static {
+        self.subscribe(move |changes: &map::Changes<K, V::Changes>| {
+            for key in changes.inserted.iter() {
+                f(key);
+            }
+        })
+    }
+
+    /// Register a subscriber called with every key removed from the map.
+    pub fn on_key_removed<F>(&mut self, mut f: F) where F: FnMut(&K) + Send + 'static {
+        self.subscribe(move |changes: &map::Changes<K, V::Changes>| {
+            for key in changes.removed.iter() {
+                f(key);
+            }
+        })
+    }
+
+    /// Register a subscriber called with every key whose value changed
+    /// while it stayed in the map, along with the changes to the value.
+    pub fn on_key_updated<F>(&mut self, mut f: F)
+        where F: FnMut(&K, &V::Changes) + Send + 'static
+    {
+        self.subscribe(move |changes: &map::Changes<K, V::Changes>| {
+            for (key, val_changes) in changes.updated.iter() {
+                f(key, val_changes);
+            }
+        })
+    }
+}
+
+impl<K: map::Key, V: map::Val<A>, A: Actor> Replica<ORMap<K, V, A>, A> {
+    /// Update the value under key, see `ORMap::update`.
+    pub fn update<F>(&mut self, key: impl Into<K>, f: F) -> &map::Op<K, V, A>
+        where F: FnOnce(&V, AddCtx<A>) -> V::Op
+    {
+        self.mutate(|m, actor| m.update(key, m.len().derive_add_ctx(actor), f))
+    }
+
+    /// Remove the entry under key, only the edits this replica has
+    /// seen are removed.
+    pub fn rm(&mut self, key: impl Into<K>) -> &map::Op<K, V, A> {
+        let key = key.into();
+        self.mutate(|m, _| {
+            let ctx = m.get(&key).derive_rm_ctx();
+            m.rm(key, ctx)
+        })
+    }
+}
+
+impl<V: mvreg::Val, A: Actor> Replica<MVReg<V, A>, A> {
+    /// Set the value of the register, overwriting the values this
+    /// replica has seen.
+    pub fn set(&mut self, val: impl Into<V>) -> &mvreg::Op<V, A> {
+        self.mutate(|reg, actor| reg.set(val, reg.read().derive_add_ctx(actor)))
+    }
+
+    /// Set the register to the resolved value if it holds concurrent
+    /// values, see `MVReg::collapse`.
+    pub fn collapse<R: mvreg::Resolver<V>>(&mut self, resolver: &R) -> Option<&mvreg::Op<V, A>> {
+        let op = self.state().collapse(resolver, self.actor.clone())?;
+        Some(self.mutate(|_, _| op))
+    }
+}
+
+impl<A: Actor> Replica<GCounter<A>, A> {
+    /// Increment the counter.
+    pub fn inc(&mut self) -> &Dot<A> {
+        self.mutate(|counter, actor| counter.inc(actor))
+    }
+}
+
+impl<A: Actor> Replica<PNCounter<A>, A> {
+    /// Increment the counter.
+    pub fn inc(&mut self) -> &pncounter::Op<A> {
+        self.mutate(|counter, actor| counter.inc(actor))
+    }
+
+    /// Decrement the counter.
+    pub fn dec(&mut self) -> &pncounter::Op<A> {
+        self.mutate(|counter, actor| counter.dec(actor))
+    }
+}
+
+impl<A: Actor> Replica<EWFlag<A>, A> {
+    /// Enable the flag.
+    pub fn enable(&mut self) -> &ewflag::Op<A> {
+        self.mutate(|flag, actor| flag.enable(flag.read().derive_add_ctx(actor)))
+    }
+
+    /// Disable the flag, only the enables this replica has seen are undone.
+    pub fn disable(&mut self) -> &ewflag::Op<A> {
+        self.mutate(|flag, _| flag.disable(flag.read().derive_rm_ctx()))
+    }
+}
+
+impl<A: Actor> Replica<DWFlag<A>, A> {
+    /// Enable the flag, only the disables this replica has seen are undone.
+    pub fn enable(&mut self) -> &dwflag::Op<A> {
+        self.mutate(|flag, _| flag.enable(flag.read().derive_rm_ctx()))
+    }
+
+    /// Disable the flag.
+    pub fn disable(&mut self) -> &dwflag::Op<A> {
+        self.mutate(|flag, actor| flag.disable(flag.read().derive_add_ctx(actor)))
+    }
+}
@@ -4,6 +4,7 @@ use serde::Serialize;
 use serde::de::DeserializeOwned;
 
 use vclock::{VClock, Actor};
+use ctx::AddCtx;
 
 /// State based CRDT's replicate by transmitting the entire CRDT state
 pub trait CvRDT {
@@ -73,3 +74,57 @@ pub trait FunkyCmRDT {
     /// Apply an Op to the CRDT
     fn apply(&mut self, op: &Self::Op) -> Result<(), Self::Error>;
 }
+
+/// Diff is implemented by CRDT's that can report the visible changes
+/// between two of their states, e.g. to update a UI after a merge.
+pub trait Diff {
+    /// The changes between two values of the CRDT
+    type Changes: Debug + Clone;
+
+    /// Returns the changes that turn the value of self into the value
+    /// of other, None if both have the same value.
+    fn diff(&self, other: &Self) -> Option<Self::Changes>;
+
+    /// Merge the given CRDT into the current CRDT, returning the changes
+    /// the merge made to the value of the current CRDT.
+    fn merge_with_changes(&mut self, other: &Self) -> Option<Self::Changes>
+        where Self: CvRDT + Clone
+    {
+        let before = self.clone();
+        self.merge(other);
+        before.diff(self)
+    }
+}
+
+/// Op's that carry the causal context they were produced in.
+pub trait CausalOp<A: Actor> {
+    /// The clock this op witnesses. For ops that add to the CRDT this is
+    /// the op's dot, for removes it's the clock of what is removed.
+    fn clock(&self) -> VClock<A>;
+}
+
+/// Undo is implemented by CRDT's whose ops can be undone.
+///
+/// An op is undone by a new op, built from the state at the time of the
+/// undo, that restores the visible value the op changed while keeping the
+/// edits made concurrently or since by other actors.
+pub trait Undo<A: Actor>: CmRDT {
+    /// Describes how to undo an op
+    type Inverse: Debug + Clone + Send;
+
+    /// Returns how to undo op, self is the state op is about to be
+    /// applied to. None if op doesn't change the visible value, or if
+    /// the change can't be undone.
+    fn inverse(&self, op: &Self::Op) -> Option<Self::Inverse>;
+
+    /// Returns the op undoing an op with the given inverse, the op is
+    /// built from the current state and edited by the given actor.
+    fn undo(&self, inverse: &Self::Inverse, actor: A) -> Self::Op;
+
+    /// Returns the op adding the value of self back to a state that lost
+    /// it, e.g. when the remove of the `Map` entry holding self is undone.
+    /// None if the value can't be rebuilt with a single op.
+    fn restore(&self, _ctx: AddCtx<A>) -> Option<Self::Op> {
+        None
+    }
+}
@@ -0,0 +1,339 @@
+//! A replicated tree with move operations.
+//!
+//! This is the move operation CRDT of Kleppmann et al. ("A highly-available
+//! move operation for replicated trees"). Every edit of the tree is a move:
+//! creating a node moves it into the tree, deleting a node moves it out
+//! of the tree and moving a node gives it a new parent. Every move is
+//! witnessed by the dot of an `AddCtx` and carries a Lamport clock
+//! greater than the clocks of the moves its author had seen. Moves are
+//! totally ordered by their timestamps: by Lamport clock, and by dot when
+//! the clocks are equal.
+//!
+//! An op that arrives out of order undoes the ops with greater
+//! timestamps, applies itself and redoes the undone ops, a merge does the
+//! same for all the ops it hasn't seen at once. A move that
+//! would make a node its own ancestor is skipped when it's (re)done, so
+//! concurrent moves can never form a cycle and every replica ends up
+//! skipping the same moves.
+//!
+//! A node whose parent was never moved into the tree is a root, replicas
+//! usually agree on the id of a root node ahead of time. The children of
+//! a deleted node are deleted along with it, unless they are moved out.
+//!
+//! Every op is kept in the tree's log, the log grows with every op.
+//!
+//! # Examples
+//!
+//! ```
+//! use crdts::{Tree, CvRDT, CmRDT};
+//!
+//! let root = 0u32;
+//! let mut a: Tree<u32, String, u8> = Tree::new();
+//! let op = a.create(1u32, root, "docs", a.len().derive_add_ctx(1));
+//! a.apply(&op);
+//! let op = a.create(2u32, root, "photos", a.len().derive_add_ctx(1));
+//! a.apply(&op);
+//!
+//! // concurrently move docs into photos and photos into docs
+//! let mut b = a.clone();
+//! let op = a.mv(1u32, 2u32, "docs", a.len().derive_add_ctx(1));
+//! a.apply(&op);
+//! let op = b.mv(2u32, 1u32, "photos", b.len().derive_add_ctx(2));
+//! b.apply(&op);
+//!
+//! // the move with the smaller timestamp wins, the other would form a cycle
+//! a.merge(&b);
+//! b.merge(&a);
+//! assert_eq!(a, b);
+//! assert_eq!(a.parent(&1), Some(&2));
+//! assert_eq!(a.parent(&2), Some(&root));
+//! ```
+
+use std::cmp::{self, Ordering};
+use std::collections::HashMap;
+
+use traits::{CvRDT, CmRDT};
+use canonical::{self, Canonical};
+use vclock::{Dot, VClock, Actor};
+use ctx::{ReadCtx, AddCtx};
+use lwwreg::Val;
+use orswot::Member;
+
+/// `Tree` is a replicated tree of nodes supporting moves.
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tree<N: Member, M: Val, A: Actor> {
+    // the dots of the ops applied so far
+    clock: VClock<A>,
+    // the greatest Lamport clock of the ops applied so far
+    counter: u64,
+    nodes: HashMap<N, Node<N, M>>,
+    // every op applied, ordered by timestamp
+    log: Vec<LogEntry<N, M, A>>
+}
+
+/// Op's move a node of a `Tree`
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Op<N: Member, M: Val, A: Actor> {
+    /// The dot witnessing the move, ops with the same dot are the same op
+    pub dot: Dot<A>,
+    /// The Lamport clock of the move
+    pub counter: u64,
+    /// The node to move
+    pub child: N,
+    /// The new parent of the node, None deletes the node
+    pub parent: Option<N>,
+    /// Metadata of the node, e.g. its name
+    pub meta: M
+}
+
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Node<N: Member, M: Val> {
+    // None if the node was deleted
+    parent: Option<N>,
+    meta: M
+}
+
+#[serde(bound(deserialize = ""))]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LogEntry<N: Member, M: Val, A: Actor> {
+    op: Op<N, M, A>,
+    // the child before the op was applied, None if it wasn't in the tree
+    old: Option<Node<N, M>>
+}
+
+impl<N: Member, M: Val, A: Actor> Default for Tree<N, M, A> {
+    fn default() -> Self {
+        Tree::new()
+    }
+}
+
+impl<N: Member, M: Val, A: Actor> CmRDT for Tree<N, M, A> {
+    type Op = Op<N, M, A>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        if self.logged(op) {
+            // we've already seen this op
+            return;
+        }
+        self.replay(vec![op.clone()]);
+    }
+}
+
+impl<N: Member, M: Val, A: Actor> CvRDT for Tree<N, M, A> {
+    /// Merge applies the ops of other's log this tree hasn't seen, the
+    /// ops with greater timestamps are undone and redone only once.
+    fn merge(&mut self, other: &Self) {
+        let novel = other.log.iter()
+            .filter(|entry| !self.logged(&entry.op))
+            .map(|entry| entry.op.clone())
+            .collect();
+        self.replay(novel);
+    }
+}
+
+impl<N: Member, M: Val, A: Actor> Canonical for Tree<N, M, A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        self.clock.encode_canonical(buf);
+        canonical::encode(&self.counter, buf);
+
+        let nodes = self.nodes.iter()
+            .map(|(child, node)| {
+                let mut elem = Vec::new();
+                canonical::encode(child, &mut elem);
+                canonical::encode(node, &mut elem);
+                elem
+            })
+            .collect();
+        canonical::encode_sorted(nodes, buf);
+
+        // the log is ordered by timestamp
+        canonical::encode(&self.log, buf);
+    }
+}
+
+impl<N: Member, M: Val, A: Actor> Tree<N, M, A> {
+    /// Returns a new, empty tree
+    pub fn new() -> Self {
+        Tree {
+            clock: VClock::new(),
+            counter: 0,
+            nodes: HashMap::new(),
+            log: Vec::new()
+        }
+    }
+
+    /// Create a node under parent, creating a node is moving it into the
+    /// tree.
+    pub fn create(&self, child: impl Into<N>, parent: impl Into<N>, meta: impl Into<M>, ctx: AddCtx<A>) -> Op<N, M, A> {
+        self.mv(child, parent, meta, ctx)
+    }
+
+    /// Move a node under a new parent, giving it new metadata.
+    pub fn mv(&self, child: impl Into<N>, parent: impl Into<N>, meta: impl Into<M>, ctx: AddCtx<A>) -> Op<N, M, A> {
+        Op {
+            dot: ctx.dot,
+            counter: self.counter + 1,
+            child: child.into(),
+            parent: Some(parent.into()),
+            meta: meta.into()
+        }
+    }
+
+    /// Delete a node along with its children, returns None if the node
+    /// isn't in the tree.
+    pub fn delete(&self, child: impl Into<N>, ctx: AddCtx<A>) -> Option<Op<N, M, A>> {
+        let child = child.into();
+        let meta = self.nodes.get(&child)?.meta.clone();
+        Some(Op {
+            dot: ctx.dot,
+            counter: self.counter + 1,
+            child,
+            parent: None,
+            meta
+        })
+    }
+
+    /// Returns true if the node was created and neither it nor any of its
+    /// ancestors were deleted.
+    pub fn contains(&self, node: &N) -> bool {
+        let mut node = node;
+        let mut in_tree = false;
+        while let Some(entry) = self.nodes.get(node) {
+            match entry.parent {
+                Some(ref parent) => node = parent,
+                None => return false
+            }
+            in_tree = true;
+        }
+        in_tree
+    }
+
+    /// Returns the number of nodes in the tree, derive the ctx of a move
+    /// from it.
+    pub fn len(&self) -> ReadCtx<usize, A> {
+        ReadCtx {
+            add_clock: self.clock.clone(),
+            rm_clock: self.clock.clone(),
+            val: self.nodes.keys().filter(|node| self.contains(node)).count()
+        }
+    }
+
+    /// The parent of a node, None if the node is a root or was deleted.
+    pub fn parent(&self, node: &N) -> Option<&N> {
+        self.nodes.get(node).and_then(|entry| entry.parent.as_ref())
+    }
+
+    /// The metadata of a node, deleted nodes keep their metadata.
+    pub fn meta(&self, node: &N) -> Option<&M> {
+        self.nodes.get(node).map(|entry| &entry.meta)
+    }
+
+    /// Returns an iterator over the children of a node, in no particular
+    /// order.
+    pub fn children<'a>(&'a self, parent: &'a N) -> impl Iterator<Item=&'a N> {
+        self.nodes.iter()
+            .filter(move |(_, entry)| entry.parent.as_ref() == Some(parent))
+            .map(|(child, _)| child)
+    }
+
+    /// Returns the number of ops in the log
+    pub fn log_len(&self) -> usize {
+        self.log.len()
+    }
+
+    /// Returns true if the op is in the log.
+    fn logged(&self, op: &Op<N, M, A>) -> bool {
+        self.log.binary_search_by(|entry| cmp_ops(&entry.op, op)).is_ok()
+    }
+
+    /// Apply ops this tree hasn't seen: the logged ops with greater
+    /// timestamps are undone, then all of them are (re)done in timestamp
+    /// order.
+    fn replay(&mut self, mut novel: Vec<Op<N, M, A>>) {
+        novel.sort_by(cmp_ops);
+        novel.dedup_by(|a, b| a.dot == b.dot);
+        let pos = match novel.first() {
+            Some(first) => match self.log.binary_search_by(|entry| cmp_ops(&entry.op, first)) {
+                Ok(pos) | Err(pos) => pos
+            },
+            None => return
+        };
+
+        let undone = self.log.split_off(pos);
+        for entry in undone.iter().rev() {
+            self.undo(entry);
+        }
+
+        for op in novel.iter() {
+            self.clock.apply(&op.dot);
+            self.counter = cmp::max(self.counter, op.counter);
+        }
+        let mut undone = undone.into_iter().map(|entry| entry.op).peekable();
+        let mut novel = novel.into_iter().peekable();
+        loop {
+            let next_is_novel = match (undone.peek(), novel.peek()) {
+                (Some(undone_op), Some(novel_op)) => cmp_ops(novel_op, undone_op) == Ordering::Less,
+                (None, Some(_)) => true,
+                (_, None) => false
+            };
+            let op = if next_is_novel { novel.next() } else { undone.next() };
+            match op {
+                Some(op) => {
+                    let entry = self.do_op(op);
+                    self.log.push(entry);
+                },
+                None => break
+            }
+        }
+    }
+
+    /// Returns true if ancestor is node or one of its ancestors.
+    fn is_ancestor(&self, ancestor: &N, node: &N) -> bool {
+        let mut node = node;
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            match self.nodes.get(node).and_then(|entry| entry.parent.as_ref()) {
+                Some(parent) => node = parent,
+                None => return false
+            }
+        }
+    }
+
+    /// Apply a move, unless it would make the child its own ancestor.
+    fn do_op(&mut self, op: Op<N, M, A>) -> LogEntry<N, M, A> {
+        let old = self.nodes.get(&op.child).cloned();
+        let forms_cycle = match op.parent {
+            Some(ref parent) => self.is_ancestor(&op.child, parent),
+            None => false
+        };
+        if !forms_cycle {
+            self.nodes.insert(op.child.clone(), Node {
+                parent: op.parent.clone(),
+                meta: op.meta.clone()
+            });
+        }
+        LogEntry { op, old }
+    }
+
+    /// Restore the child of a logged op to its state before the op.
+    fn undo(&mut self, entry: &LogEntry<N, M, A>) {
+        match entry.old {
+            Some(ref node) => {
+                self.nodes.insert(entry.op.child.clone(), node.clone());
+            },
+            None => {
+                self.nodes.remove(&entry.op.child);
+            }
+        }
+    }
+}
+
+/// Ops are ordered by Lamport clock, then by dot.
+fn cmp_ops<N: Member, M: Val, A: Actor>(a: &Op<N, M, A>, b: &Op<N, M, A>) -> Ordering {
+    (a.counter, &a.dot.actor, a.dot.counter).cmp(&(b.counter, &b.dot.actor, b.dot.counter))
+}
@@ -30,7 +30,7 @@ impl<A: Ord + Clone + Hash + Send + Serialize + DeserializeOwned + Debug> Actor
 
 /// Dot is a version marker for a single actor
 #[serde(bound(deserialize = ""))]
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Dot<A: Actor> {
     /// The actor identifier
     pub actor: A,
@@ -128,6 +128,12 @@ impl<A: Actor> CmRDT for VClock<A> {
     }
 }
 
+impl<A: Actor> CausalOp<A> for Dot<A> {
+    fn clock(&self) -> VClock<A> {
+        VClock::from(self.clone())
+    }
+}
+
 impl<A: Actor> CvRDT for VClock<A> {
     fn merge(&mut self, other: &VClock<A>) {
         for (actor, counter) in other.dots.iter() {
@@ -136,6 +142,13 @@ impl<A: Actor> CvRDT for VClock<A> {
     }
 }
 
+impl<A: Actor> Canonical for VClock<A> {
+    fn encode_canonical(&self, buf: &mut Vec<u8>) {
+        // the dots are kept in a BTreeMap, so they're already ordered
+        canonical::encode(self, buf)
+    }
+}
+
 impl<A: Actor> VClock<A> {
     /// Returns a new `VClock` instance.
     pub fn new() -> VClock<A> {
@@ -0,0 +1,171 @@
+use crdts::*;
+
+fn build_sets(members: &[(u8, u8)]) -> Vec<Orswot<u8, u8>> {
+    let mut sets: Vec<Orswot<u8, u8>> = (0..3).map(|_| Orswot::new()).collect();
+    for (i, (actor, member)) in members.iter().enumerate() {
+        let set = &mut sets[*actor as usize % 3];
+        let op = if i % 3 == 2 {
+            set.remove(*member, set.contains(member).derive_rm_ctx())
+        } else {
+            set.add(*member, set.value().derive_add_ctx(*actor))
+        };
+        set.apply(&op);
+    }
+    sets
+}
+
+quickcheck! {
+    fn prop_converged_orswots_encode_equally(members: Vec<(u8, u8)>) -> bool {
+        let sets = build_sets(&members);
+
+        let mut forward = Orswot::new();
+        for set in sets.iter() {
+            forward.merge(set);
+        }
+        let mut backward = Orswot::new();
+        for set in sets.iter().rev() {
+            backward.merge(set);
+        }
+
+        forward == backward
+            && forward.digest() == backward.digest()
+            && forward.to_canonical_binary() == backward.to_canonical_binary()
+    }
+
+    fn prop_canonical_encoding_decodes(members: Vec<(u8, u8)>) -> bool {
+        let sets = build_sets(&members);
+        let decoded: Vec<Orswot<u8, u8>> = sets.iter()
+            .map(|set| from_binary(set.to_canonical_binary()).unwrap())
+            .collect();
+        decoded == sets
+    }
+}
+
+#[test]
+fn test_digest_differs_on_divergence() {
+    let mut a: Orswot<u8, u8> = Orswot::new();
+    let b = a.clone();
+    assert_eq!(a.digest(), b.digest());
+
+    let op = a.add(1, a.value().derive_add_ctx(1));
+    a.apply(&op);
+    assert_ne!(a.digest(), b.digest());
+}
+
+#[test]
+fn test_nested_map_digest() {
+    type TestMap = Map<u8, Orswot<u8, u8>, u8>;
+    let mut a: TestMap = Map::new();
+    let mut b: TestMap = Map::new();
+
+    for member in 0..8 {
+        let op = a.at(1).add(member, 1);
+        a.apply(&op);
+        let op = b.at(1).add(member + 8, 2);
+        b.apply(&op);
+    }
+    let op = b.rm(2, RmCtx { clock: Dot { actor: 3, counter: 1 }.into() });
+    b.apply(&op);
+
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+
+    assert_eq!(ab.digest(), ba.digest());
+    assert_eq!(ab.to_canonical_binary(), ba.to_canonical_binary());
+
+    let decoded: TestMap = from_binary(ab.to_canonical_binary()).unwrap();
+    assert_eq!(decoded, ab);
+}
+
+#[test]
+fn test_mvreg_digest_ignores_value_order() {
+    let mut a: MVReg<u8, u8> = MVReg::new();
+    let mut b: MVReg<u8, u8> = MVReg::new();
+    let op = a.set(1, a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let op = b.set(2, b.read().derive_add_ctx(2));
+    b.apply(&op);
+
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+
+    assert_eq!(ab.digest(), ba.digest());
+}
+
+#[test]
+fn test_gset_and_lwwset_digests() {
+    let mut a: GSet<u8> = GSet::new();
+    let mut b: GSet<u8> = GSet::new();
+    a.insert(1);
+    b.insert(2);
+    a.merge(b.clone());
+    b.merge(a.clone());
+    assert_eq!(a.digest(), b.digest());
+
+    let mut a: LWWSet<u8, u64> = LWWSet::new();
+    let mut b: LWWSet<u8, u64> = LWWSet::new();
+    for member in 0..16 {
+        let op = a.add(member, member as u64);
+        a.apply(&op).unwrap();
+        let op = b.rm(15 - member, member as u64 + 1);
+        b.apply(&op).unwrap();
+    }
+    a.merge(&b).unwrap();
+    b.merge(&a).unwrap();
+    assert_eq!(a.to_canonical_binary(), b.to_canonical_binary());
+
+    let decoded: LWWSet<u8, u64> = from_binary(a.to_canonical_binary()).unwrap();
+    assert_eq!(decoded, a);
+}
+
+#[test]
+fn test_multimap_digest() {
+    let mut a: MultiMap<u8, u8, u8> = MultiMap::new();
+    let mut b: MultiMap<u8, u8, u8> = MultiMap::new();
+    for val in 0..8 {
+        let op = a.insert(1, val, a.get(&1).derive_add_ctx(1));
+        a.apply(&op);
+        let op = b.insert(1, 15 - val, b.get(&1).derive_add_ctx(2));
+        b.apply(&op);
+    }
+    let rm_ctx = RmCtx { clock: Dot { actor: 3, counter: 1 }.into() };
+    let op = b.remove(2, 1, rm_ctx);
+    b.apply(&op);
+
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+
+    assert_eq!(ab.digest(), ba.digest());
+    assert_eq!(ab.to_canonical_binary(), ba.to_canonical_binary());
+
+    let decoded: MultiMap<u8, u8, u8> = from_binary(ab.to_canonical_binary()).unwrap();
+    assert_eq!(decoded, ab);
+}
+
+#[test]
+fn test_tree_digest() {
+    let mut a: Tree<u8, u8, u8> = Tree::new();
+    let mut b: Tree<u8, u8, u8> = Tree::new();
+    for node in 1..8 {
+        let op = a.create(node, 0, node, a.len().derive_add_ctx(1));
+        a.apply(&op);
+        let op = b.create(node + 8, 0, node, b.len().derive_add_ctx(2));
+        b.apply(&op);
+    }
+
+    let mut ab = a.clone();
+    ab.merge(&b);
+    let mut ba = b.clone();
+    ba.merge(&a);
+
+    assert_eq!(ab.digest(), ba.digest());
+
+    let decoded: Tree<u8, u8, u8> = from_binary(ab.to_canonical_binary()).unwrap();
+    assert_eq!(decoded, ab);
+}
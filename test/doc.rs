@@ -0,0 +1,311 @@
+use serde_json::Value;
+use crdts::{Doc, CvRDT, CmRDT, to_binary, from_binary};
+use crdts::doc::{Op, Seg, Kind};
+
+const REPLICAS: u8 = 3;
+
+fn key(k: &str) -> Vec<Seg> {
+    vec![Seg::from(k)]
+}
+
+/// Runs a session of edits and merges between a few replicas, returning
+/// the replicas along with every op in the order it was produced. Keys
+/// change kinds and get removed along the way.
+fn simulate(prims: Vec<(u8, u8, u8, u8)>) -> (Vec<Doc<u8>>, Vec<Op<u8>>) {
+    let mut replicas: Vec<Doc<u8>> = (0..REPLICAS).map(|_| Doc::new()).collect();
+    let mut ops = Vec::new();
+    for (replica, choice, k, val) in prims {
+        let r = (replica % REPLICAS) as usize;
+        let actor = r as u8;
+        let doc = &replicas[r];
+        let ctx = doc.read().derive_add_ctx(actor);
+        let name = ["a", "b", "c"][k as usize % 3];
+        let path = key(name);
+        let op = match choice % 11 {
+            0 => {
+                let other = replicas[(r + 1) % REPLICAS as usize].clone();
+                replicas[r].merge(&other);
+                None
+            },
+            1 => doc.update(&path, ctx, |d, ctx| d.set(&json!(val), ctx)),
+            2 => doc.update(&path, ctx, |d, ctx| d.set(&json!(val.to_string()), ctx)),
+            3 => doc.update(&path, ctx, |d, ctx| d.push(&json!(val), ctx)),
+            4 => doc.get(&path).and_then(|d| d.remove_at(val as usize % 2))
+                .and_then(|op| doc.update(&path, ctx, |_, _| op)),
+            5 => doc.update(&path, ctx, |d, ctx| d.insert_text(0, "ab", ctx)),
+            6 => doc.get(&path).map(|d| d.remove_text(0..1))
+                .and_then(|op| doc.update(&path, ctx, |_, _| op)),
+            7 => doc.update(&path, ctx, |d, ctx| d.inc(val as u64 % 4, ctx)),
+            8 => doc.update(&path, ctx, |d, ctx| d.set(&json!({ "x": val, "y": [val] }), ctx)),
+            9 => doc.update(&[Seg::from(name), Seg::from("x")], ctx, |d, ctx| d.set(&json!(val), ctx)),
+            _ => Some(doc.remove(name, doc.read().derive_rm_ctx()))
+        };
+        if let Some(op) = op {
+            replicas[r].apply(&op);
+            ops.push(op);
+        }
+    }
+    (replicas, ops)
+}
+
+quickcheck! {
+    fn prop_merge_converges(prims: Vec<(u8, u8, u8, u8)>) -> bool {
+        let (replicas, _) = simulate(prims);
+        let mut forward = Doc::new();
+        for replica in replicas.iter() {
+            forward.merge(replica);
+        }
+        let mut backward = Doc::new();
+        for replica in replicas.iter().rev() {
+            backward.merge(replica);
+        }
+        let mut twice = forward.clone();
+        twice.merge(&backward);
+        forward.to_json() == backward.to_json()
+            && forward.conflicts() == backward.conflicts()
+            && forward.to_json() == twice.to_json()
+    }
+
+    fn prop_ops_agree_with_merge(prims: Vec<(u8, u8, u8, u8)>) -> bool {
+        let (replicas, ops) = simulate(prims);
+        let mut merged = Doc::new();
+        for replica in replicas.iter() {
+            merged.merge(replica);
+        }
+        let mut applied = Doc::new();
+        for op in ops.iter() {
+            applied.apply(op);
+        }
+        applied.to_json() == merged.to_json() && applied.conflicts() == merged.conflicts()
+    }
+}
+
+#[test]
+fn test_json_round_trip() {
+    let json = json!({
+        "name": "crdts",
+        "version": 1.5,
+        "downloads": 1000000,
+        "yanked": false,
+        "license": null,
+        "keywords": ["crdt", "replication", []],
+        "deps": { "serde": { "version": "1.0" } },
+        "features": {}
+    });
+    let doc: Doc<u8> = Doc::from_json(&json, 1);
+    assert_eq!(doc.to_json(), json);
+    assert_eq!(doc.read().val, json);
+
+    let decoded: Doc<u8> = from_binary(to_binary(&doc)).unwrap();
+    assert_eq!(decoded, doc);
+}
+
+#[test]
+fn test_empty_doc_reads_as_null() {
+    let doc: Doc<u8> = Doc::new();
+    assert_eq!(doc.to_json(), Value::Null);
+    assert_eq!(doc.kind(), None);
+    assert!(doc.conflicts().is_empty());
+}
+
+#[test]
+fn test_update_creates_missing_keys() {
+    let mut doc: Doc<u8> = Doc::new();
+    let path = vec![Seg::from("a"), Seg::from("b")];
+    let op = doc.update(&path, doc.read().derive_add_ctx(1), |d, ctx| d.set(&json!(3), ctx)).unwrap();
+    doc.apply(&op);
+    assert_eq!(doc.to_json(), json!({ "a": { "b": 3 } }));
+    assert_eq!(doc.get(&path).map(|d| d.to_json()), Some(json!(3)));
+    assert_eq!(doc.get(&[Seg::from("a")]).and_then(|d| d.kind()), Some(Kind::Map));
+
+    // indices must exist and paths can't go through other kinds
+    let ctx = doc.read().derive_add_ctx(1);
+    assert!(doc.update(&[Seg::from("a"), Seg::from(0)], ctx.clone(), |d, ctx| d.set(&json!(1), ctx)).is_none());
+    assert!(doc.update(&[Seg::from("a"), Seg::from("b"), Seg::from("c")], ctx, |d, ctx| d.set(&json!(1), ctx)).is_none());
+    assert!(doc.get(&[Seg::from("a"), Seg::from("b"), Seg::from("c")]).is_none());
+}
+
+#[test]
+fn test_maps_created_along_a_path_conflict_with_other_kinds() {
+    let mut a: Doc<u8> = Doc::new();
+    let mut b = a.clone();
+
+    let op = a.update(&[Seg::from("o"), Seg::from("x")], a.read().derive_add_ctx(1), |d, ctx| d.set(&json!(1), ctx)).unwrap();
+    a.apply(&op);
+    let op = b.put("o", &json!(5), b.read().derive_add_ctx(2));
+    b.apply(&op);
+
+    a.merge(&b);
+    assert_eq!(a.to_json(), json!({ "o": { "x": 1 } }));
+    let conflicts = a.conflicts();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path, key("o"));
+    assert_eq!(conflicts[0].values, vec![json!({ "x": 1 }), json!(5)]);
+}
+
+#[test]
+fn test_list_edits() {
+    let mut doc: Doc<u8> = Doc::from_json(&json!({ "todo": ["a", "c"] }), 1);
+    let path = key("todo");
+    let op = doc.update(&path, doc.read().derive_add_ctx(1), |d, ctx| d.insert(1, &json!("b"), ctx)).unwrap();
+    doc.apply(&op);
+    let op = doc.update(&path, doc.read().derive_add_ctx(1), |d, ctx| d.push(&json!({ "d": 4 }), ctx)).unwrap();
+    doc.apply(&op);
+    assert_eq!(doc.to_json(), json!({ "todo": ["a", "b", "c", { "d": 4 }] }));
+
+    let op = doc.update(&[Seg::from("todo"), Seg::from(3), Seg::from("d")], doc.read().derive_add_ctx(1), |d, ctx| {
+        d.set(&json!(5), ctx)
+    }).unwrap();
+    doc.apply(&op);
+    let op = doc.get(&path).unwrap().remove_at(0).unwrap();
+    let op = doc.update(&path, doc.read().derive_add_ctx(1), |_, _| op).unwrap();
+    doc.apply(&op);
+    assert_eq!(doc.to_json(), json!({ "todo": ["b", "c", { "d": 5 }] }));
+}
+
+#[test]
+fn test_concurrent_text_edits() {
+    let mut a: Doc<u8> = Doc::new();
+    let op = a.insert_text(0, "helo", a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let mut b = a.clone();
+
+    let op = a.insert_text(3, "l", a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let op = b.insert_text(4, " world", b.read().derive_add_ctx(2));
+    b.apply(&op);
+    let op = b.remove_text(0..1);
+    b.apply(&op);
+
+    a.merge(&b);
+    b.merge(&a);
+    assert_eq!(a.to_json(), json!("ello world"));
+    assert_eq!(b.to_json(), json!("ello world"));
+}
+
+#[test]
+fn test_concurrent_counter_steps_add_up() {
+    let mut a: Doc<u8> = Doc::new();
+    let op = a.inc(5, a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let mut b = a.clone();
+
+    let op = a.inc(2, a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let op = b.dec(4, b.read().derive_add_ctx(2));
+    b.apply(&op);
+
+    a.merge(&b);
+    assert_eq!(a.to_json(), json!(3));
+
+    // writing a value steps the counter back to zero
+    let op = a.set(&json!("done"), a.read().derive_add_ctx(1));
+    a.apply(&op);
+    assert_eq!(a.to_json(), json!("done"));
+    let op = a.inc(1, a.read().derive_add_ctx(1));
+    a.apply(&op);
+    assert_eq!(a.to_json(), json!(1));
+}
+
+#[test]
+fn test_counter_saturates() {
+    let mut a: Doc<u8> = Doc::new();
+    let op = a.inc(u64::MAX, a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let mut b = a.clone();
+    assert_eq!(a.to_json(), json!(i64::MAX));
+
+    let op = a.inc(1, a.read().derive_add_ctx(1));
+    a.apply(&op);
+    assert_eq!(a.to_json(), json!(i64::MAX));
+
+    let op = b.dec(u64::MAX, b.read().derive_add_ctx(2));
+    b.apply(&op);
+    let op = b.dec(u64::MAX, b.read().derive_add_ctx(2));
+    b.apply(&op);
+    assert_eq!(b.to_json(), json!(-i64::MAX));
+
+    a.merge(&b);
+    assert_eq!(a.to_json(), json!(1 - i64::MAX));
+}
+
+#[test]
+fn test_concurrent_writes_are_exposed_as_conflicts() {
+    let mut a: Doc<u8> = Doc::from_json(&json!({ "title": "draft" }), 1);
+    let mut b = a.clone();
+
+    let op = a.put("title", &json!(["a", "list"]), a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let op = b.put("title", &json!("final"), b.read().derive_add_ctx(2));
+    b.apply(&op);
+
+    a.merge(&b);
+    // lists come before registers
+    assert_eq!(a.to_json(), json!({ "title": ["a", "list"] }));
+    let conflicts = a.conflicts();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path, key("title"));
+    assert_eq!(conflicts[0].values, vec![json!(["a", "list"]), json!("final")]);
+
+    // writing the doc again resolves the conflict
+    let op = a.put("title", &json!("final"), a.read().derive_add_ctx(1));
+    a.apply(&op);
+    assert_eq!(a.to_json(), json!({ "title": "final" }));
+    assert!(a.conflicts().is_empty());
+}
+
+#[test]
+fn test_update_survives_concurrent_remove() {
+    let mut a: Doc<u8> = Doc::from_json(&json!({ "user": { "name": "alice", "age": 30 } }), 1);
+    let mut b = a.clone();
+
+    let op = a.remove("user", a.read().derive_rm_ctx());
+    a.apply(&op);
+    assert_eq!(a.to_json(), json!({}));
+
+    let op = b.update(&[Seg::from("user"), Seg::from("age")], b.read().derive_add_ctx(2), |d, ctx| {
+        d.set(&json!(31), ctx)
+    }).unwrap();
+    b.apply(&op);
+
+    a.merge(&b);
+    b.merge(&a);
+    // the edits seen by the remove are gone
+    assert_eq!(a.to_json(), json!({ "user": { "age": 31 } }));
+    assert_eq!(a.to_json(), b.to_json());
+}
+
+#[test]
+fn test_merge_agrees_with_ops_after_remove() {
+    let mut r1: Doc<u8> = Doc::new();
+    let mut r2: Doc<u8> = Doc::new();
+    let mut ops = Vec::new();
+
+    let op = r1.update(&[Seg::from("k"), Seg::from("x")], r1.read().derive_add_ctx(1), |d, ctx| d.set(&json!(1), ctx)).unwrap();
+    r1.apply(&op);
+    ops.push(op);
+    let op = r2.update(&[Seg::from("k"), Seg::from("y")], r2.read().derive_add_ctx(2), |d, ctx| d.set(&json!(2), ctx)).unwrap();
+    r2.apply(&op);
+    ops.push(op);
+
+    r1.merge(&r2);
+    let op = r1.remove("k", r1.read().derive_rm_ctx());
+    r1.apply(&op);
+    ops.push(op);
+
+    let op = r2.update(&[Seg::from("k"), Seg::from("z")], r2.read().derive_add_ctx(2), |d, ctx| d.set(&json!(3), ctx)).unwrap();
+    r2.apply(&op);
+    ops.push(op);
+    let op = r1.update(&[Seg::from("k"), Seg::from("w")], r1.read().derive_add_ctx(1), |d, ctx| d.set(&json!(4), ctx)).unwrap();
+    r1.apply(&op);
+    ops.push(op);
+
+    let mut merged = r1.clone();
+    merged.merge(&r2);
+    let mut applied = Doc::new();
+    for op in ops.iter() {
+        applied.apply(op);
+    }
+    assert_eq!(applied.to_json(), json!({ "k": { "w": 4, "z": 3 } }));
+    assert_eq!(merged.to_json(), applied.to_json());
+}
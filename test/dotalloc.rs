@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crdts::*;
+use crdts::dotalloc::DotAllocator;
+
+fn lease_path(name: &str) -> PathBuf {
+    let path = ::std::env::temp_dir()
+        .join(format!("crdts-test-dotalloc-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_counters_are_not_reused_after_crash() {
+    let path = lease_path("crash");
+    let mut handed_out = Vec::new();
+
+    for _ in 0..5 {
+        // every "process" starts from an empty state and hands out a few dots
+        let mut alloc = DotAllocator::open(1u8, &path, 4).unwrap();
+        let mut clock = VClock::new();
+        for _ in 0..3 {
+            let dot = alloc.next_dot(&clock).unwrap();
+            clock.apply(&dot);
+            handed_out.push(dot.counter);
+        }
+    }
+
+    let mut sorted = handed_out.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted, handed_out);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_leases_differing_by_extension_dont_collide() {
+    let path = lease_path("ext").with_extension("a");
+    let other_path = path.with_extension("tmp");
+    fs::write(&other_path, b"not a lease").unwrap();
+
+    let mut alloc = DotAllocator::open(1u8, &path, 4).unwrap();
+    alloc.next_dot(&VClock::new()).unwrap();
+    assert_eq!(fs::read(&other_path).unwrap(), b"not a lease");
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&other_path).unwrap();
+}
+
+#[test]
+fn test_dots_stay_ahead_of_the_clock() {
+    let path = lease_path("ahead");
+    let mut alloc = DotAllocator::open(1u8, &path, 10).unwrap();
+
+    // the state has seen counters of this actor from elsewhere
+    let clock: VClock<u8> = vec![(1, 42)].into_iter().collect();
+    assert_eq!(alloc.next_dot(&clock).unwrap().counter, 43);
+    assert_eq!(alloc.next_dot(&clock).unwrap().counter, 44);
+    drop(alloc);
+
+    let mut alloc = DotAllocator::open(1u8, &path, 10).unwrap();
+    assert_eq!(alloc.next_dot(&VClock::new()).unwrap().counter, 53);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_running_out_of_counters_fails() {
+    let path = lease_path("exhausted");
+    let mut alloc = DotAllocator::open(1u8, &path, u64::MAX).unwrap();
+
+    // the lease is cut short at the largest counter
+    assert_eq!(alloc.next_dot(&VClock::new()).unwrap().counter, 1);
+    let clock: VClock<u8> = vec![(1, u64::MAX - 1)].into_iter().collect();
+    assert_eq!(alloc.next_dot(&clock).unwrap().counter, u64::MAX);
+    assert!(alloc.next_dot(&clock).is_err());
+    drop(alloc);
+
+    // the lease on disk covers every counter
+    let mut alloc = DotAllocator::open(1u8, &path, 10).unwrap();
+    assert!(alloc.next_dot(&VClock::new()).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_lease_of_another_actor_is_rejected() {
+    let path = lease_path("actor");
+    let mut alloc = DotAllocator::open(1u8, &path, 10).unwrap();
+    alloc.next_dot(&VClock::new()).unwrap();
+
+    let err = DotAllocator::open(2u8, &path, 10).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_derive_add_ctx_with() {
+    let path = lease_path("ctx");
+    let mut alloc = DotAllocator::open(1u8, &path, 10).unwrap();
+
+    let mut reg: MVReg<u8, u8> = MVReg::new();
+    let op = reg.set(1, reg.read().derive_add_ctx_with(&mut alloc).unwrap());
+    reg.apply(&op);
+    let op = reg.set(2, reg.read().derive_add_ctx_with(&mut alloc).unwrap());
+    reg.apply(&op);
+    assert_eq!(reg.read().val, vec![2]);
+
+    let mut set: Orswot<u8, u8> = Orswot::new();
+    let ctx = set.iter().derive_add_ctx_with(&mut alloc).unwrap();
+    assert_eq!(ctx.dot, Dot { actor: 1, counter: 3 });
+    let op = set.add(1, ctx);
+    set.apply(&op);
+    assert!(set.contains(&1).val);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,80 @@
+use crdts::*;
+
+#[test]
+fn test_disable_enable() {
+    let mut flag = DWFlag::<u8>::new();
+    assert!(flag.read().val);
+
+    let op = flag.disable(flag.read().derive_add_ctx(1));
+    flag.apply(&op);
+    assert!(!flag.read().val);
+
+    let op = flag.enable(flag.read().derive_rm_ctx());
+    flag.apply(&op);
+    assert!(flag.read().val);
+}
+
+#[test]
+fn test_concurrent_disable_wins() {
+    let mut a = DWFlag::<u8>::new();
+    let op = a.disable(a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let mut b = a.clone();
+
+    // a enables while b concurrently disables again
+    let a_op = a.enable(a.read().derive_rm_ctx());
+    a.apply(&a_op);
+    let b_op = b.disable(b.read().derive_add_ctx(2));
+    b.apply(&b_op);
+
+    // exchange ops
+    a.apply(&b_op);
+    b.apply(&a_op);
+
+    assert_eq!(a, b);
+    assert!(!a.read().val);
+}
+
+const ACTOR_MAX: u8 = 4;
+
+quickcheck! {
+    fn prop_merge_converges(steps: Vec<(u8, u8, bool)>) -> bool {
+        // every actor has a replica, a step either enables or disables
+        // the flag of an actor or merges the replica of another actor
+        // into it.
+        let mut replicas: Vec<DWFlag<u8>> = (0..ACTOR_MAX).map(|_| DWFlag::new()).collect();
+        let mut ops = Vec::new();
+        for (actor, other, enable) in steps {
+            let (actor, other) = (actor % ACTOR_MAX, other % ACTOR_MAX);
+            if actor == other {
+                let flag = &replicas[actor as usize];
+                let op = if enable {
+                    flag.enable(flag.read().derive_rm_ctx())
+                } else {
+                    flag.disable(flag.read().derive_add_ctx(actor))
+                };
+                replicas[actor as usize].apply(&op);
+                ops.push(op);
+            } else {
+                let other = replicas[other as usize].clone();
+                replicas[actor as usize].merge(&other);
+            }
+        }
+
+        // the replicas converge whatever the order of the merges, and
+        // to the flag all the ops are applied to
+        let mut forward = DWFlag::new();
+        for replica in replicas.iter() {
+            forward.merge(replica);
+        }
+        let mut backward = DWFlag::new();
+        for replica in replicas.iter().rev() {
+            backward.merge(replica);
+        }
+        let mut applied = DWFlag::new();
+        for op in ops.iter() {
+            applied.apply(op);
+        }
+        forward == backward && forward.read().val == applied.read().val
+    }
+}
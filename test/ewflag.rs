@@ -0,0 +1,104 @@
+use crdts::*;
+
+#[test]
+fn test_enable_disable() {
+    let mut flag = EWFlag::<u8>::new();
+    assert!(!flag.read().val);
+
+    let op = flag.enable(flag.read().derive_add_ctx(1));
+    flag.apply(&op);
+    assert!(flag.read().val);
+
+    // replaying an op is a nop
+    flag.apply(&op);
+    assert!(flag.read().val);
+
+    let op = flag.disable(flag.read().derive_rm_ctx());
+    flag.apply(&op);
+    assert!(!flag.read().val);
+}
+
+#[test]
+fn test_concurrent_enable_wins() {
+    let mut a = EWFlag::<u8>::new();
+    let op = a.enable(a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let mut b = a.clone();
+
+    // a disables while b concurrently re-enables
+    let a_op = a.disable(a.read().derive_rm_ctx());
+    a.apply(&a_op);
+    let b_op = b.enable(b.read().derive_add_ctx(2));
+    b.apply(&b_op);
+
+    let a_snapshot = a.clone();
+    a.merge(&b);
+    b.merge(&a_snapshot);
+
+    assert_eq!(a, b);
+    assert!(a.read().val);
+}
+
+#[test]
+fn test_nested_in_map() {
+    let mut m: Map<u8, EWFlag<u8>, u8> = Map::new();
+    let op = m.update(
+        7,
+        m.get(&7).derive_add_ctx(1),
+        |flag, ctx| flag.enable(ctx)
+    );
+    m.apply(&op);
+    assert_eq!(m.get(&7).val.map(|flag| flag.read().val), Some(true));
+
+    let op = m.update(
+        7,
+        m.get(&7).derive_add_ctx(1),
+        |flag, _| flag.disable(flag.read().derive_rm_ctx())
+    );
+    m.apply(&op);
+    assert_eq!(m.get(&7).val.map(|flag| flag.read().val), Some(false));
+}
+
+const ACTOR_MAX: u8 = 4;
+
+quickcheck! {
+    fn prop_merge_converges(steps: Vec<(u8, u8, bool)>) -> bool {
+        // every actor has a replica, a step either enables or disables
+        // the flag of an actor or merges the replica of another actor
+        // into it.
+        let mut replicas: Vec<EWFlag<u8>> = (0..ACTOR_MAX).map(|_| EWFlag::new()).collect();
+        let mut ops = Vec::new();
+        for (actor, other, enable) in steps {
+            let (actor, other) = (actor % ACTOR_MAX, other % ACTOR_MAX);
+            if actor == other {
+                let flag = &replicas[actor as usize];
+                let op = if enable {
+                    flag.enable(flag.read().derive_add_ctx(actor))
+                } else {
+                    flag.disable(flag.read().derive_rm_ctx())
+                };
+                replicas[actor as usize].apply(&op);
+                ops.push(op);
+            } else {
+                let other = replicas[other as usize].clone();
+                replicas[actor as usize].merge(&other);
+            }
+        }
+
+        // the replicas converge whatever the order of the merges, and
+        // to the flag all the ops are applied to
+        let mut forward = EWFlag::new();
+        for replica in replicas.iter() {
+            forward.merge(replica);
+        }
+        let mut backward = EWFlag::new();
+        for replica in replicas.iter().rev() {
+            backward.merge(replica);
+        }
+        let mut applied = EWFlag::new();
+        for op in ops.iter() {
+            applied.apply(op);
+        }
+        forward == backward && forward.read().val == applied.read().val
+    }
+}
@@ -0,0 +1,195 @@
+use crdts::{ExpiringMap, ExpiringSet, MVReg, Orswot, CvRDT, CmRDT};
+
+type Sessions = ExpiringMap<u8, MVReg<u8, u8>, u8, u64>;
+type Tags = ExpiringMap<u8, Orswot<u8, u8>, u8, u64>;
+
+fn put(map: &mut Sessions, key: u8, val: u8, actor: u8, expiry: u64) {
+    let op = map.update(
+        key,
+        map.get(&key, &0).derive_add_ctx(actor),
+        &0,
+        expiry,
+        |reg, ctx| reg.set(val, ctx)
+    );
+    map.apply(&op);
+}
+
+fn tag(map: &mut Tags, key: u8, member: u8, actor: u8, now: u64, expiry: u64) {
+    let op = map.update(
+        key,
+        map.get(&key, &now).derive_add_ctx(actor),
+        &now,
+        expiry,
+        |set, ctx| set.add(member, ctx)
+    );
+    map.apply(&op);
+}
+
+fn gc(map: &mut Sessions, now: u64) {
+    for op in map.gc(&now) {
+        map.apply(&op);
+    }
+}
+
+quickcheck! {
+    fn prop_gc_converges(
+        puts_a: Vec<(u8, u8, u8)>,
+        puts_b: Vec<(u8, u8, u8)>,
+        now_a: u8,
+        now_b: u8
+    ) -> bool {
+        let mut a = Sessions::new();
+        let mut b = Sessions::new();
+        for (key, val, expiry) in puts_a {
+            put(&mut a, key % 8, val, 1, expiry as u64);
+        }
+        for (key, val, expiry) in puts_b {
+            put(&mut b, key % 8, val, 2, expiry as u64);
+        }
+        gc(&mut a, now_a as u64);
+        gc(&mut b, now_b as u64);
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+
+        let now = ::std::cmp::max(now_a, now_b) as u64;
+        let mut ab_gc = ab.clone();
+        gc(&mut ab_gc, now);
+        let mut ba_gc = ba.clone();
+        gc(&mut ba_gc, now);
+
+        ab == ba && ab_gc == ba_gc
+            && ab.iter(&now).val.count() == ab_gc.iter(&now).val.count()
+    }
+}
+
+#[test]
+fn test_reads_filter_expired_entries() {
+    let mut map = Sessions::new();
+    put(&mut map, 1, 10, 1, 5);
+    put(&mut map, 2, 20, 1, 9);
+
+    assert_eq!(map.len(&4).val, 2);
+    assert_eq!(map.len(&5).val, 1);
+    assert_eq!(map.get(&1, &5).val, None);
+    assert_eq!(map.get(&2, &5).val.map(|reg| reg.read().val), Some(vec![20]));
+    assert_eq!(
+        map.iter(&5).val.map(|(k, _)| *k).collect::<Vec<_>>(),
+        vec![2]
+    );
+
+    // the expired entry is still there until it's collected
+    assert_eq!(map.expiry(&1), Some(5));
+    assert!(!map.get(&1, &5).rm_clock.is_empty());
+}
+
+#[test]
+fn test_expiry_only_moves_forward() {
+    let mut map = Sessions::new();
+    put(&mut map, 1, 10, 1, 9);
+    put(&mut map, 1, 11, 1, 5);
+    assert_eq!(map.expiry(&1), Some(9));
+
+    put(&mut map, 1, 12, 1, 20);
+    assert_eq!(map.expiry(&1), Some(20));
+    assert_eq!(map.get(&1, &10).val.map(|reg| reg.read().val), Some(vec![12]));
+}
+
+#[test]
+fn test_gc_is_deterministic() {
+    let mut a = Sessions::new();
+    put(&mut a, 1, 10, 1, 5);
+    put(&mut a, 2, 20, 1, 9);
+    put(&mut a, 3, 30, 1, 3);
+    let mut b = a.clone();
+
+    let ops = a.gc(&6);
+    assert_eq!(ops, b.gc(&6));
+    assert_eq!(ops.len(), 2);
+
+    for op in ops.iter() {
+        a.apply(op);
+        a.apply(op);
+    }
+    gc(&mut b, 6);
+    assert_eq!(a, b);
+    assert_eq!(a.expiry(&1), None);
+    assert_eq!(a.expiry(&2), Some(9));
+    assert!(a.gc(&6).is_empty());
+}
+
+#[test]
+fn test_update_racing_gc_survives() {
+    let mut a = Sessions::new();
+    put(&mut a, 1, 10, 1, 5);
+    let mut b = a.clone();
+
+    // b keeps the session alive while a collects it
+    put(&mut b, 1, 11, 2, 50);
+    let gc_ops = a.gc(&5);
+    for op in gc_ops.iter() {
+        a.apply(op);
+    }
+    assert_eq!(a.get(&1, &5).val, None);
+
+    a.merge(&b);
+    for op in gc_ops.iter() {
+        b.apply(op);
+    }
+    assert_eq!(a, b);
+    assert_eq!(a.expiry(&1), Some(50));
+    assert_eq!(a.get(&1, &5).val.map(|reg| reg.read().val), Some(vec![11]));
+}
+
+#[test]
+fn test_update_of_expired_entry_starts_over() {
+    let mut a = Tags::new();
+    tag(&mut a, 1, 10, 1, 0, 5);
+    tag(&mut a, 1, 11, 1, 0, 5);
+    let b = a.clone();
+
+    // the entry expired, the new member must not bring the old ones back
+    tag(&mut a, 1, 12, 1, 6, 20);
+    assert_eq!(a.get(&1, &6).val.map(|set| set.value().val.into_iter().collect::<Vec<_>>()), Some(vec![12]));
+    assert_eq!(a.expiry(&1), Some(20));
+
+    // replicas that still hold the expired contents converge
+    let mut ba = b.clone();
+    ba.merge(&a);
+    let mut ab = a.clone();
+    ab.merge(&b);
+    assert_eq!(ab, ba);
+    assert_eq!(ab.get(&1, &6).val.map(|set| set.value().val.into_iter().collect::<Vec<_>>()), Some(vec![12]));
+}
+
+#[test]
+fn test_expiring_set() {
+    let mut set: ExpiringSet<u8, u8, u64> = ExpiringSet::new();
+    let op = set.add(1, set.len(&0).derive_add_ctx(1), &0, 5);
+    set.apply(&op);
+    let op = set.add(2, set.len(&0).derive_add_ctx(1), &0, 9);
+    set.apply(&op);
+
+    assert!(set.contains(&1, &4).val);
+    assert!(!set.contains(&1, &5).val);
+    assert_eq!(set.len(&5).val, 1);
+    assert_eq!(set.iter(&5).val.cloned().collect::<Vec<_>>(), vec![2]);
+
+    for op in set.gc(&5) {
+        set.apply(&op);
+    }
+    assert_eq!(set.expiry(&1), None);
+    assert_eq!(set.expiry(&2), Some(9));
+
+    // re-adding an expired member starts it over
+    let op = set.add(2, set.len(&10).derive_add_ctx(1), &10, 20);
+    set.apply(&op);
+    assert!(set.contains(&2, &10).val);
+
+    let op = set.rm(2, set.contains(&2, &10).derive_rm_ctx());
+    set.apply(&op);
+    assert!(!set.contains(&2, &10).val);
+    assert_eq!(set.len(&0).val, 0);
+}
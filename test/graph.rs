@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+use crdts::{Graph, CvRDT, CmRDT, Dot, VClock};
+use crdts::graph::Op;
+
+const ACTOR_MAX: u8 = 6;
+
+fn build_ops(op_prims: Vec<(u8, u8, u8, u8, u64)>) -> Vec<(u8, Op<u8, u8>)> {
+    op_prims.into_iter()
+        .map(|(actor, from, to, choice, counter)| {
+            let (from, to) = (from % 4, to % 4);
+            let dot = Dot { actor, counter };
+            let op = match choice % 4 {
+                0 => Op::AddVertex { dot, vertex: from },
+                1 => Op::AddEdge { dot, from, to },
+                2 => Op::RmVertex { clock: dot.into(), vertex: from, edges: vec![(from, to), (to, from)] },
+                _ => Op::RmEdge { clock: dot.into(), from, to }
+            };
+            (actor, op)
+        })
+        .collect()
+}
+
+fn set(vals: &[&str]) -> HashSet<String> {
+    vals.iter().map(|val| val.to_string()).collect()
+}
+
+quickcheck! {
+    fn prop_merge_converges(op_prims: Vec<(u8, u8, u8, u8, u64)>) -> bool {
+        let ops = build_ops(op_prims);
+        let mut result = None;
+        for i in 2..ACTOR_MAX {
+            let mut witnesses: Vec<Graph<u8, u8>> = (0..i).map(|_| Graph::new()).collect();
+            for (actor, op) in ops.iter() {
+                witnesses[(actor % i) as usize].apply(op);
+            }
+            let mut merged = Graph::new();
+            for witness in witnesses.iter() {
+                merged.merge(witness);
+            }
+            merged.merge(&Graph::new());
+
+            match result {
+                Some(ref prev) if prev != &merged => return false,
+                Some(_) => (),
+                None => result = Some(merged)
+            }
+        }
+        true
+    }
+
+    fn prop_edges_never_dangle(op_prims: Vec<(u8, u8, u8, u8, u64)>) -> bool {
+        let mut graph: Graph<u8, u8> = Graph::new();
+        for (_, op) in build_ops(op_prims) {
+            graph.apply(&op);
+            let vertices = graph.vertices().val;
+            let dangling = graph.edges().val.iter()
+                .any(|(from, to)| !vertices.contains(from) || !vertices.contains(to));
+            if dangling {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[test]
+fn test_add_and_remove() {
+    let mut graph: Graph<String, u8> = Graph::new();
+    let op = graph.add_vertex("a", graph.vertices().derive_add_ctx(1));
+    graph.apply(&op);
+    for (from, to) in [("a", "b"), ("a", "c"), ("c", "a")] {
+        let op = graph.add_edge(from, to, graph.vertices().derive_add_ctx(1));
+        graph.apply(&op);
+    }
+    assert_eq!(graph.vertices().val, set(&["a", "b", "c"]));
+    assert_eq!(graph.neighbors(&"a".into()).val, set(&["b", "c"]));
+    assert_eq!(graph.neighbors(&"c".into()).val, set(&["a"]));
+
+    // b was only added through its edge
+    let ctx = graph.contains_edge(&"a".into(), &"b".into()).derive_rm_ctx();
+    let op = graph.remove_edge("a", "b", ctx);
+    graph.apply(&op);
+    assert_eq!(graph.vertices().val, set(&["a", "c"]));
+
+    let op = graph.remove_vertex("a", graph.contains_vertex(&"a".into()).derive_rm_ctx());
+    graph.apply(&op);
+    assert_eq!(graph.vertices().val, HashSet::new());
+    assert_eq!(graph.edges().val, HashSet::new());
+}
+
+#[test]
+fn test_concurrent_edge_keeps_removed_vertex() {
+    let mut a: Graph<String, u8> = Graph::new();
+    let op = a.add_edge("x", "y", a.vertices().derive_add_ctx(1));
+    a.apply(&op);
+    let mut b = a.clone();
+
+    let op = a.remove_vertex("y", a.contains_vertex(&"y".into()).derive_rm_ctx());
+    a.apply(&op);
+    let op = b.add_edge("y", "z", b.vertices().derive_add_ctx(2));
+    b.apply(&op);
+
+    let mut merged = a.clone();
+    merged.merge(&b);
+    b.merge(&a);
+    assert_eq!(merged, b);
+    assert_eq!(merged.vertices().val, set(&["y", "z"]));
+    assert!(!merged.contains_edge(&"x".into(), &"y".into()).val);
+    assert_eq!(merged.neighbors(&"y".into()).val, set(&["z"]));
+}
+
+#[test]
+fn test_vertex_remove_ahead_of_edge_add() {
+    let mut a: Graph<u8, u8> = Graph::new();
+    let add = a.add_edge(1, 2, a.vertices().derive_add_ctx(1));
+    a.apply(&add);
+    let rm = a.remove_vertex(2, a.contains_vertex(&2).derive_rm_ctx());
+    a.apply(&rm);
+
+    // the remove reaches b before the edge it removes
+    let mut b: Graph<u8, u8> = Graph::new();
+    b.apply(&rm);
+    b.apply(&add);
+    assert_eq!(a, b);
+    assert!(b.vertices().val.is_empty());
+
+    let clock: VClock<u8> = vec![(1, 1)].into_iter().collect();
+    assert_eq!(b.vertices().add_clock, clock);
+}
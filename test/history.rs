@@ -0,0 +1,121 @@
+use crdts::*;
+use crdts::orswot::Op;
+
+fn build_ops(op_prims: Vec<(u8, u8, u8, u64)>) -> Vec<Op<u8, u8>> {
+    op_prims.into_iter().map(|(actor, member, choice, counter)| {
+        match choice % 2 {
+            0 => Op::Add { member, dot: Dot { actor, counter } },
+            _ => Op::Rm { member, clock: Dot { actor, counter }.into() }
+        }
+    }).collect()
+}
+
+quickcheck! {
+    fn prop_versions_read_past_states(op_prims: Vec<(u8, u8, u8, u64)>) -> bool {
+        let mut set: History<Orswot<u8, u8>> = History::new();
+        let mut seen = VClock::new();
+        let mut versions = Vec::new();
+        for op in build_ops(op_prims) {
+            set.apply(&op);
+            if op.clock() > seen || op.clock().concurrent(&seen) {
+                // the op starts a new version
+                seen.merge(&op.clock());
+                versions.push((seen.clone(), set.state().clone()));
+            }
+        }
+        versions.into_iter().all(|(version, state)| set.at(&version).val == state)
+    }
+
+    fn prop_empty_cut_reads_empty_state(op_prims: Vec<(u8, u8, u8, u64)>) -> bool {
+        let mut set: History<Orswot<u8, u8>> = History::new();
+        for op in build_ops(op_prims) {
+            set.apply(&op);
+        }
+        set.at(&VClock::new()).val == Orswot::new()
+    }
+}
+
+#[test]
+fn test_orswot_versions() {
+    let mut set: History<Orswot<u8, u8>> = History::new();
+    let mut versions = Vec::new();
+    for member in 0..5 {
+        let op = set.state().add(member, set.state().value().derive_add_ctx(1));
+        set.apply(&op);
+        versions.push(set.state().value().add_clock);
+    }
+
+    for (i, version) in versions.iter().enumerate() {
+        let past = set.at(version);
+        assert_eq!(past.add_clock, *version);
+        assert_eq!(past.val.value().val, (0..i as u8 + 1).collect());
+    }
+}
+
+#[test]
+fn test_concurrent_edits_are_cut_per_actor() {
+    let mut set: History<Orswot<u8, u8>> = History::new();
+    let (mut a, mut b) = (Orswot::<u8, u8>::new(), Orswot::<u8, u8>::new());
+    for member in 0..3 {
+        let op = a.add(member, a.value().derive_add_ctx(1));
+        a.apply(&op);
+        set.apply(&op);
+        let op = b.add(member + 10, b.value().derive_add_ctx(2));
+        b.apply(&op);
+        set.apply(&op);
+    }
+
+    let cut: VClock<u8> = vec![(1, 1), (2, 3)].into_iter().collect();
+    assert_eq!(
+        set.at(&cut).val.value().val,
+        vec![0, 10, 11, 12].into_iter().collect()
+    );
+}
+
+#[test]
+fn test_map_history() {
+    let mut m: History<Map<u8, MVReg<u8, u8>, u8>> = History::new();
+    let op = m.state().update(1, m.state().len().derive_add_ctx(1), |reg, ctx| reg.set(10, ctx));
+    m.apply(&op);
+    let op = m.state().update(2, m.state().len().derive_add_ctx(1), |reg, ctx| reg.set(20, ctx));
+    m.apply(&op);
+    let version = m.state().len().add_clock;
+    let op = m.state().update(1, m.state().len().derive_add_ctx(1), |reg, ctx| reg.set(11, ctx));
+    m.apply(&op);
+    let op = m.state().rm(2, m.state().get(&2).derive_rm_ctx());
+    m.apply(&op);
+
+    let past = m.at(&version);
+    assert_eq!(past.val.get(&1).val.map(|reg| reg.read().val), Some(vec![10]));
+    assert_eq!(past.val.get(&2).val.map(|reg| reg.read().val), Some(vec![20]));
+
+    // the remove followed the last edit, it's left out of the latest version
+    let latest = m.at(&m.state().len().add_clock);
+    assert_eq!(latest.val.get(&2).val.map(|reg| reg.read().val), Some(vec![20]));
+    let op = m.state().update(3, m.state().len().derive_add_ctx(1), |reg, ctx| reg.set(30, ctx));
+    m.apply(&op);
+    let latest = m.at(&m.state().len().add_clock);
+    assert_eq!(latest.val, *m.state());
+    assert_eq!(m.state().get(&1).val.map(|reg| reg.read().val), Some(vec![11]));
+    assert_eq!(m.state().get(&2).val, None);
+}
+
+#[test]
+fn test_branch_off_history() {
+    let mut reg: History<MVReg<u8, u8>> = History::new();
+    let op = reg.state().set(1, reg.state().read().derive_add_ctx(1));
+    reg.apply(&op);
+    let version = reg.state().read().add_clock;
+    let op = reg.state().set(2, reg.state().read().derive_add_ctx(1));
+    reg.apply(&op);
+
+    // a new actor overwrites the historical value, concurrently with the later write
+    let past = reg.at(&version);
+    assert_eq!(past.val.read().val, vec![1]);
+    let branch = past.val.set(3, past.derive_add_ctx(2));
+    reg.apply(&branch);
+
+    let mut vals = reg.state().read().val;
+    vals.sort();
+    assert_eq!(vals, vec![2, 3]);
+}
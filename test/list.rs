@@ -0,0 +1,199 @@
+use crdts::{List, MVReg, VClock, Causal, CvRDT, CmRDT};
+use crdts::list::Op;
+
+type TList = List<MVReg<u8, u8>, u8>;
+type TOp = Op<MVReg<u8, u8>, u8>;
+
+const REPLICAS: u8 = 3;
+
+fn values(list: &TList) -> Vec<Vec<u8>> {
+    list.iter().val
+        .map(|reg| {
+            let mut vals = reg.read().val;
+            vals.sort();
+            vals
+        })
+        .collect()
+}
+
+fn chars(list: &List<MVReg<char, u8>, u8>) -> String {
+    list.iter().val.map(|reg| reg.read().val[0]).collect()
+}
+
+fn text(list: &List<MVReg<char, u8>, u8>, index: usize, s: &str, actor: u8) -> Op<MVReg<char, u8>, u8> {
+    let ctx = list.len().derive_add_ctx(actor);
+    let ops = s.chars().map(|c| MVReg::new().set(c, ctx.clone())).collect();
+    list.insert_all(index, ctx, ops)
+}
+
+/// Runs a session of edits and merges between a few replicas, returning
+/// the replicas along with every op in the order it was produced.
+fn simulate(prims: Vec<(u8, u8, u8, u8)>) -> (Vec<TList>, Vec<TOp>) {
+    let mut replicas: Vec<TList> = (0..REPLICAS).map(|_| List::new()).collect();
+    let mut ops = Vec::new();
+    for (replica, choice, index, val) in prims {
+        let r = (replica % REPLICAS) as usize;
+        let actor = r as u8;
+        let list = &replicas[r];
+        let len = list.len().val;
+        let index = if len == 0 { 0 } else { index as usize % (len + 1) };
+        let op = match choice % 5 {
+            0 | 1 => Some(list.insert(index, list.len().derive_add_ctx(actor), |reg, ctx| reg.set(val, ctx))),
+            2 => list.update(index, list.len().derive_add_ctx(actor), |reg, ctx| reg.set(val, ctx)),
+            3 => list.remove(index),
+            _ => {
+                let other = replicas[(r + 1) % REPLICAS as usize].clone();
+                replicas[r].merge(&other);
+                None
+            }
+        };
+        if let Some(op) = op {
+            replicas[r].apply(&op);
+            ops.push(op);
+        }
+    }
+    (replicas, ops)
+}
+
+quickcheck! {
+    fn prop_merge_converges(prims: Vec<(u8, u8, u8, u8)>) -> bool {
+        let (replicas, _) = simulate(prims);
+        let mut forward = TList::new();
+        for replica in replicas.iter() {
+            forward.merge(replica);
+        }
+        let mut backward = TList::new();
+        for replica in replicas.iter().rev() {
+            backward.merge(replica);
+        }
+        let mut twice = forward.clone();
+        twice.merge(&backward);
+        forward == backward && forward == twice
+    }
+
+    fn prop_ops_agree_with_merge(prims: Vec<(u8, u8, u8, u8)>) -> bool {
+        let (replicas, ops) = simulate(prims);
+        let mut merged = TList::new();
+        for replica in replicas.iter() {
+            merged.merge(replica);
+        }
+        let mut applied = TList::new();
+        for op in ops.iter() {
+            applied.apply(op);
+            applied.apply(op);
+        }
+        values(&applied) == values(&merged)
+    }
+}
+
+#[test]
+fn test_insert_get_remove() {
+    let mut list = TList::new();
+    for (index, val) in [(0, 1), (1, 3), (1, 2)] {
+        let op = list.insert(index, list.len().derive_add_ctx(1), |reg, ctx| reg.set(val, ctx));
+        list.apply(&op);
+    }
+    assert_eq!(values(&list), vec![vec![1], vec![2], vec![3]]);
+    assert_eq!(list.get(1).val.map(|reg| reg.read().val), Some(vec![2]));
+
+    let op = list.remove(1).unwrap();
+    list.apply(&op);
+    assert_eq!(values(&list), vec![vec![1], vec![3]]);
+    assert!(list.remove(2).is_none());
+
+    let op = list.remove_range(0..5);
+    list.apply(&op);
+    assert_eq!(list.len().val, 0);
+}
+
+#[test]
+fn test_concurrent_inserts_dont_interleave() {
+    let mut a = List::new();
+    let op = text(&a, 0, "ab", 1);
+    a.apply(&op);
+    let mut b = a.clone();
+
+    let op = text(&a, 1, "xyz", 1);
+    a.apply(&op);
+    let op = text(&b, 1, "123", 2);
+    b.apply(&op);
+
+    let mut ab = a.clone();
+    ab.merge(&b);
+    b.merge(&a);
+    assert_eq!(ab, b);
+    let merged = chars(&ab);
+    assert!(merged == "a123xyzb" || merged == "axyz123b");
+}
+
+#[test]
+fn test_insert_after_removed_elem() {
+    let mut a = List::new();
+    let op = text(&a, 0, "ab", 1);
+    a.apply(&op);
+    let mut b = a.clone();
+
+    let op = a.remove(0).unwrap();
+    a.apply(&op);
+    let op = text(&b, 1, "c", 2);
+    b.apply(&op);
+
+    a.merge(&b);
+    assert_eq!(chars(&a), "cb");
+}
+
+#[test]
+fn test_remove_wins_over_concurrent_update() {
+    let mut a = TList::new();
+    let op = a.insert(0, a.len().derive_add_ctx(1), |reg, ctx| reg.set(1, ctx));
+    a.apply(&op);
+    let mut b = a.clone();
+
+    let op = a.remove(0).unwrap();
+    a.apply(&op);
+    let op = b.update(0, b.get(0).derive_add_ctx(2), |reg, ctx| reg.set(2, ctx)).unwrap();
+    b.apply(&op);
+
+    a.merge(&b);
+    assert_eq!(a.len().val, 0);
+    b.merge(&a);
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_insert_before_anchor_is_ignored() {
+    let mut a = TList::new();
+    let op1 = a.insert(0, a.len().derive_add_ctx(1), |reg, ctx| reg.set(1, ctx));
+    a.apply(&op1);
+    let op2 = a.insert(1, a.len().derive_add_ctx(1), |reg, ctx| reg.set(2, ctx));
+    a.apply(&op2);
+
+    let mut b = TList::new();
+    b.apply(&op2);
+    assert_eq!(b.len().val, 0);
+    b.apply(&op1);
+    b.apply(&op2);
+    assert_eq!(b, a);
+}
+
+#[test]
+fn test_insert_after_truncated_elem() {
+    let mut a = List::new();
+    let op = text(&a, 0, "ab", 1);
+    a.apply(&op);
+    let mut b = a.clone();
+    let op = text(&b, 1, "c", 2);
+    b.apply(&op);
+
+    let mut clock = VClock::new();
+    clock.witness(1, 1);
+    a.truncate(&clock);
+    let mut applied = a.clone();
+    applied.apply(&op);
+    assert_eq!(chars(&applied), "c");
+
+    a.merge(&b);
+    assert_eq!(a, applied);
+    b.merge(&applied);
+    assert_eq!(chars(&b), "c");
+}
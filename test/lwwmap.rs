@@ -0,0 +1,135 @@
+use crdts::{LWWMap, FunkyCvRDT, FunkyCmRDT, Error};
+use crdts::lwwset::Bias;
+use crdts::lwwmap::Op;
+
+fn build_map(ops: &[(u8, Option<u8>, u8)], bias: Bias) -> LWWMap<u8, u8, (u8, u8, u8)> {
+    let mut map = LWWMap::with_bias(bias);
+    for (key, val, marker) in ops.iter() {
+        // tie the marker to the write so different writes never share
+        // markers, a remove shares it with the put of its value 0
+        let marker = (*marker, *key, val.unwrap_or(0));
+        let op = match val {
+            Some(val) => map.put(*key, *val, marker),
+            None => map.rm(*key, marker)
+        };
+        map.apply(&op).unwrap();
+    }
+    map
+}
+
+quickcheck! {
+    fn prop_merge_converges(ops_a: Vec<(u8, Option<u8>, u8)>, ops_b: Vec<(u8, Option<u8>, u8)>, rm_bias: bool) -> bool {
+        let bias = if rm_bias { Bias::Rm } else { Bias::Add };
+        let a = build_map(&ops_a, bias);
+        let b = build_map(&ops_b, bias);
+
+        let mut ab = a.clone();
+        ab.merge(&b).unwrap();
+        let mut ba = b.clone();
+        ba.merge(&a).unwrap();
+        let mut abb = ab.clone();
+        abb.merge(&b).unwrap();
+
+        ab == ba && ab == abb
+    }
+
+    fn prop_ops_commute(ops: Vec<(u8, Option<u8>, u8)>, rm_bias: bool) -> bool {
+        let bias = if rm_bias { Bias::Rm } else { Bias::Add };
+        let mut reversed = ops.clone();
+        reversed.reverse();
+
+        build_map(&ops, bias) == build_map(&reversed, bias)
+    }
+}
+
+#[test]
+fn test_put_get_rm() {
+    let mut map: LWWMap<String, String, u64> = LWWMap::new();
+    assert!(map.is_empty());
+
+    map.apply(&map.put("a", "x", 1)).unwrap();
+    map.apply(&map.put("b", "y", 1)).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&"a".into()), Some(&"x".to_string()));
+
+    // stale put
+    map.apply(&map.put("a", "z", 0)).unwrap();
+    assert_eq!(map.get(&"a".into()), Some(&"x".to_string()));
+
+    map.apply(&map.rm("a", 2)).unwrap();
+    assert_eq!(map.get(&"a".into()), None);
+    assert_eq!(
+        map.iter().collect::<Vec<_>>(),
+        vec![(&"b".to_string(), &"y".to_string())]
+    );
+
+    // the tombstone keeps stale puts out
+    map.apply(&map.put("a", "x", 1)).unwrap();
+    assert_eq!(map.get(&"a".into()), None);
+}
+
+#[test]
+fn test_bias_breaks_ties() {
+    let put = Op::Put { key: 1u8, val: 2u8, marker: 7u64 };
+    let rm = Op::Rm { key: 1u8, marker: 7u64 };
+
+    for &(bias, expected) in [(Bias::Add, Some(&2)), (Bias::Rm, None)].iter() {
+        let mut put_first = LWWMap::with_bias(bias);
+        put_first.apply(&put).unwrap();
+        put_first.apply(&rm).unwrap();
+
+        let mut rm_first = LWWMap::with_bias(bias);
+        rm_first.apply(&rm).unwrap();
+        rm_first.apply(&put).unwrap();
+
+        assert_eq!(put_first.get(&1), expected);
+        assert_eq!(put_first, rm_first);
+    }
+}
+
+#[test]
+fn test_conflicting_marker() {
+    let mut map: LWWMap<u8, u8, u64> = LWWMap::new();
+    map.apply(&map.put(1, 2, 5)).unwrap();
+
+    // the same write again is fine
+    assert_eq!(map.apply(&map.put(1, 2, 5)), Ok(()));
+    assert_eq!(map.apply(&map.put(1, 3, 5)), Err(Error::ConflictingMarker));
+    assert_eq!(map.get(&1), Some(&2));
+
+    let mut other: LWWMap<u8, u8, u64> = LWWMap::new();
+    other.apply(&other.put(0, 9, 9)).unwrap();
+    other.apply(&other.put(1, 3, 5)).unwrap();
+    let before = map.clone();
+    assert_eq!(map.merge(&other), Err(Error::ConflictingMarker));
+    assert_eq!(map, before);
+}
+
+#[test]
+fn test_conflicting_marker_behind_tombstone() {
+    let put_a = Op::Put { key: 1u8, val: 2u8, marker: 7u64 };
+    let put_b = Op::Put { key: 1u8, val: 3u8, marker: 7u64 };
+    let rm = Op::Rm { key: 1u8, marker: 7u64 };
+
+    // the remove hides the first put, yet the second put must still fail
+    for orders in [[&put_a, &rm, &put_b], [&rm, &put_a, &put_b], [&put_a, &put_b, &rm]].iter() {
+        let mut map = LWWMap::with_bias(Bias::Rm);
+        let results: Vec<_> = orders.iter().map(|op| map.apply(op)).collect();
+        assert!(results.contains(&Err(Error::ConflictingMarker)));
+    }
+
+    let mut a = LWWMap::with_bias(Bias::Rm);
+    a.apply(&put_a).unwrap();
+    a.apply(&rm).unwrap();
+    let mut b = LWWMap::with_bias(Bias::Rm);
+    b.apply(&put_b).unwrap();
+    assert_eq!(a.merge(&b), Err(Error::ConflictingMarker));
+    assert_eq!(b.merge(&a), Err(Error::ConflictingMarker));
+}
+
+#[test]
+fn test_merge_with_different_bias_fails() {
+    let mut a: LWWMap<u8, u8, u64> = LWWMap::with_bias(Bias::Add);
+    let b: LWWMap<u8, u8, u64> = LWWMap::with_bias(Bias::Rm);
+    assert_eq!(a.merge(&b), Err(Error::MergeConflict));
+}
@@ -0,0 +1,117 @@
+use crdts::{LWWSet, FunkyCvRDT, FunkyCmRDT, Error};
+use crdts::lwwset::{Bias, Op};
+
+fn build_set(ops: &[(u8, u8, bool)], bias: Bias) -> LWWSet<u8, (u8, u8)> {
+    let mut set = LWWSet::with_bias(bias);
+    for (member, marker, add) in ops.iter() {
+        // tie the marker to the member so ops on different members never
+        // share markers
+        let marker = (*marker, *member);
+        let op = if *add {
+            set.add(*member, marker)
+        } else {
+            set.rm(*member, marker)
+        };
+        set.apply(&op).unwrap();
+    }
+    set
+}
+
+quickcheck! {
+    fn prop_merge_converges(ops_a: Vec<(u8, u8, bool)>, ops_b: Vec<(u8, u8, bool)>, rm_bias: bool) -> bool {
+        let bias = if rm_bias { Bias::Rm } else { Bias::Add };
+        let a = build_set(&ops_a, bias);
+        let b = build_set(&ops_b, bias);
+
+        let mut ab = a.clone();
+        ab.merge(&b).unwrap();
+        let mut ba = b.clone();
+        ba.merge(&a).unwrap();
+        let mut abb = ab.clone();
+        abb.merge(&b).unwrap();
+
+        ab.value() == ba.value() && ab == abb
+    }
+
+    fn prop_ops_commute(ops: Vec<(u8, u8, bool)>, rm_bias: bool) -> bool {
+        let bias = if rm_bias { Bias::Rm } else { Bias::Add };
+        let mut reversed = ops.clone();
+        reversed.reverse();
+
+        build_set(&ops, bias).value() == build_set(&reversed, bias).value()
+    }
+}
+
+#[test]
+fn test_stale_ops_are_ignored() {
+    let mut set: LWWSet<String, u64> = LWWSet::new();
+    set.apply(&set.add("apple", 2)).unwrap();
+    set.apply(&set.rm("apple", 1)).unwrap();
+    assert!(set.contains(&"apple".into()));
+
+    set.apply(&set.rm("apple", 3)).unwrap();
+    set.apply(&set.add("apple", 2)).unwrap();
+    assert!(!set.contains(&"apple".into()));
+    assert_eq!(set.iter().count(), 0);
+}
+
+#[test]
+fn test_bias_breaks_ties() {
+    let add = Op::Add { member: 1u8, marker: 7u64 };
+    let rm = Op::Rm { member: 1u8, marker: 7u64 };
+
+    for &(bias, expected) in [(Bias::Add, true), (Bias::Rm, false)].iter() {
+        let mut add_first = LWWSet::with_bias(bias);
+        add_first.apply(&add).unwrap();
+        add_first.apply(&rm).unwrap();
+
+        let mut rm_first = LWWSet::with_bias(bias);
+        rm_first.apply(&rm).unwrap();
+        rm_first.apply(&add).unwrap();
+
+        assert_eq!(add_first.contains(&1), expected);
+        assert_eq!(add_first, rm_first);
+    }
+}
+
+#[test]
+fn test_merge_with_different_bias_fails() {
+    let mut a: LWWSet<u8, u64> = LWWSet::with_bias(Bias::Add);
+    let b: LWWSet<u8, u64> = LWWSet::with_bias(Bias::Rm);
+    a.apply(&a.add(1, 1)).unwrap();
+
+    assert_eq!(a.merge(&b), Err(Error::MergeConflict));
+    assert_eq!(a.bias(), Bias::Add);
+    assert!(a.contains(&1));
+}
+
+#[test]
+fn test_same_marker_never_conflicts() {
+    let writes = [
+        Op::Add { member: 1u8, marker: 7u64 },
+        Op::Rm { member: 1u8, marker: 7u64 }
+    ];
+
+    // every pair of writes sharing a marker is settled, whether the
+    // writes are applied or merged and in whichever order
+    for &bias in [Bias::Add, Bias::Rm].iter() {
+        for first in writes.iter() {
+            for second in writes.iter() {
+                let mut a = LWWSet::with_bias(bias);
+                a.apply(first).unwrap();
+                let mut b = LWWSet::with_bias(bias);
+                b.apply(second).unwrap();
+
+                let mut applied = a.clone();
+                assert_eq!(applied.apply(second), Ok(()));
+                let mut ab = a.clone();
+                assert_eq!(ab.merge(&b), Ok(()));
+                let mut ba = b.clone();
+                assert_eq!(ba.merge(&a), Ok(()));
+
+                assert_eq!(ab, applied);
+                assert_eq!(ba, applied);
+            }
+        }
+    }
+}
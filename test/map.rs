@@ -1,4 +1,4 @@
-use crdts::{map, mvreg, VClock, Dot, MVReg, Map, CvRDT, CmRDT, Causal};
+use crdts::{map, mvreg, VClock, Dot, MVReg, Map, Orswot, CvRDT, CmRDT, Causal, Diff, Error};
 use quickcheck::TestResult;
 
 type TestActor = u8;
@@ -21,7 +21,7 @@ fn build_opvec(prims: (u8, Vec<(u8, u8, u8, u8, u8)>)) -> OpVec {
             counter: i as u64
         }.into();
 
-        let op = match choice % 3 {
+        let op = match choice % 4 {
             0 => {
                 map::Op::Up {
                     dot: clock.inc(actor),
@@ -38,6 +38,17 @@ fn build_opvec(prims: (u8, Vec<(u8, u8, u8, u8, u8)>)) -> OpVec {
                 }
             },
             1 => map::Op::Rm { clock, key },
+            2 => {
+                map::Op::Batch {
+                    dot: clock.inc(actor),
+                    updates: vec![(key, map::Op::Up {
+                        dot: clock.inc(actor),
+                        key: inner_key,
+                        op: mvreg::Op::Put { clock: clock.clone(), val }
+                    })],
+                    rms: vec![(inner_choice, clock)]
+                }
+            },
             _ => map::Op::Nop
         };
         ops.push(op);
@@ -132,6 +143,170 @@ fn test_remove() {
     assert_eq!(m.len().val, 0);
 }
 
+#[test]
+fn test_iter() {
+    let mut m: Map<u8, MVReg<u8, u8>, u8> = Map::new();
+    for key in [4, 2, 9, 7] {
+        let op = m.update(key, m.get(&key).derive_add_ctx(1), |reg, ctx| {
+            reg.set(key * 10, ctx)
+        });
+        m.apply(&op);
+    }
+
+    let keys: Vec<u8> = m.keys().val.cloned().collect();
+    assert_eq!(keys, vec![2, 4, 7, 9]);
+
+    let vals: Vec<Vec<u8>> = m.values().val.map(|r| r.read().val).collect();
+    assert_eq!(vals, vec![vec![20], vec![40], vec![70], vec![90]]);
+
+    let entries: Vec<(u8, Vec<u8>)> = {
+        let read_ctx = m.iter();
+        assert_eq!(read_ctx.add_clock.as_ref(), &m.len().add_clock);
+        read_ctx.val.map(|(k, r)| (*k, r.read().val)).collect()
+    };
+    assert_eq!(
+        entries,
+        vec![(2, vec![20]), (4, vec![40]), (7, vec![70]), (9, vec![90])]
+    );
+
+    let page: Vec<u8> = m.range(3..8).val.map(|(k, _)| *k).collect();
+    assert_eq!(page, vec![4, 7]);
+
+    let page: Vec<u8> = m.range(7..).val.map(|(k, _)| *k).collect();
+    assert_eq!(page, vec![7, 9]);
+
+    // the traversal ctx can be used to remove any key we've seen
+    let rm_ctx = m.range(..5).derive_rm_ctx();
+    let rm_op = m.rm(2, rm_ctx);
+    m.apply(&rm_op);
+    assert_eq!(m.keys().val.cloned().collect::<Vec<_>>(), vec![4, 7, 9]);
+}
+
+#[test]
+fn test_batch() {
+    let mut m1 = TestMap::new();
+    let op1 = m1.batch(m1.len().derive_add_ctx(1))
+        .update(1, |map, ctx| map.update(10, ctx, |reg, ctx| reg.set(1, ctx)))
+        .update(2, |map, ctx| map.update(20, ctx, |reg, ctx| reg.set(2, ctx)))
+        .build()
+        .unwrap();
+    m1.apply(&op1);
+
+    // the whole batch is witnessed by a single dot
+    assert_eq!(m1.len().val, 2);
+    assert_eq!(m1.len().add_clock, VClock::from(Dot { actor: 1, counter: 1 }));
+
+    let mut m2 = m1.clone();
+    let op2 = m1.batch(m1.len().derive_add_ctx(1))
+        .rm(1, m1.get(&1).derive_rm_ctx())
+        .update(2, |map, ctx| map.update(21, ctx, |reg, ctx| reg.set(3, ctx)))
+        .update(3, |map, ctx| map.update(30, ctx, |reg, ctx| reg.set(4, ctx)))
+        .build()
+        .unwrap();
+    m1.apply(&op2);
+    assert_eq!(m1.keys().val.cloned().collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(m1.get(&2).val.map(|inner| inner.len().val), Some(2));
+
+    // replaying the batch is a nop
+    let m1_snapshot = m1.clone();
+    m1.apply(&op2);
+    assert_eq!(m1, m1_snapshot);
+
+    // merging the state is the same as applying the batch
+    let mut m3 = m2.clone();
+    m3.merge(&m1);
+    m2.apply(&op2);
+    assert_eq!(m2, m1);
+    assert_eq!(m3, m1);
+}
+
+#[test]
+fn test_batch_rejects_repeated_keys() {
+    let m = TestMap::new();
+    let res = m.batch(m.len().derive_add_ctx(1))
+        .update(1, |map, ctx| map.update(10, ctx, |reg, ctx| reg.set(1, ctx)))
+        .update(1, |map, ctx| map.update(11, ctx, |reg, ctx| reg.set(2, ctx)))
+        .build();
+    assert_eq!(res, Err(Error::ConflictingMarker));
+
+    // removing and updating the same key is fine
+    let res = m.batch(m.len().derive_add_ctx(1))
+        .rm(1, m.get(&1).derive_rm_ctx())
+        .update(1, |map, ctx| map.update(10, ctx, |reg, ctx| reg.set(1, ctx)))
+        .build();
+    assert!(res.is_ok());
+}
+
+#[test]
+fn test_path() {
+    let mut m1 = TestMap::new();
+
+    // a path op is the same op we would build with nested updates
+    let op1 = m1.at(1).at(10).update(1, |reg, ctx| reg.set(5, ctx));
+    let nested_op1 = m1.update(1, m1.get(&1).derive_add_ctx(1), |map, ctx| {
+        map.update(10, ctx, |reg, ctx| reg.set(5, ctx))
+    });
+    assert_eq!(op1, nested_op1);
+    m1.apply(&op1);
+
+    {
+        let read_ctx = m1.at(1).at(10).read();
+        assert_eq!(read_ctx.val.map(|reg| reg.read().val), Some(vec![5]));
+        assert_eq!(read_ctx.add_clock.as_ref(), &m1.len().add_clock);
+    }
+    assert_eq!(m1.at(1).at(11).read().val, None);
+    assert_eq!(m1.at(2).at(10).read().val, None);
+
+    let mut m2 = m1.clone();
+    let op2 = m2.at(1).at(10).update(2, |reg, ctx| reg.set(6, ctx));
+    m2.apply(&op2);
+
+    // removing through the path only removes the edits we've seen
+    let op3 = m1.at(1).rm(10, 1);
+    m1.apply(&op3);
+    assert_eq!(m1.at(1).at(10).read().val, None);
+
+    m1.merge(&m2);
+    assert_eq!(
+        m1.at(1).at(10).read().val.map(|reg| reg.read().val),
+        Some(vec![6])
+    );
+}
+
+#[test]
+fn test_path_rm_missing_is_nop() {
+    let mut m = TestMap::new();
+    assert_eq!(m.at(1).rm(10, 1), map::Op::Nop);
+
+    let op = m.at(1).at(10).update(1, |reg, ctx| reg.set(5, ctx));
+    m.apply(&op);
+    let before = m.clone();
+    assert_eq!(m.at(1).rm(11, 1), map::Op::Nop);
+    assert_eq!(m.at(2).rm(10, 1), map::Op::Nop);
+    m.apply(&m.at(2).rm(10, 1));
+    assert_eq!(m, before);
+}
+
+#[test]
+fn test_deferred_len() {
+    let mut m = TestMap::new();
+    let rm_clock: VClock<u8> = vec![(1, 1), (2, 1)].into_iter().collect();
+    m.apply(&map::Op::Rm { clock: rm_clock.clone(), key: 1 });
+    m.apply(&map::Op::Rm { clock: rm_clock, key: 2 });
+    assert_eq!(m.deferred_len(), 2);
+
+    let op = m.at(1).at(10).update(1, |reg, ctx| reg.set(5, ctx));
+    m.apply(&op);
+    // the update was seen by the remove
+    assert_eq!(m.get(&1).val, None);
+    assert_eq!(m.deferred_len(), 2);
+
+    let op = m.at(3).at(30).update(2, |reg, ctx| reg.set(6, ctx));
+    m.apply(&op);
+    assert_eq!(m.deferred_len(), 0);
+    assert_eq!(m.keys().val.cloned().collect::<Vec<_>>(), vec![3]);
+}
+
 #[test]
 fn test_reset_remove_semantics() {
     let mut m1 = TestMap::new();
@@ -168,6 +343,41 @@ fn test_reset_remove_semantics() {
     assert_eq!(inner_map.len().val, 1);
 }
 
+#[test]
+fn test_reset_remove_with_concurrent_edit_by_seen_actor() {
+    let mut m1 = TestMap::new();
+    let op1 = m1.update(101, m1.get(&101).derive_add_ctx(1), |map, ctx| {
+        map.update(110, ctx, |reg, ctx| reg.set(32, ctx))
+    });
+    m1.apply(&op1);
+    let mut m2 = m1.clone();
+
+    // m2 removes the key after seeing the first edit of actor 1, actor 1
+    // concurrently edits the key again
+    let rm_op = m2.rm(101, m2.get(&101).derive_rm_ctx());
+    m2.apply(&rm_op);
+    let op2 = m1.update(101, m1.get(&101).derive_add_ctx(1), |map, ctx| {
+        map.update(220, ctx, |reg, ctx| reg.set(5, ctx))
+    });
+    m1.apply(&op2);
+
+    let mut m1_merged = m1.clone();
+    m1_merged.merge(&m2);
+    let mut m2_merged = m2.clone();
+    m2_merged.merge(&m1);
+    m1.apply(&rm_op);
+    m2.apply(&op2);
+    assert_eq!(m1_merged, m2_merged);
+    assert_eq!(m1_merged, m1);
+    assert_eq!(m1_merged, m2);
+
+    // the edit seen by the remove is gone, the concurrent one survives
+    let inner_map = m1_merged.get(&101).val.unwrap();
+    assert_eq!(inner_map.get(&110).val, None);
+    assert_eq!(inner_map.get(&220).val.map(|r| r.read().val), Some(vec![5]));
+    assert_eq!(inner_map.len().val, 1);
+}
+
 #[test]
 fn test_updating_with_current_clock_should_be_a_nop() {
     let mut m1: TestMap = Map::new();
@@ -743,3 +953,33 @@ quickcheck! {
         m == m_snapshot
     }
 }
+
+#[test]
+fn test_diff() {
+    let mut a: Map<u8, Orswot<u8, u8>, u8> = Map::new();
+    for key in 1..4 {
+        let op = a.update(key, a.len().derive_add_ctx(1), |set, ctx| set.add(key, ctx));
+        a.apply(&op);
+    }
+    assert!(a.diff(&a.clone()).is_none());
+
+    let mut b = a.clone();
+    let op = b.rm(1, b.get(&1).derive_rm_ctx());
+    b.apply(&op);
+    let op = b.update(2, b.len().derive_add_ctx(2), |set, ctx| set.add(20, ctx));
+    b.apply(&op);
+    let op = b.update(4, b.len().derive_add_ctx(2), |set, ctx| set.add(4, ctx));
+    b.apply(&op);
+
+    // an edit that doesn't change the value isn't reported
+    let op = b.update(3, b.len().derive_add_ctx(2), |set, ctx| set.add(3, ctx));
+    b.apply(&op);
+
+    let changes = a.merge_with_changes(&b).unwrap();
+    assert_eq!(changes.inserted, vec![4].into_iter().collect());
+    assert_eq!(changes.removed, vec![1].into_iter().collect());
+    assert_eq!(changes.updated.keys().collect::<Vec<_>>(), vec![&2]);
+    assert_eq!(changes.updated[&2].added, vec![20].into_iter().collect());
+    assert!(changes.updated[&2].removed.is_empty());
+    assert_eq!(a, b);
+}
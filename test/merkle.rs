@@ -0,0 +1,218 @@
+use crdts::*;
+use crdts::merkle::{Merkle, MerkleTree, Delta};
+
+/// A peer reached over a loopback "network", every message is
+/// serialized to make sure the protocol only exchanges what it claims.
+struct LoopbackPeer<C> {
+    state: C,
+    bytes_sent: usize
+}
+
+impl<C: Merkle + Clone + ::serde::Serialize + ::serde::de::DeserializeOwned> LoopbackPeer<C> {
+    fn send<T: ::serde::Serialize + ::serde::de::DeserializeOwned>(&mut self, msg: &T) -> T {
+        let encoded = to_binary(msg);
+        self.bytes_sent += encoded.len();
+        from_binary(encoded).unwrap()
+    }
+
+    /// The peer diffs its own tree against ours, returns None if the
+    /// states are equal.
+    fn diff(&mut self, tree: &MerkleTree) -> Option<Vec<usize>> {
+        let tree = self.send(tree);
+        let peer_tree = self.state.merkle_tree_like(&tree).unwrap();
+        if peer_tree.root() == tree.root() {
+            self.send(&None)
+        } else {
+            self.send(&Some(tree.diff(&peer_tree).unwrap()))
+        }
+    }
+
+    fn delta(&mut self, tree: &MerkleTree, buckets: &[usize]) -> Delta<C> {
+        let delta = self.state.delta(tree, buckets).unwrap();
+        self.send(&delta)
+    }
+
+    fn merge_delta(&mut self, delta: Delta<C>) {
+        let delta = self.send(&delta);
+        self.state.merge_delta(&delta).unwrap();
+    }
+}
+
+/// Bring local and the peer in sync, returns the number of differing buckets.
+fn sync<C>(local: &mut C, peer: &mut LoopbackPeer<C>, depth: u8) -> usize
+    where C: Merkle + Clone + ::serde::Serialize + ::serde::de::DeserializeOwned
+{
+    let local_tree = local.merkle_tree(depth);
+    let buckets = match peer.diff(&local_tree) {
+        Some(buckets) => buckets,
+        None => return 0
+    };
+
+    let local_delta = local.delta(&local_tree, &buckets).unwrap();
+    let peer_delta = peer.delta(&local_tree, &buckets);
+    local.merge_delta(&peer_delta).unwrap();
+    peer.merge_delta(local_delta);
+    buckets.len()
+}
+
+fn build_set(actor: u8, ops: &[(u8, bool)]) -> Orswot<u8, u8> {
+    let mut set = Orswot::new();
+    for (member, is_add) in ops {
+        let op = if *is_add {
+            set.add(*member, set.value().derive_add_ctx(actor))
+        } else {
+            set.remove(*member, set.contains(member).derive_rm_ctx())
+        };
+        set.apply(&op);
+    }
+    set
+}
+
+quickcheck! {
+    fn prop_merkle_sync_is_merge(
+        shared: Vec<(u8, bool)>,
+        local_ops: Vec<(u8, bool)>,
+        peer_ops: Vec<(u8, bool)>,
+        depth: u8
+    ) -> bool {
+        let depth = depth % 6;
+        let base = build_set(1, &shared);
+
+        let mut local = base.clone();
+        local.merge(&build_set(2, &local_ops));
+        let mut remote = base.clone();
+        remote.merge(&build_set(3, &peer_ops));
+
+        let mut merged = local.clone();
+        merged.merge(&remote);
+
+        let mut peer = LoopbackPeer { state: remote, bytes_sent: 0 };
+        sync(&mut local, &mut peer, depth);
+
+        local == merged
+            && peer.state == merged
+            && sync(&mut local, &mut peer, depth) == 0
+    }
+}
+
+#[test]
+fn test_sync_only_sends_differing_buckets() {
+    let mut local: Orswot<u32, u8> = Orswot::new();
+    for member in 0..10_000u32 {
+        let op = local.add(member, local.value().derive_add_ctx(1));
+        local.apply(&op);
+    }
+    let mut remote = local.clone();
+    let op = remote.add(10_000u32, remote.value().derive_add_ctx(2));
+    remote.apply(&op);
+    let op = remote.remove(7u32, remote.contains(&7).derive_rm_ctx());
+    remote.apply(&op);
+
+    let full_state_len = to_binary(&remote).len();
+    let mut peer = LoopbackPeer { state: remote, bytes_sent: 0 };
+    assert_eq!(sync(&mut local, &mut peer, 10), 2);
+    assert_eq!(local, peer.state);
+    assert!(peer.bytes_sent * 10 < full_state_len);
+}
+
+#[test]
+fn test_map_sync() {
+    type TestMap = Map<u16, Orswot<u8, u8>, u8>;
+    let mut local: TestMap = Map::new();
+    for key in 0..500u16 {
+        let op = local.at(key).add(1, 1);
+        local.apply(&op);
+    }
+    let mut remote = local.clone();
+
+    let op = local.at(3u16).add(2, 1);
+    local.apply(&op);
+    let op = remote.rm(4u16, remote.get(&4).derive_rm_ctx());
+    remote.apply(&op);
+    let op = remote.at(600u16).add(3, 2);
+    remote.apply(&op);
+
+    let mut merged = local.clone();
+    merged.merge(&remote);
+
+    let mut peer = LoopbackPeer { state: remote, bytes_sent: 0 };
+    assert!(sync(&mut local, &mut peer, 6) <= 3);
+    assert_eq!(local, merged);
+    assert_eq!(peer.state, merged);
+}
+
+#[test]
+fn test_map_buckets_are_key_ranges() {
+    type TestMap = Map<u16, Orswot<u8, u8>, u8>;
+    let mut local: TestMap = Map::new();
+    for key in 0..64u16 {
+        let op = local.at(key).add(1, 1);
+        local.apply(&op);
+    }
+    let mut remote = local.clone();
+    let op = remote.at(10u16).add(2, 2);
+    remote.apply(&op);
+
+    // 8 buckets of 8 keys each, key 10 falls in the second one
+    let local_tree = local.merkle_tree(3);
+    assert_eq!(local_tree.bounds().len(), 7);
+    let remote_tree = remote.merkle_tree_like(&local_tree).unwrap();
+    let buckets = local_tree.diff(&remote_tree).unwrap();
+    assert_eq!(buckets, vec![1]);
+
+    let delta = remote.delta(&local_tree, &buckets).unwrap();
+    assert_eq!(
+        delta.state.keys().val.cloned().collect::<Vec<_>>(),
+        (8..16).collect::<Vec<_>>()
+    );
+    local.merge_delta(&delta).unwrap();
+    assert_eq!(local, remote);
+}
+
+#[test]
+fn test_invalid_trees_are_rejected() {
+    let set = build_set(1, &[(1, true), (2, true)]);
+    let tree = set.merkle_tree(2);
+    assert_eq!(tree.diff(&set.merkle_tree(3)), Err(Error::InvalidTree));
+
+    // a tree deeper than MAX_DEPTH, the depth is the first byte
+    let mut bytes = to_binary(&tree);
+    bytes[0] = 200;
+    let deep: MerkleTree = from_binary(bytes).unwrap();
+    assert_eq!(set.merkle_tree_like(&deep), Err(Error::InvalidTree));
+    assert_eq!(tree.diff(&deep), Err(Error::InvalidTree));
+    assert_eq!(set.delta(&deep, &[0]), Err(Error::InvalidTree));
+
+    let mut other = set.clone();
+    let delta = Delta {
+        depth: 200,
+        bounds: Vec::new(),
+        buckets: vec![0].into_iter().collect(),
+        state: set.clone()
+    };
+    assert_eq!(other.merge_delta(&delta), Err(Error::InvalidTree));
+    assert_eq!(other, set);
+}
+
+#[test]
+fn test_map_bounds_of_other_keys_are_rejected() {
+    let mut local: Map<u16, Orswot<u8, u8>, u8> = Map::new();
+    for key in 0..16u16 {
+        let op = local.at(key).add(1, 1);
+        local.apply(&op);
+    }
+    let tree = local.merkle_tree(2);
+
+    // the bounds don't decode as keys of the other map
+    let mut other: Map<String, Orswot<u8, u8>, u8> = Map::new();
+    assert_eq!(other.merkle_tree_like(&tree), Err(Error::InvalidTree));
+    assert_eq!(other.delta(&tree, &[0]), Err(Error::InvalidTree));
+
+    let delta = Delta {
+        depth: tree.depth(),
+        bounds: tree.bounds().to_vec(),
+        buckets: vec![0].into_iter().collect(),
+        state: Map::new()
+    };
+    assert_eq!(other.merge_delta(&delta), Err(Error::InvalidTree));
+}
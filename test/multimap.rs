@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use crdts::{MultiMap, CvRDT, CmRDT, Dot, VClock};
+use crdts::multimap::Op;
+
+const ACTOR_MAX: u8 = 11;
+
+fn build_ops(op_prims: Vec<(u8, u8, u8, u8, u64)>) -> Vec<(u8, Op<u8, u8, u8>)> {
+    op_prims.into_iter()
+        .map(|(actor, key, val, choice, counter)| {
+            let key = key % 4;
+            let val = val % 4;
+            let op = match choice % 3 {
+                0 => Op::Insert { dot: Dot { actor, counter }, key, val },
+                1 => Op::Rm { clock: Dot { actor, counter }.into(), key, val: Some(val) },
+                _ => Op::Rm { clock: Dot { actor, counter }.into(), key, val: None }
+            };
+            (actor, op)
+        })
+        .collect()
+}
+
+fn vals(map: &MultiMap<String, String, u8>, key: &str) -> HashSet<String> {
+    map.get(&key.to_string()).val
+}
+
+fn set(vals: &[&str]) -> HashSet<String> {
+    vals.iter().map(|val| val.to_string()).collect()
+}
+
+quickcheck! {
+    fn prop_merge_converges(op_prims: Vec<(u8, u8, u8, u8, u64)>) -> bool {
+        let ops = build_ops(op_prims);
+        let mut result = None;
+        for i in 2..ACTOR_MAX {
+            let mut witnesses: Vec<MultiMap<u8, u8, u8>> =
+                (0..i).map(|_| MultiMap::new()).collect();
+            for (actor, op) in ops.iter() {
+                witnesses[(actor % i) as usize].apply(op);
+            }
+            let mut merged = MultiMap::new();
+            for witness in witnesses.iter() {
+                merged.merge(witness);
+            }
+            merged.merge(&MultiMap::new());
+
+            match result {
+                Some(ref prev) if prev != &merged => return false,
+                Some(_) => (),
+                None => result = Some(merged)
+            }
+        }
+        true
+    }
+
+    fn prop_merge_is_idempotent_and_commutative(
+        a_prims: Vec<(u8, u8, u8, u8, u64)>,
+        b_prims: Vec<(u8, u8, u8, u8, u64)>
+    ) -> bool {
+        let mut a: MultiMap<u8, u8, u8> = MultiMap::new();
+        let mut b: MultiMap<u8, u8, u8> = MultiMap::new();
+        for (_, op) in build_ops(a_prims) {
+            a.apply(&op);
+        }
+        for (_, op) in build_ops(b_prims) {
+            b.apply(&op);
+        }
+
+        let mut ab = a.clone();
+        ab.merge(&b);
+        let mut ba = b.clone();
+        ba.merge(&a);
+        let mut abb = ab.clone();
+        abb.merge(&b);
+
+        let pairs = |m: &MultiMap<u8, u8, u8>| {
+            m.iter().val.map(|(k, v)| (*k, *v)).collect::<HashSet<_>>()
+        };
+        pairs(&ab) == pairs(&ba) && ab == abb
+    }
+}
+
+#[test]
+fn test_insert_remove() {
+    let mut map: MultiMap<String, String, u8> = MultiMap::new();
+    for tag in ["draft", "urgent"].iter() {
+        let op = map.insert("doc", *tag, map.get(&"doc".into()).derive_add_ctx(1));
+        map.apply(&op);
+    }
+    let op = map.insert("other", "draft", map.len().derive_add_ctx(1));
+    map.apply(&op);
+    assert_eq!(vals(&map, "doc"), set(&["draft", "urgent"]));
+    assert_eq!(map.len().val, 2);
+
+    let ctx = map.contains(&"doc".into(), &"draft".into()).derive_rm_ctx();
+    let op = map.remove("doc", "draft", ctx);
+    map.apply(&op);
+    assert_eq!(vals(&map, "doc"), set(&["urgent"]));
+    assert_eq!(vals(&map, "other"), set(&["draft"]));
+
+    let op = map.remove_all("doc", map.get(&"doc".into()).derive_rm_ctx());
+    map.apply(&op);
+    assert_eq!(vals(&map, "doc"), set(&[]));
+    assert_eq!(map.keys().val.cloned().collect::<Vec<_>>(), vec!["other".to_string()]);
+}
+
+#[test]
+fn test_concurrent_insert_wins() {
+    let mut a: MultiMap<String, String, u8> = MultiMap::new();
+    let op = a.insert("doc", "draft", a.get(&"doc".into()).derive_add_ctx(1));
+    a.apply(&op);
+    let mut b = a.clone();
+
+    // b re-inserts the tag that a concurrently removes
+    let op = a.remove("doc", "draft", a.contains(&"doc".into(), &"draft".into()).derive_rm_ctx());
+    a.apply(&op);
+    let op = b.insert("doc", "draft", b.get(&"doc".into()).derive_add_ctx(2));
+    b.apply(&op);
+
+    let mut merged = a.clone();
+    merged.merge(&b);
+    b.merge(&a);
+    assert_eq!(merged, b);
+    assert_eq!(vals(&merged, "doc"), set(&["draft"]));
+}
+
+#[test]
+fn test_deferred_remove_all() {
+    let mut map: MultiMap<u8, u8, u8> = MultiMap::new();
+    let clock: VClock<u8> = vec![(1, 2)].into_iter().collect();
+    map.apply(&Op::Rm { clock, key: 0, val: None });
+    assert_eq!(map.deferred_len(), 1);
+
+    map.apply(&Op::Insert { dot: Dot { actor: 1, counter: 1 }, key: 0, val: 1 });
+    map.apply(&Op::Insert { dot: Dot { actor: 1, counter: 2 }, key: 0, val: 2 });
+    map.apply(&Op::Insert { dot: Dot { actor: 1, counter: 3 }, key: 0, val: 3 });
+    assert_eq!(map.deferred_len(), 0);
+    assert_eq!(map.get(&0).val, vec![3].into_iter().collect());
+}
+
+#[test]
+fn test_deferred_removes_survive_serialization() {
+    let mut map: MultiMap<u8, u8, u8> = MultiMap::new();
+    let clock: VClock<u8> = vec![(1, 1)].into_iter().collect();
+    map.apply(&Op::Rm { clock, key: 0, val: Some(1) });
+
+    let mut decoded: MultiMap<u8, u8, u8> =
+        crdts::from_binary(crdts::to_binary(&map)).unwrap();
+    assert_eq!(decoded, map);
+
+    decoded.apply(&Op::Insert { dot: Dot { actor: 1, counter: 1 }, key: 0, val: 1 });
+    assert_eq!(decoded.get(&0).val, HashSet::new());
+    assert_eq!(decoded.deferred_len(), 0);
+}
@@ -101,6 +101,85 @@ fn test_multi_val() {
     );
 }
 
+#[test]
+fn test_diff() {
+    let mut a: MVReg<u8, u8> = MVReg::new();
+    let op = a.set(1, a.read().derive_add_ctx(1));
+    a.apply(&op);
+
+    let mut b = a.clone();
+    let op = b.set(2, b.read().derive_add_ctx(2));
+    b.apply(&op);
+    let mut c = a.clone();
+    let op = c.set(3, c.read().derive_add_ctx(3));
+    c.apply(&op);
+
+    let changes = a.diff(&b).unwrap();
+    assert_eq!(changes.added, vec![2]);
+    assert_eq!(changes.removed, vec![1]);
+    assert!(a.diff(&a.clone()).is_none());
+
+    // concurrent values are kept side by side
+    let changes = b.merge_with_changes(&c).unwrap();
+    assert_eq!(changes, mvreg::Changes { added: vec![3], removed: vec![] });
+}
+
+#[test]
+fn test_resolvers() {
+    use crdts::mvreg::{Max, Min, ByMarker, Merge};
+
+    let mut reg: MVReg<(u64, u8), u8> = MVReg::new();
+    assert_eq!(reg.resolve(&Max).val, None);
+    for (actor, timestamp, val) in [(1, 30, 5), (2, 10, 9), (3, 20, 1)] {
+        reg.apply(&Op::Put { clock: Dot { actor, counter: 1 }.into(), val: (timestamp, val) });
+    }
+    assert_eq!(reg.read().val.len(), 3);
+
+    assert_eq!(reg.resolve(&Max).val, Some((30, 5)));
+    assert_eq!(reg.resolve(&Min).val, Some((10, 9)));
+    assert_eq!(reg.resolve(&ByMarker(|&(_, val): &(u64, u8)| val)).val, Some((10, 9)));
+    let sum = |vals: &[(u64, u8)]| (0, vals.iter().map(|(_, val)| val).sum());
+    assert_eq!(reg.resolve(&sum).val, Some((0, 15)));
+
+    let mut counters: MVReg<GCounter<u8>, u8> = MVReg::new();
+    for actor in 1..4 {
+        let mut counter = GCounter::new();
+        counter.apply(&counter.inc(actor));
+        counters.apply(&Op::Put { clock: Dot { actor, counter: 1 }.into(), val: counter });
+    }
+    assert_eq!(counters.resolve(&Merge).val.map(|counter| counter.value()), Some(3));
+}
+
+#[test]
+fn test_collapse() {
+    use crdts::mvreg::Max;
+
+    let mut a: MVReg<u8, u8> = MVReg::new();
+    let mut b = a.clone();
+    let op = a.set(3, a.read().derive_add_ctx(1));
+    a.apply(&op);
+    let op = b.set(7, b.read().derive_add_ctx(2));
+    b.apply(&op);
+    a.merge(&b);
+    b.merge(&a);
+
+    // both replicas collapse concurrently, they agree on the value
+    let a_op = a.collapse(&Max, 1).unwrap();
+    let b_op = b.collapse(&Max, 2).unwrap();
+    a.apply(&a_op);
+    a.apply(&b_op);
+    b.apply(&b_op);
+    b.apply(&a_op);
+    assert_eq!(a, b);
+    assert_eq!(a.read().val, vec![7, 7]);
+    assert_eq!(a.resolve(&Max).val, Some(7));
+
+    let op = a.collapse(&Max, 1).unwrap();
+    a.apply(&op);
+    assert_eq!(a.read().val, vec![7]);
+    assert!(a.collapse(&Max, 1).is_none());
+}
+
 #[test]
 fn test_op_commute_quickcheck1() {
     let mut reg1 = MVReg::new();
@@ -165,6 +244,17 @@ quickcheck! {
         next_read_ctx.val == vec![23]
     }
     
+    fn prop_merge_with_changes_is_diff(r1_ops: Vec<(u8, u8)>, r2_ops: Vec<(u8, u8)>) -> bool {
+        let mut r1 = build_test_reg(r1_ops).reg;
+        let r2 = build_test_reg(r2_ops).reg;
+
+        let mut merged = r1.clone();
+        merged.merge(&r2);
+        let expected = r1.diff(&merged);
+
+        r1.merge_with_changes(&r2) == expected && r1 == merged
+    }
+
     fn prop_merge_idempotent(r_ops: Vec<(u8, u8)>) -> bool {
         let mut r = build_test_reg(r_ops).reg;
         let r_snapshot = r.clone();
@@ -318,3 +408,72 @@ quickcheck! {
         TestResult::from_bool(true)
     }
 }
+
+// adversarial ops drawn from a tiny space of clocks and values, so
+// different ops often reuse the same clock
+fn build_adversarial_ops(prims: Vec<(u8, u8, u8, u8)>) -> Vec<Op<u8, u8>> {
+    prims.into_iter()
+        .map(|(a_counter, b_counter, actor, val)| {
+            let mut clock = VClock::new();
+            clock.apply(&Dot { actor: actor % 2, counter: (a_counter % 3) as u64 });
+            clock.apply(&Dot { actor: 2, counter: (b_counter % 2) as u64 });
+            Op::Put { clock, val: val % 3 }
+        })
+        .collect()
+}
+
+quickcheck! {
+    fn prop_checked_apply_commutes(prims: Vec<(u8, u8, u8, u8)>) -> TestResult {
+        let ops = build_adversarial_ops(prims);
+        let mut forward = MVReg::new();
+        let mut backward = MVReg::new();
+        for op in ops.iter() {
+            if forward.checked_apply(op).is_err() {
+                return TestResult::discard();
+            }
+        }
+        for op in ops.iter().rev() {
+            if backward.checked_apply(op).is_err() {
+                return TestResult::discard();
+            }
+        }
+        TestResult::from_bool(forward == backward)
+    }
+
+    fn prop_checked_apply_rejects_without_side_effects(prims: Vec<(u8, u8, u8, u8)>) -> bool {
+        let mut reg = MVReg::new();
+        for op in build_adversarial_ops(prims) {
+            let before = reg.clone();
+            let mut unchecked = reg.clone();
+            unchecked.apply(&op);
+            match reg.checked_apply(&op) {
+                Ok(()) => if reg != unchecked {
+                    return false;
+                },
+                Err(err) => if err != Error::ConflictingMarker || reg != before {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[test]
+fn test_reused_clock_breaks_commutativity() {
+    let clock: VClock<u8> = Dot { actor: 1, counter: 1 }.into();
+    let op1 = Op::Put { clock: clock.clone(), val: 1 };
+    let op2 = Op::Put { clock, val: 2 };
+
+    let mut a = MVReg::new();
+    a.apply(&op1);
+    a.apply(&op2);
+    let mut b = MVReg::new();
+    b.apply(&op2);
+    b.apply(&op1);
+    assert_ne!(a, b);
+
+    let mut a = MVReg::new();
+    assert_eq!(a.checked_apply(&op1), Ok(()));
+    assert_eq!(a.checked_apply(&op2), Err(Error::ConflictingMarker));
+}
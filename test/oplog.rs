@@ -0,0 +1,256 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crdts::*;
+use crdts::oplog::OpLog;
+
+fn log_dir(name: &str) -> PathBuf {
+    let dir = ::std::env::temp_dir()
+        .join(format!("crdts-test-oplog-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn add(log: &mut OpLog<Orswot<u8, u8>>, member: u8) {
+    let op = log.state().add(member, log.state().value().derive_add_ctx(1));
+    log.append(op).unwrap();
+}
+
+fn remove(log: &mut OpLog<Orswot<u8, u8>>, member: u8) {
+    let op = log.state().remove(member, log.state().contains(&member).derive_rm_ctx());
+    log.append(op).unwrap();
+}
+
+#[test]
+fn test_replay_on_open() {
+    let dir = log_dir("replay");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    for member in 0..10 {
+        add(&mut log, member);
+    }
+    remove(&mut log, 3);
+    let state = log.state().clone();
+    drop(log);
+
+    let log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 0).unwrap();
+    assert_eq!(log.state(), &state);
+    assert_eq!(log.len(), 11);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_snapshots_compact_the_log() {
+    let dir = log_dir("compact");
+    let mut log = OpLog::open(&dir, 4).unwrap();
+    for member in 0..10 {
+        add(&mut log, member);
+    }
+    assert_eq!(log.len(), 2);
+    let state = log.state().clone();
+    drop(log);
+
+    let mut log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 4).unwrap();
+    assert_eq!(log.state(), &state);
+    assert_eq!(log.len(), 2);
+
+    log.snapshot().unwrap();
+    assert!(log.is_empty());
+    assert_eq!(fs::metadata(dir.join("oplog")).unwrap().len(), 0);
+    drop(log);
+
+    let log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 4).unwrap();
+    assert_eq!(log.state(), &state);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_remove_after_snapshot_is_replayed() {
+    let dir = log_dir("rm");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    log.snapshot().unwrap();
+
+    // the clock of this remove is dominated by the snapshot's state
+    remove(&mut log, 1);
+    drop(log);
+
+    let log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 0).unwrap();
+    assert!(!log.state().contains(&1).val);
+    assert_eq!(log.len(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_torn_record_is_dropped() {
+    let dir = log_dir("torn");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    add(&mut log, 2);
+    let state = log.state().clone();
+    add(&mut log, 3);
+    drop(log);
+
+    // crash in the middle of writing the last record
+    let log_path = dir.join("oplog");
+    let len = fs::metadata(&log_path).unwrap().len();
+    OpenOptions::new().write(true).open(&log_path).unwrap()
+        .set_len(len - 3).unwrap();
+
+    let mut log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 0).unwrap();
+    assert_eq!(log.state(), &state);
+    assert_eq!(log.len(), 2);
+
+    // appends after the recovery aren't hidden behind the torn record
+    add(&mut log, 4);
+    let state = log.state().clone();
+    drop(log);
+
+    let log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 0).unwrap();
+    assert_eq!(log.state(), &state);
+    assert!(log.state().contains(&4).val);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_torn_header_is_dropped() {
+    let dir = log_dir("torn-header");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    let state = log.state().clone();
+    drop(log);
+
+    // crash before the whole header of the next record was written
+    let mut file = OpenOptions::new().append(true).open(dir.join("oplog")).unwrap();
+    file.write_all(&[0xff; 10]).unwrap();
+    drop(file);
+
+    let log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 0).unwrap();
+    assert_eq!(log.state(), &state);
+    assert_eq!(log.len(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_garbage_tail_is_rejected() {
+    let dir = log_dir("garbage");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    drop(log);
+
+    let mut file = OpenOptions::new().append(true).open(dir.join("oplog")).unwrap();
+    file.write_all(&[0xff; 40]).unwrap();
+    drop(file);
+    let bytes = fs::read(dir.join("oplog")).unwrap();
+
+    let err = OpLog::<Orswot<u8, u8>>::open(&dir, 0).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidData);
+    assert_eq!(fs::read(dir.join("oplog")).unwrap(), bytes);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_corrupt_last_record_is_rejected() {
+    let dir = log_dir("corrupt-last");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    add(&mut log, 2);
+    drop(log);
+
+    // the whole record was written, it wasn't torn by a crash
+    let mut bytes = fs::read(dir.join("oplog")).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(dir.join("oplog"), &bytes).unwrap();
+
+    let err = OpLog::<Orswot<u8, u8>>::open(&dir, 0).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidData);
+    assert_eq!(fs::read(dir.join("oplog")).unwrap(), bytes);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_corrupt_record_in_the_middle_is_rejected() {
+    let dir = log_dir("corrupt-middle");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    let first_len = fs::metadata(dir.join("oplog")).unwrap().len() as usize;
+    add(&mut log, 2);
+    add(&mut log, 3);
+    drop(log);
+
+    // flip a bit in the payload of the first record
+    let mut bytes = fs::read(dir.join("oplog")).unwrap();
+    bytes[first_len - 1] ^= 0xff;
+    fs::write(dir.join("oplog"), &bytes).unwrap();
+
+    let err = OpLog::<Orswot<u8, u8>>::open(&dir, 0).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidData);
+
+    // the records after the corrupt one are kept
+    assert_eq!(fs::read(dir.join("oplog")).unwrap(), bytes);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_corrupt_length_is_rejected() {
+    let dir = log_dir("corrupt-length");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    add(&mut log, 2);
+    add(&mut log, 3);
+    drop(log);
+
+    // a corrupt length that points past the end of the log mustn't pass
+    // for a torn record, that would drop the records after it
+    let mut bytes = fs::read(dir.join("oplog")).unwrap();
+    for byte in &mut bytes[..8] {
+        *byte ^= 0xff;
+    }
+    fs::write(dir.join("oplog"), &bytes).unwrap();
+
+    let err = OpLog::<Orswot<u8, u8>>::open(&dir, 0).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidData);
+    assert_eq!(fs::read(dir.join("oplog")).unwrap(), bytes);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_crash_between_snapshot_and_compaction() {
+    let dir = log_dir("uncompacted");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    for member in 0..5 {
+        add(&mut log, member);
+    }
+    let uncompacted = fs::read(dir.join("oplog")).unwrap();
+    log.snapshot().unwrap();
+    let state = log.state().clone();
+    drop(log);
+
+    // the snapshot was written but the log still holds the ops it covers
+    fs::write(dir.join("oplog"), &uncompacted).unwrap();
+
+    let log: OpLog<Orswot<u8, u8>> = OpLog::open(&dir, 0).unwrap();
+    assert_eq!(log.state(), &state);
+    assert!(log.is_empty());
+    assert_eq!(fs::metadata(dir.join("oplog")).unwrap().len(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_corrupt_snapshot_is_rejected() {
+    let dir = log_dir("corrupt");
+    let mut log = OpLog::open(&dir, 0).unwrap();
+    add(&mut log, 1);
+    log.snapshot().unwrap();
+    drop(log);
+
+    let mut snapshot = fs::read(dir.join("snapshot")).unwrap();
+    let last = snapshot.len() - 1;
+    snapshot[last] ^= 0xff;
+    fs::write(dir.join("snapshot"), &snapshot).unwrap();
+
+    let err = OpLog::<Orswot<u8, u8>>::open(&dir, 0).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidData);
+    fs::remove_dir_all(&dir).unwrap();
+}
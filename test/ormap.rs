@@ -0,0 +1,288 @@
+use crdts::{map, mvreg, VClock, Dot, MVReg, ORMap, CvRDT, CmRDT};
+use quickcheck::TestResult;
+
+type TestActor = u8;
+type TestKey = u8;
+type TestVal = MVReg<u8, TestActor>;
+type TestOp = map::Op<TestKey, ORMap<TestKey, TestVal, TestActor>, TestActor>;
+type TestMap = ORMap<TestKey, ORMap<TestKey, TestVal, TestActor>, TestActor>;
+type OpPrims = (u8, Vec<(u8, u8, u8, u8, u8)>);
+
+#[derive(Debug, Clone)]
+struct OpVec(TestActor, Vec<TestOp>);
+
+fn build_opvec(prims: OpPrims) -> OpVec {
+    let (actor, ops_data) = prims;
+
+    let mut ops = Vec::new();
+    for (i, op_data) in ops_data.into_iter().enumerate() {
+        let (choice, inner_choice, key, inner_key, val) = op_data;
+        let clock: VClock<_> = Dot {
+            actor,
+            counter: i as u64
+        }.into();
+
+        let op = match choice % 4 {
+            0 => {
+                map::Op::Up {
+                    dot: clock.inc(actor),
+                    key,
+                    op: match inner_choice % 3 {
+                        0 => map::Op::Up {
+                            dot: clock.inc(actor),
+                            key: inner_key,
+                            op: mvreg::Op::Put { clock, val }
+                        },
+                        1 => map::Op::Rm { clock, key: inner_key },
+                        _ => map::Op::Nop
+                    }
+                }
+            },
+            1 => map::Op::Rm { clock, key },
+            2 => {
+                map::Op::Batch {
+                    dot: clock.inc(actor),
+                    updates: vec![(key, map::Op::Up {
+                        dot: clock.inc(actor),
+                        key: inner_key,
+                        op: mvreg::Op::Put { clock: clock.clone(), val }
+                    })],
+                    // half of the batches replace the key they update
+                    rms: vec![(if inner_choice % 2 == 0 { key } else { inner_choice }, clock)]
+                }
+            },
+            _ => map::Op::Nop
+        };
+        ops.push(op);
+    }
+    OpVec(actor, ops)
+}
+
+fn apply_ops(map: &mut TestMap, ops: &[TestOp]) {
+    for op in ops.iter() {
+        map.apply(op);
+    }
+}
+
+#[test]
+fn test_update_and_remove() {
+    let mut m: TestMap = ORMap::new();
+    let op = m.update(101, m.get(&101).derive_add_ctx(1), |map, ctx| {
+        map.update(110, ctx, |reg, ctx| reg.set(2, ctx))
+    });
+    m.apply(&op);
+    assert_eq!(m.len().val, 1);
+    assert_eq!(
+        m.get(&101).val
+            .and_then(|inner| inner.get(&110).val)
+            .map(|reg| reg.read().val),
+        Some(vec![2])
+    );
+
+    let rm_op = m.rm(101, m.get(&101).derive_rm_ctx());
+    m.apply(&rm_op);
+    assert_eq!(m.get(&101).val, None);
+    assert_eq!(m.len().val, 0);
+}
+
+#[test]
+fn test_concurrent_update_keeps_contents() {
+    let mut m1 = TestMap::new();
+    let op1 = m1.update(101, m1.get(&101).derive_add_ctx(74), |map, ctx| {
+        map.update(110, ctx, |reg, ctx| reg.set(32, ctx))
+    });
+    m1.apply(&op1);
+
+    let mut m2 = m1.clone();
+
+    let op2 = m1.rm(101, m1.get(&101).derive_rm_ctx());
+    m1.apply(&op2);
+
+    let op3 = m2.update(101, m2.get(&101).derive_add_ctx(37), |map, ctx| {
+        map.update(220, ctx, |reg, ctx| reg.set(5, ctx))
+    });
+    m2.apply(&op3);
+
+    let mut m1_merged = m1.clone();
+    m1_merged.merge(&m2);
+
+    // exchanging the ops should give the same result as merging
+    m1.apply(&op3);
+    m2.apply(&op2);
+    assert_eq!(m1, m2);
+    assert_eq!(m1, m1_merged);
+
+    // the edits seen by the remover survive, unlike with Map
+    let inner = m1.get(&101).val.unwrap();
+    assert_eq!(inner.get(&110).val.map(|r| r.read().val), Some(vec![32]));
+    assert_eq!(inner.get(&220).val.map(|r| r.read().val), Some(vec![5]));
+    assert_eq!(inner.len().val, 2);
+}
+
+#[test]
+fn test_readd_starts_from_empty_contents() {
+    let mut m1 = TestMap::new();
+    let op = m1.update(101, m1.get(&101).derive_add_ctx(1), |map, ctx| {
+        map.update(110, ctx, |reg, ctx| reg.set(2, ctx))
+    });
+    m1.apply(&op);
+    let mut m2 = m1.clone();
+
+    let rm_op = m1.rm(101, m1.get(&101).derive_rm_ctx());
+    m1.apply(&rm_op);
+    let readd_op = m1.update(101, m1.get(&101).derive_add_ctx(1), |map, ctx| {
+        map.update(220, ctx, |reg, ctx| reg.set(3, ctx))
+    });
+    m1.apply(&readd_op);
+
+    // a concurrent update keeps its own edits, not the removed ones
+    let op = m2.update(101, m2.get(&101).derive_add_ctx(2), |map, ctx| {
+        map.update(230, ctx, |reg, ctx| reg.set(4, ctx))
+    });
+    m2.apply(&op);
+
+    let mut merged = m1.clone();
+    merged.merge(&m2);
+    m1.apply(&op);
+    m2.apply(&rm_op);
+    m2.apply(&readd_op);
+    assert_eq!(m1, merged);
+    assert_eq!(m2, merged);
+
+    let inner = merged.get(&101).val.unwrap();
+    assert_eq!(inner.get(&110).val, None);
+    assert_eq!(inner.get(&220).val.map(|r| r.read().val), Some(vec![3]));
+    assert_eq!(inner.get(&230).val.map(|r| r.read().val), Some(vec![4]));
+}
+
+#[test]
+fn test_gc_drops_stable_tombstones() {
+    let mut m = TestMap::new();
+    for key in [101, 102] {
+        let op = m.update(key, m.get(&key).derive_add_ctx(1), |map, ctx| {
+            map.update(110, ctx, |reg, ctx| reg.set(2, ctx))
+        });
+        m.apply(&op);
+        let rm_op = m.rm(key, m.get(&key).derive_rm_ctx());
+        m.apply(&rm_op);
+    }
+
+    // only the edits of the first key are stable
+    let before = m.clone();
+    m.gc(&Dot { actor: 1, counter: 1 }.into());
+    assert_ne!(m, before);
+    let first_dropped = m.clone();
+    m.gc(&Dot { actor: 1, counter: 1 }.into());
+    assert_eq!(m, first_dropped);
+
+    m.gc(&m.len().add_clock);
+    assert_ne!(m, first_dropped);
+    assert_eq!(m.len().val, 0);
+}
+
+#[test]
+fn test_readd_after_gc_on_one_replica() {
+    let mut m1 = TestMap::new();
+    let op = m1.update(101, m1.get(&101).derive_add_ctx(1), |map, ctx| {
+        map.update(110, ctx, |reg, ctx| reg.set(2, ctx))
+    });
+    m1.apply(&op);
+    let rm_op = m1.rm(101, m1.get(&101).derive_rm_ctx());
+    m1.apply(&rm_op);
+    let mut m2 = m1.clone();
+
+    // only m1 drops the tombstone before re-adding the key
+    m1.gc(&m1.len().add_clock);
+    let readd_op = m1.update(101, m1.get(&101).derive_add_ctx(1), |map, ctx| {
+        map.update(220, ctx, |reg, ctx| reg.set(3, ctx))
+    });
+    m1.apply(&readd_op);
+    m2.apply(&readd_op);
+
+    // the removed contents don't come back on the replica that kept them
+    for m in [&m1, &m2] {
+        let inner = m.get(&101).val.unwrap();
+        assert_eq!(inner.len().val, 1);
+        assert_eq!(inner.get(&110).val, None);
+        assert_eq!(inner.get(&220).val.map(|r| r.read().val), Some(vec![3]));
+    }
+}
+
+quickcheck! {
+    fn prop_op_exchange_same_as_merge(
+        ops1_prim: OpPrims,
+        ops2_prim: OpPrims
+    ) -> TestResult {
+        let ops1 = build_opvec(ops1_prim);
+        let ops2 = build_opvec(ops2_prim);
+
+        if ops1.0 == ops2.0 {
+            return TestResult::discard();
+        }
+
+        let mut m1: TestMap = ORMap::new();
+        let mut m2: TestMap = ORMap::new();
+
+        apply_ops(&mut m1, &ops1.1);
+        apply_ops(&mut m2, &ops2.1);
+
+        let mut m_merged = m1.clone();
+        m_merged.merge(&m2);
+
+        apply_ops(&mut m1, &ops2.1);
+        apply_ops(&mut m2, &ops1.1);
+
+        TestResult::from_bool(m1 == m_merged && m2 == m_merged)
+    }
+
+    fn prop_merge_commutative(
+        ops1_prim: OpPrims,
+        ops2_prim: OpPrims
+    ) -> TestResult {
+        let ops1 = build_opvec(ops1_prim);
+        let ops2 = build_opvec(ops2_prim);
+
+        if ops1.0 == ops2.0 {
+            return TestResult::discard();
+        }
+
+        let mut m1: TestMap = ORMap::new();
+        let mut m2: TestMap = ORMap::new();
+
+        apply_ops(&mut m1, &ops1.1);
+        apply_ops(&mut m2, &ops2.1);
+
+        let m1_snapshot = m1.clone();
+        m1.merge(&m2);
+        m2.merge(&m1_snapshot);
+
+        TestResult::from_bool(m1 == m2)
+    }
+
+    fn prop_merge_idempotent(
+        ops_prim: OpPrims
+    ) -> bool {
+        let ops = build_opvec(ops_prim);
+
+        let mut m: TestMap = ORMap::new();
+        apply_ops(&mut m, &ops.1);
+        let m_snapshot = m.clone();
+
+        m.merge(&m_snapshot);
+
+        m == m_snapshot
+    }
+
+    fn prop_op_idempotent(
+        ops_prim: OpPrims
+    ) -> bool {
+        let ops = build_opvec(ops_prim);
+        let mut m = TestMap::new();
+
+        apply_ops(&mut m, &ops.1);
+        let m_snapshot = m.clone();
+        apply_ops(&mut m, &ops.1);
+
+        m == m_snapshot
+    }
+}
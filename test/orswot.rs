@@ -74,6 +74,43 @@ quickcheck! {
         }
         true
     }
+
+    fn prop_diff_turns_value_into_other(
+        a_prims: Vec<(u8, u8, u8, u64)>,
+        b_prims: Vec<(u8, u8, u8, u64)>
+    ) -> bool {
+        let mut a: Orswot<u8, u8> = Orswot::new();
+        let mut b: Orswot<u8, u8> = Orswot::new();
+        for (_, op) in build_opvec(a_prims).ops {
+            a.apply(&op);
+        }
+        for (_, op) in build_opvec(b_prims).ops {
+            b.apply(&op);
+        }
+
+        let mut val = a.value().val;
+        if let Some(changes) = a.diff(&b) {
+            for member in changes.removed {
+                val.remove(&member);
+            }
+            val.extend(changes.added);
+        }
+        val == b.value().val
+    }
+}
+
+#[test]
+fn add_all_adds_every_member_under_one_dot() {
+    let mut a = Orswot::<u8, u8>::new();
+    let op = a.add_all(vec![1, 2, 3], a.value().derive_add_ctx(1));
+    a.apply(&op);
+    assert_eq!(a.value().val, vec![1, 2, 3].into_iter().collect());
+    assert_eq!(a.value().add_clock, VClock::from(Dot { actor: 1, counter: 1 }));
+
+    // removing one member leaves the others
+    let op = a.remove(2, a.contains(&2).derive_rm_ctx());
+    a.apply(&op);
+    assert_eq!(a.value().val, vec![1, 3].into_iter().collect());
 }
 
 /// When two orswots have identical clocks, but different elements,
@@ -159,6 +196,33 @@ fn merge_clocks_of_identical_entries() {
     assert_eq!(read_ctx.rm_clock, final_clock);
 }
 
+// merging with a set that dropped some of an entry's dots must drop
+// them here too, or a later remove of the remaining dots can't remove
+// the entry.
+#[test]
+fn merge_drops_dots_removed_by_other() {
+    let mut a = Orswot::<u8, u8>::new();
+    let mut c = a.clone();
+    let a_op = a.add(1, a.value().derive_add_ctx(1));
+    a.apply(&a_op);
+    let c_op = c.add(1, c.value().derive_add_ctx(3));
+    c.apply(&c_op);
+
+    // b removes the add it has seen
+    let mut b = a.clone();
+    let b_rm = b.remove(1, b.contains(&1).derive_rm_ctx());
+    b.apply(&b_rm);
+
+    a.merge(&c);
+    a.merge(&b);
+    assert_eq!(a.contains(&1).rm_clock, VClock::from(Dot { actor: 3, counter: 1 }));
+
+    // c removes the add it has seen
+    let c_rm = c.remove(1, c.contains(&1).derive_rm_ctx());
+    a.apply(&c_rm);
+    assert_eq!(a.value().val, HashSet::new());
+}
+
 // port from riak_dt
 #[test]
 fn test_disjoint_merge() {
@@ -305,3 +369,74 @@ fn test_reset_remove_semantics() {
         vec![2].into_iter().collect()
     );
 }
+
+#[test]
+fn test_borrowing_reads_match_owned_reads() {
+    let mut a = Orswot::<u8, u8>::new();
+    for (member, actor) in [(1, 1), (2, 2), (1, 3)] {
+        let op = a.add(member, a.iter().derive_add_ctx(actor));
+        a.apply(&op);
+    }
+
+    for member in 0..3 {
+        assert_eq!(a.contains_ref(&member).into_owned(), a.contains(&member));
+    }
+
+    let members: HashSet<u8> = a.iter().val.cloned().collect();
+    assert_eq!(members, a.value().val);
+    assert_eq!(a.iter().add_clock.into_owned(), a.value().add_clock);
+}
+
+#[test]
+fn test_deferred_removes_survive_serialization() {
+    let mut a: Orswot<u8, u8> = Orswot::new();
+
+    // remove 1 and 2 with a clock that has seen dots we haven't
+    let rm_clock: VClock<u8> = vec![(1, 2), (2, 1)].into_iter().collect();
+    a.apply(&Op::Rm { clock: rm_clock.clone(), member: 1 });
+    a.apply(&Op::Rm { clock: rm_clock.clone(), member: 2 });
+    assert_eq!(a.deferred_len(), 2);
+
+    let mut b: Orswot<u8, u8> = from_binary(to_binary(&a)).unwrap();
+    assert_eq!(a, b);
+
+    for set in [&mut a, &mut b] {
+        // a covered add is removed as soon as it's applied
+        set.apply(&Op::Add { dot: Dot { actor: 1, counter: 1 }, member: 1 });
+        assert_eq!(set.value().val, HashSet::new());
+
+        // an add the removes haven't seen survives
+        set.apply(&Op::Add { dot: Dot { actor: 3, counter: 1 }, member: 2 });
+        set.apply(&Op::Add { dot: Dot { actor: 1, counter: 2 }, member: 3 });
+        assert_eq!(set.deferred_len(), 2);
+
+        // the last dot the removes are waiting on
+        set.apply(&Op::Add { dot: Dot { actor: 2, counter: 1 }, member: 2 });
+        assert_eq!(set.deferred_len(), 0);
+        assert_eq!(set.value().val, vec![2, 3].into_iter().collect());
+    }
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_merge_with_changes() {
+    let mut a: Orswot<u8, u8> = Orswot::new();
+    let op = a.add(1, a.value().derive_add_ctx(1));
+    a.apply(&op);
+    let op = a.add(2, a.value().derive_add_ctx(1));
+    a.apply(&op);
+
+    let mut b = a.clone();
+    let op = b.remove(1, b.contains(&1).derive_rm_ctx());
+    b.apply(&op);
+    let op = b.add(3, b.value().derive_add_ctx(2));
+    b.apply(&op);
+
+    let changes = a.merge_with_changes(&b).unwrap();
+    assert_eq!(changes.added, vec![3].into_iter().collect());
+    assert_eq!(changes.removed, vec![1].into_iter().collect());
+    assert_eq!(a, b);
+
+    // nothing left to change
+    assert!(a.merge_with_changes(&b).is_none());
+}
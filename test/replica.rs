@@ -0,0 +1,342 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crdts::*;
+
+fn exchange<C: CmRDT, A: Actor>(a: &mut Replica<C, A>, b: &mut Replica<C, A>) {
+    let a_ops: Vec<C::Op> = a.drain_outbox().collect();
+    let b_ops: Vec<C::Op> = b.drain_outbox().collect();
+    for op in a_ops.iter() {
+        b.apply_remote(op);
+    }
+    for op in b_ops.iter() {
+        a.apply_remote(op);
+    }
+}
+
+#[test]
+fn test_orswot_replicas_converge() {
+    let mut a: Replica<Orswot<u8, u8>, u8> = Replica::new(1);
+    let mut b: Replica<Orswot<u8, u8>, u8> = Replica::new(2);
+
+    a.add(1);
+    a.add(2);
+    b.add(3);
+    assert_eq!(a.outbox_len(), 2);
+    assert_eq!(b.outbox_len(), 1);
+
+    exchange(&mut a, &mut b);
+    assert_eq!(a.outbox_len(), 0);
+    assert_eq!(a.state(), b.state());
+
+    // b's remove doesn't see a's concurrent re-add
+    b.remove(1);
+    a.add(1);
+    exchange(&mut a, &mut b);
+    assert_eq!(a.state(), b.state());
+    assert_eq!(a.state().value().val, vec![1, 2, 3].into_iter().collect());
+}
+
+#[test]
+fn test_map_replica() {
+    let mut a: Replica<Map<u8, MVReg<u8, u8>, u8>, u8> = Replica::new(1);
+    let mut b: Replica<Map<u8, MVReg<u8, u8>, u8>, u8> = Replica::new(2);
+
+    a.update(1, |reg, ctx| reg.set(10, ctx));
+    a.update(2, |reg, ctx| reg.set(20, ctx));
+    exchange(&mut a, &mut b);
+
+    b.rm(1);
+    b.update(2, |reg, ctx| reg.set(21, ctx));
+    exchange(&mut a, &mut b);
+
+    assert_eq!(a.state(), b.state());
+    assert_eq!(a.state().get(&1).val, None);
+    assert_eq!(a.state().get(&2).val.map(|reg| reg.read().val), Some(vec![21]));
+}
+
+#[test]
+fn test_counter_and_flag_replicas() {
+    let mut a: Replica<PNCounter<u8>, u8> = Replica::new(1);
+    let mut b: Replica<PNCounter<u8>, u8> = Replica::new(2);
+    a.inc();
+    a.inc();
+    b.dec();
+    exchange(&mut a, &mut b);
+    assert_eq!(a.state().value(), 1);
+    assert_eq!(b.state().value(), 1);
+
+    let mut a: Replica<EWFlag<u8>, u8> = Replica::new(1);
+    let mut b: Replica<EWFlag<u8>, u8> = Replica::new(2);
+    a.enable();
+    exchange(&mut a, &mut b);
+    b.disable();
+    a.enable();
+    exchange(&mut a, &mut b);
+    assert!(a.state().read().val);
+    assert!(b.state().read().val);
+}
+
+#[test]
+fn test_merge_remote() {
+    let mut a: Replica<MVReg<u8, u8>, u8> = Replica::new(1);
+    let mut b: Replica<MVReg<u8, u8>, u8> = Replica::new(2);
+    a.set(1);
+    b.set(2);
+
+    a.merge_remote(b.state());
+    assert_eq!(a.state().read().val.len(), 2);
+
+    // a's set now overwrites both concurrent values
+    let op = a.set(3).clone();
+    b.apply_remote(&op);
+    assert_eq!(b.state().read().val, vec![3]);
+}
+
+#[test]
+fn test_orswot_subscribers() {
+    let mut a: Replica<Orswot<u8, u8>, u8> = Replica::new(1);
+    let mut b: Replica<Orswot<u8, u8>, u8> = Replica::new(2);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let added = events.clone();
+    b.on_member_added(move |member| added.lock().unwrap().push(("added", *member)));
+    let removed = events.clone();
+    b.on_member_removed(move |member| removed.lock().unwrap().push(("removed", *member)));
+
+    a.add(1);
+    a.add(2);
+    let ops: Vec<_> = a.drain_outbox().collect();
+    for op in ops.iter().chain(ops.iter()) {
+        b.apply_remote(op);
+    }
+    // local edits are reported too, re-adding a member isn't a change
+    b.remove(1);
+    b.add(2);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![("added", 1), ("added", 2), ("removed", 1)]
+    );
+}
+
+#[test]
+fn test_map_subscribers() {
+    let mut a: Replica<Map<u8, MVReg<u8, u8>, u8>, u8> = Replica::new(1);
+    let mut b: Replica<Map<u8, MVReg<u8, u8>, u8>, u8> = Replica::new(2);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let inserted = events.clone();
+    b.on_key_inserted(move |key| inserted.lock().unwrap().push(format!("inserted {}", key)));
+    let removed = events.clone();
+    b.on_key_removed(move |key| removed.lock().unwrap().push(format!("removed {}", key)));
+    let updated = events.clone();
+    b.on_key_updated(move |key, changes| {
+        updated.lock().unwrap().push(format!("updated {} to {:?}", key, changes.added))
+    });
+
+    a.update(1, |reg, ctx| reg.set(10, ctx));
+    a.update(2, |reg, ctx| reg.set(20, ctx));
+    a.update(1, |reg, ctx| reg.set(11, ctx));
+    a.rm(2);
+    b.merge_remote(a.state());
+    b.merge_remote(a.state());
+
+    let ops: Vec<_> = a.drain_outbox().collect();
+    for op in ops.iter() {
+        b.apply_remote(op);
+    }
+    let op = a.update(1, |reg, ctx| reg.set(12, ctx)).clone();
+    b.apply_remote(&op);
+    b.rm(1);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "inserted 1".to_string(),
+            "updated 1 to [12]".to_string(),
+            "removed 1".to_string()
+        ]
+    );
+}
+
+#[test]
+fn test_subscribe() {
+    let mut a: Replica<MVReg<u8, u8>, u8> = Replica::new(1);
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let changes_ref = changes.clone();
+    a.subscribe(move |c: &mvreg::Changes<u8>| {
+        changes_ref.lock().unwrap().push((c.removed.clone(), c.added.clone()))
+    });
+
+    a.set(1);
+    let op = a.set(2).clone();
+    a.apply_remote(&op);
+
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![(vec![], vec![1]), (vec![1], vec![2])]
+    );
+}
+
+static DIFFS: AtomicUsize = AtomicUsize::new(0);
+
+/// A counter that counts how often it's diffed
+#[derive(Debug, Clone, Default)]
+struct DiffCounted(GCounter<u8>);
+
+impl CmRDT for DiffCounted {
+    type Op = Dot<u8>;
+
+    fn apply(&mut self, op: &Self::Op) {
+        self.0.apply(op)
+    }
+}
+
+impl Diff for DiffCounted {
+    type Changes = u64;
+
+    fn diff(&self, other: &Self) -> Option<u64> {
+        DIFFS.fetch_add(1, Ordering::SeqCst);
+        Some(other.0.value() - self.0.value())
+    }
+}
+
+#[test]
+fn test_changes_are_computed_once_for_all_subscribers() {
+    let mut a: Replica<DiffCounted, u8> = Replica::new(1);
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    for _ in 0..3 {
+        let changes_ref = changes.clone();
+        a.subscribe(move |c: &u64| changes_ref.lock().unwrap().push(*c));
+    }
+
+    a.mutate(|counter, actor| counter.0.inc(actor));
+    assert_eq!(DIFFS.load(Ordering::SeqCst), 1);
+    assert_eq!(*changes.lock().unwrap(), vec![1, 1, 1]);
+}
+
+#[test]
+fn test_orswot_undo_redo() {
+    let mut a: Replica<Orswot<u8, u8>, u8> = Replica::new(1);
+    let mut b: Replica<Orswot<u8, u8>, u8> = Replica::new(2);
+    a.enable_undo();
+
+    a.add(1);
+    a.add(2);
+    a.remove(1);
+    // adding a member that's already in the set changes nothing
+    a.add(2);
+
+    assert!(a.undo().is_some());
+    assert_eq!(a.state().value().val, vec![1, 2].into_iter().collect());
+    assert!(a.undo().is_some());
+    assert_eq!(a.state().value().val, vec![1].into_iter().collect());
+
+    assert!(a.redo().is_some());
+    assert_eq!(a.state().value().val, vec![1, 2].into_iter().collect());
+
+    // b concurrently adds 2 as well, undoing a's add keeps b's
+    b.add(2);
+    exchange(&mut a, &mut b);
+    assert!(a.undo().is_some());
+    exchange(&mut a, &mut b);
+    assert_eq!(a.state(), b.state());
+    assert_eq!(a.state().value().val, vec![1, 2].into_iter().collect());
+
+    // a new edit clears the redo stack
+    a.add(3);
+    assert!(a.redo().is_none());
+    assert!(a.undo().is_some());
+    assert!(a.undo().is_some());
+    assert!(a.undo().is_none());
+    assert_eq!(a.state().value().val, vec![2].into_iter().collect());
+}
+
+#[test]
+fn test_map_undo() {
+    let mut a: Replica<Map<u8, MVReg<u8, u8>, u8>, u8> = Replica::new(1);
+    a.enable_undo();
+
+    a.update(1, |reg, ctx| reg.set(10, ctx));
+    a.update(1, |reg, ctx| reg.set(11, ctx));
+    a.update(2, |reg, ctx| reg.set(20, ctx));
+
+    a.undo();
+    assert_eq!(a.state().get(&2).val, None);
+    a.undo();
+    assert_eq!(a.state().get(&1).val.map(|reg| reg.read().val), Some(vec![10]));
+    a.redo();
+    assert_eq!(a.state().get(&1).val.map(|reg| reg.read().val), Some(vec![11]));
+}
+
+#[test]
+fn test_map_undo_rm() {
+    let mut a: Replica<Map<u8, Orswot<u8, u8>, u8>, u8> = Replica::new(1);
+    let mut b: Replica<Map<u8, Orswot<u8, u8>, u8>, u8> = Replica::new(2);
+    a.enable_undo();
+
+    a.update(1, |set, ctx| set.add(10, ctx));
+    a.update(1, |set, ctx| set.add(11, ctx));
+    a.update(2, |set, ctx| set.add(20, ctx));
+    exchange(&mut a, &mut b);
+
+    let members = |r: &Replica<Map<u8, Orswot<u8, u8>, u8>, u8>, key| {
+        r.state().get(&key).val.map(|set| set.value().val)
+    };
+
+    a.rm(1);
+    assert_eq!(members(&a, 1), None);
+    assert!(a.undo().is_some());
+    assert_eq!(members(&a, 1), Some(vec![10, 11].into_iter().collect()));
+    exchange(&mut a, &mut b);
+    assert_eq!(a.state(), b.state());
+
+    assert!(a.redo().is_some());
+    assert_eq!(members(&a, 1), None);
+
+    // removes in a batch are restored along with the updates being undone
+    a.mutate(|m, actor| {
+        let rm_ctx = m.get(&2).derive_rm_ctx();
+        m.batch(m.len().derive_add_ctx(actor))
+            .rm(2, rm_ctx)
+            .update(3, |set, ctx| set.add(30, ctx))
+            .build()
+            .unwrap()
+    });
+    assert_eq!(members(&a, 2), None);
+    assert!(a.undo().is_some());
+    assert_eq!(members(&a, 2), Some(vec![20].into_iter().collect()));
+    assert_eq!(members(&a, 3), None);
+    exchange(&mut a, &mut b);
+    assert_eq!(a.state(), b.state());
+}
+
+#[test]
+fn test_mvreg_undo_keeps_concurrent_values() {
+    let mut a: Replica<MVReg<u8, u8>, u8> = Replica::new(1);
+    let mut b: Replica<MVReg<u8, u8>, u8> = Replica::new(2);
+    a.enable_undo();
+
+    a.set(1);
+    exchange(&mut a, &mut b);
+    a.set(2);
+    b.set(3);
+    exchange(&mut a, &mut b);
+
+    // undoing a's write of 2 restores 1, b's concurrent 3 is kept
+    a.undo();
+    exchange(&mut a, &mut b);
+    assert_eq!(a.state(), b.state());
+    let mut vals = a.state().read().val;
+    vals.sort();
+    assert_eq!(vals, vec![1, 3]);
+}
+
+#[test]
+fn test_undo_is_opt_in() {
+    let mut a: Replica<Orswot<u8, u8>, u8> = Replica::new(1);
+    a.add(1);
+    assert!(a.undo().is_none());
+    assert_eq!(a.outbox_len(), 1);
+}
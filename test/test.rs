@@ -1,12 +1,31 @@
 #[macro_use] extern crate quickcheck;
 
 extern crate crdts;
+extern crate serde;
+#[macro_use] extern crate serde_json;
 
+mod canonical;
+mod doc;
+mod dotalloc;
+mod dwflag;
+mod ewflag;
+mod expiring;
 mod gcounter;
+mod graph;
 mod gset;
+mod history;
+mod list;
+mod lwwmap;
 mod lwwreg;
+mod lwwset;
 mod map;
+mod merkle;
+mod multimap;
 mod mvreg;
+mod oplog;
+mod ormap;
 mod orswot;
 mod pncounter;
+mod replica;
+mod tree;
 mod vclock;
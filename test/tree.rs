@@ -0,0 +1,197 @@
+use crdts::{Tree, CvRDT, CmRDT, Dot};
+use crdts::tree::Op;
+
+const NODES: u8 = 8;
+const ROOT: u8 = 0;
+
+type TestTree = Tree<u8, u8, u8>;
+
+// every op gets a distinct dot, the Lamport clocks collide often so ties
+// are broken by dot.
+fn build_ops(op_prims: Vec<(u8, u8, u8, u8)>) -> Vec<Op<u8, u8, u8>> {
+    op_prims.into_iter()
+        .take(256)
+        .enumerate()
+        .map(|(i, (counter, child, parent, meta))| {
+            let parent = parent % (NODES + 1);
+            Op {
+                dot: Dot { actor: (i % 4) as u8, counter: i as u64 + 1 },
+                counter: (counter % 8) as u64 + 1,
+                child: child % (NODES - 1) + 1,
+                parent: if parent == NODES { None } else { Some(parent) },
+                meta
+            }
+        })
+        .collect()
+}
+
+fn apply_all<'a>(ops: impl Iterator<Item=&'a Op<u8, u8, u8>>) -> TestTree {
+    let mut tree = Tree::new();
+    for op in ops {
+        tree.apply(op);
+    }
+    tree
+}
+
+fn has_cycle(tree: &TestTree) -> bool {
+    (0..NODES).any(|node| {
+        let mut node = node;
+        for _ in 0..=NODES {
+            match tree.parent(&node) {
+                Some(parent) => node = *parent,
+                None => return false
+            }
+        }
+        true
+    })
+}
+
+quickcheck! {
+    fn prop_no_cycles(op_prims: Vec<(u8, u8, u8, u8)>, seed: usize) -> bool {
+        let ops = build_ops(op_prims);
+        let mut shuffled = ops.clone();
+        if !shuffled.is_empty() {
+            let mid = seed % shuffled.len();
+            shuffled.rotate_left(mid);
+            shuffled.reverse();
+        }
+
+        let mut tree = Tree::new();
+        for op in shuffled.iter() {
+            tree.apply(op);
+            if has_cycle(&tree) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn prop_ops_converge_in_any_order(op_prims: Vec<(u8, u8, u8, u8)>, seed: usize) -> bool {
+        let ops = build_ops(op_prims);
+        let mut shuffled = ops.clone();
+        if !shuffled.is_empty() {
+            let mid = seed % shuffled.len();
+            shuffled.rotate_left(mid);
+        }
+
+        let in_order = apply_all(ops.iter());
+        in_order == apply_all(ops.iter().rev())
+            && in_order == apply_all(shuffled.iter())
+            // ops are idempotent
+            && in_order == apply_all(ops.iter().chain(shuffled.iter()))
+    }
+
+    fn prop_merge_converges(op_prims: Vec<(u8, u8, u8, u8)>, witnesses: u8) -> bool {
+        let ops = build_ops(op_prims);
+        let n = (witnesses % 5) as usize + 1;
+        let mut replicas: Vec<TestTree> = (0..n).map(|_| Tree::new()).collect();
+        for (i, op) in ops.iter().enumerate() {
+            replicas[i % n].apply(op);
+        }
+
+        let mut merged = Tree::new();
+        for replica in replicas.iter().rev() {
+            merged.merge(replica);
+        }
+        let mut merged_again = merged.clone();
+        merged_again.merge(&replicas[0]);
+
+        merged == apply_all(ops.iter()) && merged == merged_again
+    }
+}
+
+#[test]
+fn test_create_move_delete() {
+    let mut tree: Tree<u8, String, u8> = Tree::new();
+    let op = tree.create(1, ROOT, "home", tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+    let op = tree.create(2, 1u8, "alice", tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+    let op = tree.create(3, 1u8, "bob", tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+
+    let mut children: Vec<u8> = tree.children(&1).cloned().collect();
+    children.sort();
+    assert_eq!(children, vec![2, 3]);
+    assert!(tree.contains(&3));
+
+    let op = tree.mv(3, 2u8, "bob", tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+    assert_eq!(tree.parent(&3), Some(&2));
+
+    let op = tree.delete(2, tree.len().derive_add_ctx(1)).unwrap();
+    tree.apply(&op);
+    assert!(!tree.contains(&2));
+    // children go along with their parent
+    assert!(!tree.contains(&3));
+    assert!(tree.contains(&1));
+    assert_eq!(tree.meta(&2), Some(&"alice".to_string()));
+    assert_eq!(tree.delete(9, tree.len().derive_add_ctx(1)), None);
+
+    // nodes can be moved out of a deleted node
+    let op = tree.mv(3, 1u8, "bob", tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+    assert!(tree.contains(&3));
+    assert_eq!(tree.log_len(), 6);
+}
+
+#[test]
+fn test_move_under_own_descendant_is_skipped() {
+    let mut tree: TestTree = Tree::new();
+    let op = tree.create(1, ROOT, 0, tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+    let op = tree.create(2, 1u8, 0, tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+
+    let op = tree.mv(1, 2u8, 0, tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+    assert_eq!(tree.parent(&1), Some(&ROOT));
+    let op = tree.mv(1, 1u8, 0, tree.len().derive_add_ctx(1));
+    tree.apply(&op);
+    assert_eq!(tree.parent(&1), Some(&ROOT));
+    assert_eq!(tree.log_len(), 4);
+}
+
+#[test]
+fn test_late_op_is_applied_in_timestamp_order() {
+    let mut a: TestTree = Tree::new();
+    let create = a.create(1, ROOT, 0, a.len().derive_add_ctx(1));
+    a.apply(&create);
+    let mut b = a.clone();
+
+    // a moves 1 away and b later deletes it, a sees the delete first
+    let mv = a.mv(1, 5u8, 0, a.len().derive_add_ctx(1));
+    let op = b.delete(1, b.len().derive_add_ctx(2)).unwrap();
+    b.apply(&op);
+    let later = b.mv(1, 6u8, 0, b.len().derive_add_ctx(2));
+    b.apply(&later);
+
+    a.apply(&later);
+    a.apply(&op);
+    a.apply(&mv);
+    b.apply(&mv);
+    assert_eq!(a, b);
+    assert_eq!(a.parent(&1), Some(&6));
+}
+
+#[test]
+fn test_ops_built_from_the_same_state_are_distinct() {
+    let mut tree: TestTree = Tree::new();
+    let ctx = tree.len().derive_add_ctx(1);
+    let first = tree.create(1, ROOT, 0, ctx.clone());
+
+    // the next dot of the actor, before the first op is applied
+    let mut next = ctx.clone();
+    next.dot = next.clock.inc(1);
+    let second = tree.create(2, ROOT, 0, next);
+
+    let mut other = tree.clone();
+    tree.apply(&first);
+    tree.apply(&second);
+    other.apply(&second);
+    other.apply(&first);
+    assert_eq!(tree, other);
+    assert!(tree.contains(&1));
+    assert!(tree.contains(&2));
+    assert_eq!(tree.len().val, 2);
+}